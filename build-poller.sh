@@ -2,4 +2,6 @@
 
 cd poller
 TARGET_CC=x86_64-linux-musl-gcc cargo build --release --target x86_64-unknown-linux-musl
-(cd target/x86_64-unknown-linux-musl/release && mkdir -p lambda && cp bootstrap lambda/)
\ No newline at end of file
+(cd target/x86_64-unknown-linux-musl/release && mkdir -p lambda && cp bootstrap lambda/)
+mkdir -p target/x86_64-unknown-linux-musl/release/lambda/assets
+cp -r assets/heroes target/x86_64-unknown-linux-musl/release/lambda/assets/
@@ -40,6 +40,20 @@ export class NanTuanTrackerCdkStack extends Stack {
       targets: [new aws_events_targets.LambdaFunction(pollerLambda)]
     });
 
+    const dailyReportRule = new aws_events.Rule(this, 'dailyReportScheduleRule', {
+      schedule: Schedule.cron({ minute: '0', hour: '1' }),
+      targets: [new aws_events_targets.LambdaFunction(pollerLambda, {
+        event: aws_events.RuleTargetInput.fromObject({ report: 'daily' })
+      })]
+    });
+
+    const weeklyReportRule = new aws_events.Rule(this, 'weeklyReportScheduleRule', {
+      schedule: Schedule.cron({ minute: '0', hour: '1', weekDay: 'MON' }),
+      targets: [new aws_events_targets.LambdaFunction(pollerLambda, {
+        event: aws_events.RuleTargetInput.fromObject({ report: 'weekly' })
+      })]
+    });
+
     const guildIdTable = new aws_dynamodb.Table(this , "GuildIdTable", {
       tableName: 'Guilds',
       billingMode: aws_dynamodb.BillingMode.PROVISIONED,
@@ -50,5 +64,99 @@ export class NanTuanTrackerCdkStack extends Stack {
         type: aws_dynamodb.AttributeType.NUMBER
       }
     });
+
+    const headToHeadTable = new aws_dynamodb.Table(this , "HeadToHeadTable", {
+      tableName: 'HeadToHead',
+      billingMode: aws_dynamodb.BillingMode.PROVISIONED,
+      readCapacity: 1,
+      writeCapacity: 1,
+      partitionKey: {
+        name: 'id', 
+        type: aws_dynamodb.AttributeType.STRING
+      }
+    });
+
+    const leasesTable = new aws_dynamodb.Table(this , "LeasesTable", {
+      tableName: 'Leases',
+      billingMode: aws_dynamodb.BillingMode.PROVISIONED,
+      readCapacity: 1,
+      writeCapacity: 1,
+      partitionKey: {
+        name: 'id', 
+        type: aws_dynamodb.AttributeType.STRING
+      },
+      timeToLiveAttribute: 'expires_at'
+    });
+
+    const seenMatchesTable = new aws_dynamodb.Table(this , "SeenMatchesTable", {
+      tableName: 'SeenMatches',
+      billingMode: aws_dynamodb.BillingMode.PROVISIONED,
+      readCapacity: 2,
+      writeCapacity: 1,
+      partitionKey: {
+        name: 'id', 
+        type: aws_dynamodb.AttributeType.STRING
+      },
+      timeToLiveAttribute: 'expires_at'
+    });
+
+    const quarantineTable = new aws_dynamodb.Table(this , "QuarantineTable", {
+      tableName: 'Quarantine',
+      billingMode: aws_dynamodb.BillingMode.PROVISIONED,
+      readCapacity: 1,
+      writeCapacity: 1,
+      partitionKey: {
+        name: 'id', 
+        type: aws_dynamodb.AttributeType.STRING
+      }
+    });
+
+    const deliveriesTable = new aws_dynamodb.Table(this , "DeliveriesTable", {
+      tableName: 'Deliveries',
+      billingMode: aws_dynamodb.BillingMode.PROVISIONED,
+      readCapacity: 1,
+      writeCapacity: 1,
+      partitionKey: {
+        name: 'id', 
+        type: aws_dynamodb.AttributeType.STRING
+      }
+    });
+
+    const streaksTable = new aws_dynamodb.Table(this , "StreaksTable", {
+      tableName: 'Streaks',
+      billingMode: aws_dynamodb.BillingMode.PROVISIONED,
+      readCapacity: 1,
+      writeCapacity: 1,
+      partitionKey: {
+        name: 'id', 
+        type: aws_dynamodb.AttributeType.STRING
+      }
+    });
+
+    const healthTable = new aws_dynamodb.Table(this , "HealthTable", {
+      tableName: 'Health',
+      billingMode: aws_dynamodb.BillingMode.PROVISIONED,
+      readCapacity: 1,
+      writeCapacity: 1,
+      partitionKey: {
+        name: 'id', 
+        type: aws_dynamodb.AttributeType.STRING
+      }
+    });
+
+    const matchHistoryTable = new aws_dynamodb.Table(this , "MatchHistoryTable", {
+      tableName: 'MatchHistory',
+      billingMode: aws_dynamodb.BillingMode.PROVISIONED,
+      readCapacity: 2,
+      writeCapacity: 2,
+      partitionKey: {
+        name: 'pk', 
+        type: aws_dynamodb.AttributeType.STRING
+      },
+      sortKey: {
+        name: 'sk', 
+        type: aws_dynamodb.AttributeType.STRING
+      }
+    });
   }
 }
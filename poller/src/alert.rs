@@ -0,0 +1,161 @@
+use lambda_runtime::Error;
+use serde::{Serialize, Deserialize};
+use crate::{error::PollerError, outcome::RunOutcome, publisher::publisher::SINKS};
+
+pub const COMPONENT_PROVIDER: &str = "provider";
+pub const COMPONENT_STORE: &str = "store";
+
+/// The health of a component, such as the data provider, the state store or a sink, across polling cycles
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ComponentHealth {
+    /// Cycles in a row the component failed in
+    pub failures: i64,
+    /// Whether the admins were alerted about the current failures
+    pub alerted: bool
+}
+
+/// Enum of the messages sent to the admins about a component
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Alert {
+    /// The component failed `failures` cycles in a row, the last time with `error`
+    Failing { component: String, failures: i64, error: String },
+    /// The component the admins were alerted about works again
+    Recovered { component: String }
+}
+
+impl ComponentHealth {
+
+    /// Get the health of `component` after a cycle, with the alert to be sent, if any. The admins are alerted once
+    /// when the component reaches `threshold` failures in a row, then once more when it recovers.
+    ///
+    /// # Arguments
+    ///
+    /// * `component` - The name of the component
+    /// * `error` - The error of the component in the cycle, None when it worked
+    /// * `threshold` - The number of failed cycles in a row alerted about
+    pub fn next(&self, component: &str, error: Option<&str>, threshold: i64) -> (ComponentHealth, Option<Alert>) {
+        match error {
+            Some(error) => {
+                let failures = self.failures + 1;
+                let alert = (!self.alerted && failures >= threshold).then(|| Alert::Failing {
+                    component: component.to_string(),
+                    failures,
+                    error: error.to_string()
+                });
+                let alerted = self.alerted || alert.is_some();
+                (ComponentHealth { failures, alerted }, alert)
+            },
+            None => {
+                let alert = self.alerted.then(|| Alert::Recovered { component: component.to_string() });
+                (ComponentHealth::default(), alert)
+            }
+        }
+    }
+
+}
+
+impl Alert {
+
+    /// Get the text of the alert, as sent to the admins
+    pub fn text(&self) -> String {
+        match self {
+            Alert::Failing { component, failures, error } => {
+                format!("[ALERT] {} failed {} polling cycles in a row: {}", component, failures, error)
+            },
+            Alert::Recovered { component } => format!("[RECOVERED] {} works again", component)
+        }
+    }
+
+}
+
+/// Get the components a polling cycle used, with the error of the ones which failed. Components the cycle did not get
+/// to, such as the sinks of a cycle without new matches, are left out.
+///
+/// # Arguments
+///
+/// * `result` - The result of the polling cycle
+pub fn component_results(result: &Result<RunOutcome, Error>) -> Vec<(String, Option<String>)> {
+    match result {
+        Ok(outcome) => {
+            let mut results = vec![(COMPONENT_PROVIDER.to_string(), None), (COMPONENT_STORE.to_string(), None)];
+            for sink in SINKS {
+                if let Some(error) = outcome.sink_errors.get(sink) {
+                    results.push((sink.to_string(), Some(error.to_string())));
+                } else if outcome.delivered_sinks.contains(sink) {
+                    results.push((sink.to_string(), None));
+                }
+            }
+            results
+        },
+        Err(error) => match failed_component(error) {
+            Some(component) => vec![(component, Some(error.to_string()))],
+            None => Vec::new()
+        }
+    }
+}
+
+/// Get the component which raised `error`, telling the errors of the state store backends apart from the others
+/// Otherwise, None when the error does not come from a component, e.g. an invalid setting
+fn failed_component(error: &Error) -> Option<String> {
+    if let Some(error) = error.downcast_ref::<PollerError>() {
+        return match error {
+            PollerError::Provider(_) => Some(COMPONENT_PROVIDER.to_string()),
+            PollerError::State(_) => Some(COMPONENT_STORE.to_string()),
+            PollerError::Sink { sink, .. } => Some(sink.to_string()),
+            PollerError::DataQuality(_) | PollerError::Config(_) => None
+        };
+    }
+    let is_store_error = error.is::<aws_sdk_dynamodb::Error>()
+        || error.is::<rusqlite::Error>()
+        || error.is::<std::io::Error>();
+
+    is_store_error.then(|| COMPONENT_STORE.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use lambda_runtime::Error;
+    use crate::{error::PollerError, outcome::RunOutcome};
+    use super::{Alert, ComponentHealth, component_results};
+
+    #[test]
+    fn test_next() {
+        let health = ComponentHealth::default();
+        let (health, alert) = health.next("kook", Some("Unauthorized"), 3);
+        assert_eq!((health, alert), (ComponentHealth { failures: 1, alerted: false }, None));
+        let (health, _) = health.next("kook", Some("Unauthorized"), 3);
+        let (health, alert) = health.next("kook", Some("Unauthorized"), 3);
+        assert_eq!(health, ComponentHealth { failures: 3, alerted: true });
+        assert_eq!(alert.unwrap().text(), "[ALERT] kook failed 3 polling cycles in a row: Unauthorized");
+
+        // Repeated failures are not alerted again
+        let (health, alert) = health.next("kook", Some("Unauthorized"), 3);
+        assert_eq!((health, alert), (ComponentHealth { failures: 4, alerted: true }, None));
+
+        let (health, alert) = health.next("kook", None, 3);
+        assert_eq!((health, alert), (ComponentHealth::default(), Some(Alert::Recovered { component: "kook".to_string() })));
+        assert_eq!(health.next("kook", None, 3), (ComponentHealth::default(), None));
+    }
+
+    #[test]
+    fn test_component_results() {
+        let mut outcome = RunOutcome::default();
+        outcome.record_failure("kook", &["6789012345"], &"Unauthorized".into());
+        outcome.record_delivery("discord");
+        assert_eq!(component_results(&Ok(outcome)), vec![
+            ("provider".to_string(), None),
+            ("store".to_string(), None),
+            ("kook".to_string(), Some("Unauthorized".to_string())),
+            ("discord".to_string(), None)
+        ]);
+
+        let error: Error = PollerError::Provider("Invalid JWT".to_string()).into();
+        assert_eq!(component_results(&Err(error)), vec![("provider".to_string(), Some("Provider error: Invalid JWT".to_string()))]);
+        let error: Error = std::io::Error::other("Disk full").into();
+        assert_eq!(component_results(&Err(error)), vec![("store".to_string(), Some("Disk full".to_string()))]);
+        let error: Error = "Invalid PLAYER_LIST: some".into();
+        assert!(component_results(&Err(error)).is_empty());
+    }
+
+}
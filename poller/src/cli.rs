@@ -0,0 +1,191 @@
+use std::path::PathBuf;
+use chrono::{DateTime, NaiveDate, Utc};
+use clap::{Parser, Subcommand, builder::PossibleValuesParser};
+use lambda_runtime::Error;
+use serde_json::{Map, Value};
+use poller::{config, deadline::Deadline, error::PollerError, outcome::RunOutcome, settings::Settings};
+use poller::poller::{DEFAULT_BACKFILL_TAKE, Poller};
+use poller::publisher::{dry_run::DryRunOutput, publisher::SINKS};
+use poller::telemetry::logger::JsonLogger;
+
+/// Operator commands of the poller, run against the state store and the sinks of the configuration
+#[derive(Parser, Debug)]
+#[command(name = "poller-cli", version)]
+struct Cli {
+    /// The TOML configuration file, `CONFIG_FILE` by default. Environmental variables override its settings
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+    /// Render the payloads to stdout instead of sending them, leaving the checkpoint and the ledgers as is
+    #[arg(long, global = true)]
+    dry_run: bool,
+    /// Write the payloads and scoreboards of a dry run to this directory instead of stdout. Implies `--dry-run`
+    #[arg(long, global = true)]
+    output_dir: Option<PathBuf>,
+    #[command(subcommand)]
+    command: Command
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Poll new matches once, as a scheduled run does
+    PollOnce,
+    /// Publish the matches which ended since a date and were never seen, leaving the checkpoint as is
+    Backfill {
+        /// The earliest end of the matches, an RFC 3339 date time or a date such as `2024-05-01` in UTC
+        #[arg(long, value_parser = parse_since)]
+        since: DateTime<Utc>,
+        /// The id of the guild, the guild of the configuration by default
+        #[arg(long)]
+        guild: Option<i64>,
+        /// The number of latest matches of the guild fetched from Stratz
+        #[arg(long, default_value_t = DEFAULT_BACKFILL_TAKE)]
+        take: i64
+    },
+    /// Publish a match of the match history again, whatever the delivery ledger says
+    Republish {
+        /// The id of the match
+        match_id: String,
+        /// The only sink to publish to, every sink by default
+        #[arg(long, value_parser = PossibleValuesParser::new(SINKS))]
+        sink: Option<String>
+    },
+    /// Print the message every sink would receive for a match, without sending anything
+    Preview {
+        /// The id of the match
+        match_id: String
+    },
+    /// Read or move the checkpoint of the guild
+    Checkpoint {
+        #[command(subcommand)]
+        command: CheckpointCommand
+    },
+    /// Check the configuration and list every missing or invalid setting
+    ValidateConfig
+}
+
+#[derive(Subcommand, Debug)]
+enum CheckpointCommand {
+    /// Print the latest match id processed
+    Get,
+    /// Move the checkpoint to a match id. Matches are deduplicated against the seen match ids, so this neither skips
+    /// nor replays matches
+    Set {
+        /// The latest match id to be considered processed
+        match_id: i64
+    }
+}
+
+/// The entry point of the operator CLI
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    JsonLogger::init()?;
+    let cli = Cli::parse();
+    let config_file = cli.config.or_else(|| config::config_file().map(PathBuf::from));
+    let settings = Settings::load(config_file.as_deref()).map(|mut settings| {
+        match (cli.dry_run, cli.output_dir) {
+            (_, Some(dir)) => settings.run.dry_run = Some(DryRunOutput::Directory(dir)),
+            (true, None) => settings.run.dry_run = Some(DryRunOutput::Stdout),
+            (false, None) => {}
+        }
+        settings
+    });
+
+    if let Command::ValidateConfig = cli.command {
+        let errors = match settings {
+            Ok(_) => Vec::new(),
+            Err(e) => match e.downcast_ref::<PollerError>() {
+                Some(PollerError::Config(errors)) => errors.clone(),
+                _ => vec![e.to_string()]
+            }
+        };
+        if errors.is_empty() {
+            println!("Configuration is valid");
+            return Ok(());
+        }
+        for error in errors.iter() {
+            println!("{}", error);
+        }
+        return Err(format!("{} invalid settings", errors.len()).into());
+    }
+
+    let poller = Poller::from_settings(settings?).await?;
+    match cli.command {
+        Command::PollOnce => print_outcome(&poller.process(&Deadline::none()).await?),
+        Command::Backfill { since, guild, take } => {
+            let guild_id = guild.unwrap_or(poller.guild.id);
+            print_outcome(&poller.backfill(guild_id, since, take, &Deadline::none()).await?)
+        },
+        Command::Republish { match_id, sink } => {
+            let sinks = match sink.as_deref() {
+                Some(sink) => vec![sink],
+                None => SINKS.to_vec()
+            };
+            print_outcome(&poller.republish(&match_id, &sinks).await?)
+        },
+        Command::Preview { match_id } => {
+            let payloads: Map<String, Value> = poller.preview(&match_id).await?.into_iter()
+                .map(|(sink, payload)| (sink.to_string(), payload))
+                .collect();
+            println!("{}", serde_json::to_string_pretty(&payloads)?);
+            Ok(())
+        },
+        Command::Checkpoint { command: CheckpointCommand::Get } => {
+            match poller.checkpoint().await? {
+                Some(match_id) => println!("{}", match_id),
+                None => println!("No checkpoint")
+            }
+            Ok(())
+        },
+        Command::Checkpoint { command: CheckpointCommand::Set { match_id } } => {
+            poller.set_checkpoint(match_id).await?;
+            println!("{}", match_id);
+            Ok(())
+        },
+        Command::ValidateConfig => Ok(())
+    }
+}
+
+/// Print the outcome of a run, failing when a delivery failed
+fn print_outcome(outcome: &RunOutcome) -> Result<(), Error> {
+    println!("{}", serde_json::to_string_pretty(outcome)?);
+    if outcome.has_failures() {
+        return Err("Failed deliveries".into());
+    }
+
+    Ok(())
+}
+
+/// Parse the `--since` of a backfill, an RFC 3339 date time or a date at midnight UTC
+fn parse_since(value: &str) -> Result<DateTime<Utc>, String> {
+    if let Ok(since) = DateTime::parse_from_rfc3339(value) {
+        return Ok(since.with_timezone(&Utc));
+    }
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .ok()
+        .and_then(|date| date.and_hms_opt(0, 0, 0))
+        .map(|since| since.and_utc())
+        .ok_or_else(|| format!("Invalid date: {}", value))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use chrono::{TimeZone, Utc};
+    use clap::Parser;
+    use super::{Cli, Command, parse_since};
+
+    #[test]
+    fn test_parse_since() {
+        assert_eq!(parse_since("2024-05-01").unwrap(), Utc.with_ymd_and_hms(2024, 5, 1, 0, 0, 0).unwrap());
+        assert_eq!(parse_since("2024-05-01T20:30:00+08:00").unwrap(), Utc.with_ymd_and_hms(2024, 5, 1, 12, 30, 0).unwrap());
+        assert_eq!(parse_since("yesterday").unwrap_err(), "Invalid date: yesterday");
+    }
+
+    #[test]
+    fn test_parse_republish() {
+        let cli = Cli::try_parse_from(["poller-cli", "republish", "6789012345", "--sink", "kook"]).unwrap();
+        assert!(matches!(cli.command, Command::Republish { match_id, sink } if match_id == "6789012345" && sink.as_deref() == Some("kook")));
+        assert!(Cli::try_parse_from(["poller-cli", "republish", "6789012345", "--sink", "slack"]).is_err());
+    }
+
+}
@@ -1,69 +1,569 @@
-use std::env;
+use std::{collections::BTreeMap, env, path::{Path, PathBuf}};
+use serde::{Deserialize, Deserializer, de::DeserializeOwned};
 
-/// Get the Stratz API Key from `STRATZ_JWT` envrionmental variable
-/// Otherwise, exception thrown
-pub fn stratz_jwt() -> String {
-    let value = env::var("STRATZ_JWT").expect("Missing STRATZ_JWT environmental variable");
-    return value;
+/// Get the path of the TOML configuration file from `CONFIG_FILE` environmental variable
+/// Otherwise, None and settings are only read from environmental variables
+pub fn config_file() -> Option<String> {
+    env::var("CONFIG_FILE").ok()
 }
 
-/// Get the Discord webhook URL from `DISCORD_WEBHOOK_URL` environmental variable
-/// Otherwise, exception thrown
-pub fn discord_webhook_url() -> String {
-    let value = env::var("DISCORD_WEBHOOK_URL").expect("Missing DISCORD_WEBHOOK_URL environmental variable");
-    return value;
+/// Get a setting from the environmental variable `name` of the process
+pub fn env_var(name: &str) -> Option<String> {
+    env::var(name).ok()
 }
 
-/// Get the Kook token from `KOOK_TOKEN` environmental variable
-/// Otherwise, exception thrown
-pub fn kook_token() -> String {
-    let value = env::var("KOOK_TOKEN").expect("Missing KOOK_TOKEN environmental variable");
-    return value;
+/// A setting of the configuration, read from the file and overridden by its environmental variable. An invalid value
+/// is kept with its error rather than failing the whole file, so that every invalid setting is reported at once.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum Setting<T> {
+    #[default]
+    Missing,
+    Value(T),
+    Invalid(String)
 }
 
-#[cfg(test)]
-mod tests {
+impl<T> Setting<T> {
 
-    use std::env;
-    use super::{stratz_jwt, discord_webhook_url, kook_token};
+    /// Get the value of the setting
+    /// Otherwise, None when it is missing or invalid, an invalid setting being reported when the overrides are applied
+    pub fn value(&self) -> Option<&T> {
+        match self {
+            Setting::Value(value) => Some(value),
+            Setting::Missing | Setting::Invalid(_) => None
+        }
+    }
 
-    #[test]
-    #[should_panic(expected = "Missing STRATZ_JWT environmental variable")]
-    fn test_stratz_jwt_without_env() {
-        stratz_jwt();
+}
+
+impl<'de, T: DeserializeOwned> Deserialize<'de> for Setting<T> {
+
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = toml::Value::deserialize(deserializer)?;
+        match T::deserialize(value) {
+            Ok(value) => Ok(Setting::Value(value)),
+            Err(e) => Ok(Setting::Invalid(e.message().to_string()))
+        }
     }
-    
-    #[test]
-    fn test_stratz_jwt() {
-        env::set_var("STRATZ_JWT", "TestingJWT");
-        assert_eq!(stratz_jwt(), "TestingJWT");
-        env::remove_var("STRATZ_JWT");
+
+}
+
+/// Types a setting is parsed into from the text of its environmental variable
+pub trait FromVar: Sized {
+
+    /// Parse the text of an environmental variable
+    /// Otherwise, None when the text is not a valid value
+    fn from_var(value: &str) -> Option<Self>;
+
+}
+
+impl FromVar for String {
+    fn from_var(value: &str) -> Option<Self> {
+        Some(value.to_string())
     }
+}
 
-    #[test]
-    #[should_panic(expected = "Missing DISCORD_WEBHOOK_URL environmental variable")]
-    fn test_discord_webhook_url_without_env() {
-        discord_webhook_url();
+impl FromVar for PathBuf {
+    fn from_var(value: &str) -> Option<Self> {
+        Some(PathBuf::from(value))
+    }
+}
+
+/// Lists are comma separated in environmental variables
+impl FromVar for Vec<String> {
+    fn from_var(value: &str) -> Option<Self> {
+        Some(value.split(',').map(str::trim).filter(|item| !item.is_empty()).map(str::to_string).collect())
+    }
+}
+
+macro_rules! from_var_by_parse {
+    ($($type:ty),*) => {
+        $(impl FromVar for $type {
+            fn from_var(value: &str) -> Option<Self> {
+                value.trim().parse().ok()
+            }
+        })*
+    };
+}
+
+from_var_by_parse!(bool, u8, u16, u64, usize, i64, f64);
+
+/// Unknown fields of a section of the configuration file, reported as errors
+type Unknown = BTreeMap<String, toml::Value>;
+
+/// The configuration of the poller as written in the TOML file, one typed section per part of the poller
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct Config {
+    pub guild: GuildConfig,
+    pub provider: ProviderConfig,
+    pub sinks: SinksConfig,
+    pub players: PlayersConfig,
+    pub filters: FiltersConfig,
+    pub templates: TemplatesConfig,
+    pub storage: StorageConfig,
+    pub run: RunConfig,
+    #[serde(flatten)]
+    unknown: Unknown
+}
+
+/// The `[guild]` section, the guild polled
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct GuildConfig {
+    pub id: Setting<i64>,
+    pub take: Setting<i64>,
+    #[serde(flatten)]
+    unknown: Unknown
+}
+
+/// The `[provider]` section, the credentials of Stratz
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct ProviderConfig {
+    pub stratz_jwt: Setting<String>,
+    #[serde(flatten)]
+    unknown: Unknown
+}
+
+/// The `[sinks]` section, where matches, reports and alerts are posted
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct SinksConfig {
+    pub kook: KookConfig,
+    pub discord: DiscordConfig,
+    #[serde(flatten)]
+    unknown: Unknown
+}
+
+/// The `[sinks.kook]` section
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct KookConfig {
+    pub token: Setting<String>,
+    pub target_id: Setting<String>,
+    pub report_target_id: Setting<String>,
+    pub alert_target_id: Setting<String>,
+    #[serde(flatten)]
+    unknown: Unknown
+}
+
+/// The `[sinks.discord]` section
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct DiscordConfig {
+    pub webhook_url: Setting<String>,
+    pub report_webhook_url: Setting<String>,
+    pub alert_webhook_url: Setting<String>,
+    #[serde(flatten)]
+    unknown: Unknown
+}
+
+/// The `[players]` section, which players are listed on the card
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct PlayersConfig {
+    pub list: Setting<String>,
+    #[serde(flatten)]
+    unknown: Unknown
+}
+
+/// The `[filters]` section, the rules a match passes to be published
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct FiltersConfig {
+    pub lobby_types: Setting<Vec<String>>,
+    pub game_modes: Setting<Vec<String>>,
+    pub min_duration_seconds: Setting<i64>,
+    pub min_guild_members: Setting<usize>,
+    pub exclude_abandoned: Setting<bool>,
+    pub match_results: Setting<Vec<String>>,
+    #[serde(flatten)]
+    unknown: Unknown
+}
+
+/// The `[templates]` section, how matches and reports are rendered
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct TemplatesConfig {
+    pub hero_assets_dir: Setting<PathBuf>,
+    pub streak_threshold: Setting<i64>,
+    pub report_timezone: Setting<String>,
+    pub highlights: HighlightsConfig,
+    pub digest: DigestConfig,
+    #[serde(flatten)]
+    unknown: Unknown
+}
+
+/// The `[templates.highlights]` section, the thresholds of the highlight detectors
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct HighlightsConfig {
+    pub kills: Setting<u8>,
+    pub kda: Setting<f64>,
+    pub deathless_participation: Setting<u8>,
+    pub comeback_networth: Setting<i64>,
+    pub long_game_minutes: Setting<i64>,
+    pub short_game_minutes: Setting<i64>,
+    #[serde(flatten)]
+    unknown: Unknown
+}
+
+/// The `[templates.digest]` section, when matches are batched into a digest
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct DigestConfig {
+    pub threshold: Setting<usize>,
+    pub window_minutes: Setting<i64>,
+    #[serde(flatten)]
+    unknown: Unknown
+}
+
+/// The `[storage]` section, where and how long the state is kept
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct StorageConfig {
+    pub backend: Setting<String>,
+    pub file: Setting<PathBuf>,
+    pub database: Setting<PathBuf>,
+    pub dynamodb_endpoint: Setting<String>,
+    pub seen_retention_days: Setting<i64>,
+    pub lease_seconds: Setting<i64>,
+    pub bootstrap_policy: Setting<String>,
+    pub bootstrap_backfill: Setting<usize>,
+    pub tables: TablesConfig,
+    #[serde(flatten)]
+    unknown: Unknown
+}
+
+/// The `[storage.tables]` section, the names of the AWS DynamoDB tables
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct TablesConfig {
+    pub guilds: Setting<String>,
+    pub leases: Setting<String>,
+    pub seen_matches: Setting<String>,
+    pub quarantine: Setting<String>,
+    pub deliveries: Setting<String>,
+    pub head_to_head: Setting<String>,
+    pub streaks: Setting<String>,
+    pub health: Setting<String>,
+    pub match_history: Setting<String>,
+    #[serde(flatten)]
+    unknown: Unknown
+}
+
+/// The `[run]` section, how the poller runs
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct RunConfig {
+    pub mode: Setting<String>,
+    pub poll_interval_seconds: Setting<u64>,
+    pub poll_jitter_seconds: Setting<u64>,
+    pub reports: Setting<Vec<String>>,
+    pub error_mode: Setting<String>,
+    pub deadline_margin_seconds: Setting<i64>,
+    pub metrics_port: Setting<u16>,
+    pub metrics_namespace: Setting<String>,
+    pub alert_threshold: Setting<i64>,
+    pub dry_run: Setting<bool>,
+    pub dry_run_dir: Setting<PathBuf>,
+    #[serde(flatten)]
+    unknown: Unknown
+}
+
+impl Config {
+
+    /// Read the TOML configuration file at `path`
+    /// Otherwise, an error when the file cannot be read or is not a TOML table of sections
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the configuration file
+    pub fn read(path: &Path) -> Result<Config, String> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| format!("Cannot read configuration file {}: {}", path.display(), e))?;
+
+        Config::parse(&content)
+    }
+
+    /// Parse the content of a TOML configuration file
+    /// Otherwise, an error when the content is not a TOML table of sections. Invalid settings are kept in the
+    /// configuration, and reported when the overrides are applied.
+    ///
+    /// # Arguments
+    ///
+    /// * `content` - The content of the configuration file
+    pub fn parse(content: &str) -> Result<Config, String> {
+        toml::from_str(content).map_err(|e| format!("Invalid configuration file: {}", e.message()))
+    }
+
+    /// Override the settings with the environmental variables found by `lookup` and get the errors of every unknown
+    /// or invalid setting
+    ///
+    /// # Arguments
+    ///
+    /// * `lookup` - The lookup of an environmental variable by name, usually `env_var`
+    pub fn apply_env(&mut self, lookup: &dyn Fn(&str) -> Option<String>) -> Vec<String> {
+        let mut overlay = Overlay { lookup, errors: Vec::new() };
+        overlay.unknown("", &self.unknown);
+
+        let guild = &mut self.guild;
+        overlay.apply(&mut guild.id, "guild.id", "GUILD_ID");
+        overlay.apply(&mut guild.take, "guild.take", "POLL_TAKE");
+        overlay.unknown("guild", &guild.unknown);
+
+        overlay.apply(&mut self.provider.stratz_jwt, "provider.stratz_jwt", "STRATZ_JWT");
+        overlay.unknown("provider", &self.provider.unknown);
+
+        let kook = &mut self.sinks.kook;
+        overlay.apply(&mut kook.token, "sinks.kook.token", "KOOK_TOKEN");
+        overlay.apply(&mut kook.target_id, "sinks.kook.target_id", "KOOK_TARGET_ID");
+        overlay.apply(&mut kook.report_target_id, "sinks.kook.report_target_id", "REPORT_KOOK_TARGET_ID");
+        overlay.apply(&mut kook.alert_target_id, "sinks.kook.alert_target_id", "ALERT_KOOK_TARGET_ID");
+        overlay.unknown("sinks.kook", &kook.unknown);
+        let discord = &mut self.sinks.discord;
+        overlay.apply(&mut discord.webhook_url, "sinks.discord.webhook_url", "DISCORD_WEBHOOK_URL");
+        overlay.apply(
+            &mut discord.report_webhook_url, "sinks.discord.report_webhook_url", "REPORT_DISCORD_WEBHOOK_URL"
+        );
+        overlay.apply(&mut discord.alert_webhook_url, "sinks.discord.alert_webhook_url", "ALERT_DISCORD_WEBHOOK_URL");
+        overlay.unknown("sinks.discord", &discord.unknown);
+        overlay.unknown("sinks", &self.sinks.unknown);
+
+        overlay.apply(&mut self.players.list, "players.list", "PLAYER_LIST");
+        overlay.unknown("players", &self.players.unknown);
+
+        let filters = &mut self.filters;
+        overlay.apply(&mut filters.lobby_types, "filters.lobby_types", "FILTER_LOBBY_TYPES");
+        overlay.apply(&mut filters.game_modes, "filters.game_modes", "FILTER_GAME_MODES");
+        overlay.apply(&mut filters.min_duration_seconds, "filters.min_duration_seconds", "FILTER_MIN_DURATION_SECONDS");
+        overlay.apply(&mut filters.min_guild_members, "filters.min_guild_members", "FILTER_MIN_GUILD_MEMBERS");
+        overlay.apply(&mut filters.exclude_abandoned, "filters.exclude_abandoned", "FILTER_EXCLUDE_ABANDONED");
+        overlay.apply(&mut filters.match_results, "filters.match_results", "FILTER_MATCH_RESULTS");
+        overlay.unknown("filters", &filters.unknown);
+
+        let templates = &mut self.templates;
+        overlay.apply(&mut templates.hero_assets_dir, "templates.hero_assets_dir", "HERO_ASSETS_DIR");
+        overlay.apply(&mut templates.streak_threshold, "templates.streak_threshold", "STREAK_THRESHOLD");
+        overlay.apply(&mut templates.report_timezone, "templates.report_timezone", "REPORT_TIMEZONE");
+        overlay.unknown("templates", &templates.unknown);
+        let highlights = &mut templates.highlights;
+        overlay.apply(&mut highlights.kills, "templates.highlights.kills", "HIGHLIGHT_KILLS");
+        overlay.apply(&mut highlights.kda, "templates.highlights.kda", "HIGHLIGHT_KDA");
+        overlay.apply(
+            &mut highlights.deathless_participation,
+            "templates.highlights.deathless_participation",
+            "HIGHLIGHT_DEATHLESS_PARTICIPATION"
+        );
+        overlay.apply(
+            &mut highlights.comeback_networth, "templates.highlights.comeback_networth", "HIGHLIGHT_COMEBACK_NETWORTH"
+        );
+        overlay.apply(
+            &mut highlights.long_game_minutes, "templates.highlights.long_game_minutes", "HIGHLIGHT_LONG_GAME_MINUTES"
+        );
+        overlay.apply(
+            &mut highlights.short_game_minutes,
+            "templates.highlights.short_game_minutes",
+            "HIGHLIGHT_SHORT_GAME_MINUTES"
+        );
+        overlay.unknown("templates.highlights", &highlights.unknown);
+        let digest = &mut templates.digest;
+        overlay.apply(&mut digest.threshold, "templates.digest.threshold", "DIGEST_THRESHOLD");
+        overlay.apply(&mut digest.window_minutes, "templates.digest.window_minutes", "DIGEST_WINDOW_MINUTES");
+        overlay.unknown("templates.digest", &digest.unknown);
+
+        let storage = &mut self.storage;
+        overlay.apply(&mut storage.backend, "storage.backend", "STATE_STORE");
+        overlay.apply(&mut storage.file, "storage.file", "STATE_FILE");
+        overlay.apply(&mut storage.database, "storage.database", "STATE_DATABASE");
+        overlay.apply(&mut storage.dynamodb_endpoint, "storage.dynamodb_endpoint", "DYNAMODB_ENDPOINT");
+        overlay.apply(&mut storage.seen_retention_days, "storage.seen_retention_days", "SEEN_RETENTION_DAYS");
+        overlay.apply(&mut storage.lease_seconds, "storage.lease_seconds", "LEASE_SECONDS");
+        overlay.apply(&mut storage.bootstrap_policy, "storage.bootstrap_policy", "BOOTSTRAP_POLICY");
+        overlay.apply(&mut storage.bootstrap_backfill, "storage.bootstrap_backfill", "BOOTSTRAP_BACKFILL");
+        overlay.unknown("storage", &storage.unknown);
+        let tables = &mut storage.tables;
+        overlay.apply(&mut tables.guilds, "storage.tables.guilds", "GUILDS_TABLE_NAME");
+        overlay.apply(&mut tables.leases, "storage.tables.leases", "LEASES_TABLE_NAME");
+        overlay.apply(&mut tables.seen_matches, "storage.tables.seen_matches", "SEEN_MATCHES_TABLE_NAME");
+        overlay.apply(&mut tables.quarantine, "storage.tables.quarantine", "QUARANTINE_TABLE_NAME");
+        overlay.apply(&mut tables.deliveries, "storage.tables.deliveries", "DELIVERIES_TABLE_NAME");
+        overlay.apply(&mut tables.head_to_head, "storage.tables.head_to_head", "HEAD_TO_HEAD_TABLE_NAME");
+        overlay.apply(&mut tables.streaks, "storage.tables.streaks", "STREAKS_TABLE_NAME");
+        overlay.apply(&mut tables.health, "storage.tables.health", "HEALTH_TABLE_NAME");
+        overlay.apply(&mut tables.match_history, "storage.tables.match_history", "MATCH_HISTORY_TABLE_NAME");
+        overlay.unknown("storage.tables", &tables.unknown);
+
+        let run = &mut self.run;
+        overlay.apply(&mut run.mode, "run.mode", "RUN_MODE");
+        overlay.apply(&mut run.poll_interval_seconds, "run.poll_interval_seconds", "POLL_INTERVAL_SECONDS");
+        overlay.apply(&mut run.poll_jitter_seconds, "run.poll_jitter_seconds", "POLL_JITTER_SECONDS");
+        overlay.apply(&mut run.reports, "run.reports", "DAEMON_REPORTS");
+        overlay.apply(&mut run.error_mode, "run.error_mode", "ERROR_MODE");
+        overlay.apply(&mut run.deadline_margin_seconds, "run.deadline_margin_seconds", "DEADLINE_MARGIN_SECONDS");
+        overlay.apply(&mut run.metrics_port, "run.metrics_port", "METRICS_PORT");
+        overlay.apply(&mut run.metrics_namespace, "run.metrics_namespace", "METRICS_NAMESPACE");
+        overlay.apply(&mut run.alert_threshold, "run.alert_threshold", "ALERT_THRESHOLD");
+        overlay.apply(&mut run.dry_run, "run.dry_run", "DRY_RUN");
+        overlay.apply(&mut run.dry_run_dir, "run.dry_run_dir", "DRY_RUN_DIR");
+        overlay.unknown("run", &run.unknown);
+
+        overlay.errors
+    }
+
+}
+
+/// Overrides of the settings by their environmental variables, keeping the errors of every unknown or invalid setting
+struct Overlay<'a> {
+    lookup: &'a dyn Fn(&str) -> Option<String>,
+    errors: Vec<String>
+}
+
+impl Overlay<'_> {
+
+    /// Override `setting` with the environmental variable `name` when it is set, keeping the error of an invalid value
+    ///
+    /// # Arguments
+    ///
+    /// * `setting` - The setting read from the configuration file
+    /// * `path` - The path of the setting in the configuration file, such as `guild.take`
+    /// * `name` - The name of the environmental variable overriding the setting, such as `POLL_TAKE`
+    fn apply<T: FromVar>(&mut self, setting: &mut Setting<T>, path: &str, name: &str) {
+        if let Some(value) = (self.lookup)(name) {
+            *setting = match T::from_var(&value) {
+                Some(value) => Setting::Value(value),
+                None => Setting::Invalid(value)
+            };
+            if let Setting::Invalid(value) = setting {
+                self.errors.push(format!("Invalid {}: {}", name, value));
+            }
+        } else if let Setting::Invalid(message) = setting {
+            self.errors.push(format!("Invalid field {}: {}", path, message));
+        }
+    }
+
+    /// Keep an error for every unknown field of the section at `path`
+    fn unknown(&mut self, path: &str, fields: &Unknown) {
+        for field in fields.keys() {
+            match path {
+                "" => self.errors.push(format!("Unknown field {}", field)),
+                _ => self.errors.push(format!("Unknown field {}.{}", path, field))
+            }
+        }
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::collections::HashMap;
+    use std::path::PathBuf;
+    use super::{Config, Setting};
+
+    /// Build a lookup of environmental variables from `vars`, so that tests do not touch the process environment
+    fn lookup(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let vars: HashMap<String, String> = vars.iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        move |name| vars.get(name).cloned()
     }
 
     #[test]
-    fn test_discord_webhook_url() {
-        env::set_var("DISCORD_WEBHOOK_URL", "TestingUrl");
-        assert_eq!(discord_webhook_url(), "TestingUrl");
-        env::remove_var("DISCORD_WEBHOOK_URL");
+    fn test_parse() {
+        let mut config = Config::parse(r#"
+            [guild]
+            id = 117311
+
+            [sinks.kook]
+            token = "TestingToken"
+
+            [filters]
+            lobby_types = ["RANKED", "UNRANKED"]
+            exclude_abandoned = true
+
+            [templates]
+            hero_assets_dir = "/var/task/assets/heroes"
+
+            [templates.highlights]
+            kda = 12.5
+
+            [storage.tables]
+            guilds = "TestingGuilds"
+        "#).unwrap();
+        assert!(config.apply_env(&lookup(&[])).is_empty());
+        assert_eq!(config.guild.id, Setting::Value(117311));
+        assert_eq!(config.guild.take, Setting::Missing);
+        assert_eq!(config.sinks.kook.token, Setting::Value("TestingToken".to_string()));
+        assert_eq!(config.filters.lobby_types, Setting::Value(vec!["RANKED".to_string(), "UNRANKED".to_string()]));
+        assert_eq!(config.filters.exclude_abandoned, Setting::Value(true));
+        assert_eq!(config.templates.hero_assets_dir, Setting::Value(PathBuf::from("/var/task/assets/heroes")));
+        assert_eq!(config.templates.highlights.kda, Setting::Value(12.5));
+        assert_eq!(config.storage.tables.guilds, Setting::Value("TestingGuilds".to_string()));
     }
 
     #[test]
-    #[should_panic(expected = "Missing KOOK_TOKEN environmental variable")]
-    fn test_kook_token_without_env() {
-        kook_token();
+    fn test_apply_env() {
+        let mut config = Config::parse(r#"
+            [guild]
+            id = 117311
+            take = 5
+
+            [filters]
+            lobby_types = ["RANKED"]
+        "#).unwrap();
+        let errors = config.apply_env(&lookup(&[
+            ("POLL_TAKE", "10"),
+            ("STRATZ_JWT", "TestingJWT"),
+            ("FILTER_LOBBY_TYPES", "RANKED, UNRANKED"),
+            ("GUILDS_TABLE_NAME", "TestingGuilds")
+        ]));
+        assert!(errors.is_empty());
+        assert_eq!(config.guild.id, Setting::Value(117311));
+        assert_eq!(config.guild.take, Setting::Value(10));
+        assert_eq!(config.provider.stratz_jwt, Setting::Value("TestingJWT".to_string()));
+        assert_eq!(config.filters.lobby_types, Setting::Value(vec!["RANKED".to_string(), "UNRANKED".to_string()]));
+        assert_eq!(config.storage.tables.guilds, Setting::Value("TestingGuilds".to_string()));
+        assert_eq!(config.storage.tables.leases, Setting::Missing);
     }
 
     #[test]
-    fn test_kook_token() {
-        env::set_var("KOOK_TOKEN", "TestingToken");
-        assert_eq!(kook_token(), "TestingToken");
-        env::remove_var("KOOK_TOKEN");
+    fn test_apply_env_errors() {
+        let mut config = Config::parse(r#"
+            [guild]
+            id = "NanTuan"
+            name = "NanTuan"
+
+            [filters]
+            lobby_types = [["RANKED"]]
+            min_guild_member = 2
+
+            [templates.highlights]
+            kills = 300
+
+            [metrics]
+            port = 9100
+        "#).unwrap();
+        let errors = config.apply_env(&lookup(&[("POLL_TAKE", "ten"), ("DRY_RUN", "yes")]));
+        assert_eq!(errors, vec![
+            "Unknown field metrics",
+            "Invalid field guild.id: invalid type: string \"NanTuan\", expected i64",
+            "Invalid POLL_TAKE: ten",
+            "Unknown field guild.name",
+            "Invalid field filters.lobby_types: invalid type: sequence, expected a string",
+            "Unknown field filters.min_guild_member",
+            "Invalid field templates.highlights.kills: invalid value: integer `300`, expected u8",
+            "Invalid DRY_RUN: yes"
+        ]);
+        assert_eq!(config.guild.id.value(), None);
+
+        // An environmental variable replaces an invalid value of the file
+        let mut config = Config::parse("[guild]\nid = \"NanTuan\"").unwrap();
+        assert!(config.apply_env(&lookup(&[("GUILD_ID", "117311")])).is_empty());
+        assert_eq!(config.guild.id, Setting::Value(117311));
+
+        let error = Config::parse("[guild\nid = 117311").unwrap_err();
+        assert!(error.starts_with("Invalid configuration file: "));
     }
 
 }
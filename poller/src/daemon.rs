@@ -0,0 +1,156 @@
+use std::time::{Duration, Instant};
+use lambda_runtime::Error;
+use tokio::signal::unix::{SignalKind, signal};
+use crate::{config::RunConfig, deadline::Deadline, poller::Poller, report::summary::ReportPeriod, telemetry::metrics};
+
+const DEFAULT_INTERVAL_SECONDS: u64 = 300;
+const DEFAULT_JITTER_SECONDS: u64 = 30;
+
+/// Struct containing the schedule of the daemon
+#[derive(Debug, PartialEq, Eq)]
+pub struct Schedule {
+    pub interval: Duration,
+    pub jitter: Duration,
+    /// The summary reports posted at the start of every period
+    pub reports: Vec<ReportPeriod>
+}
+
+impl Schedule {
+
+    /// Build the schedule from the `poll_interval_seconds`, `poll_jitter_seconds` and `reports` settings of the
+    /// `[run]` section, every 5 minutes with up to 30 seconds of jitter and both reports by default
+    pub fn from_config(run: &RunConfig) -> Result<Schedule, Error> {
+        let interval = match run.poll_interval_seconds.value() {
+            Some(0) => return Err("Invalid POLL_INTERVAL_SECONDS: 0".into()),
+            Some(seconds) => *seconds,
+            None => DEFAULT_INTERVAL_SECONDS
+        };
+        let jitter = run.poll_jitter_seconds.value().copied().unwrap_or(DEFAULT_JITTER_SECONDS);
+
+        let reports = match run.reports.value() {
+            Some(periods) => periods.iter()
+                .map(|period| {
+                    ReportPeriod::parse(period.trim()).map_err(|_| format!("Invalid DAEMON_REPORTS: {}", period).into())
+                })
+                .collect::<Result<Vec<ReportPeriod>, Error>>()?,
+            None => vec![ReportPeriod::Daily, ReportPeriod::Weekly]
+        };
+
+        Ok(Schedule { interval: Duration::from_secs(interval), jitter: Duration::from_secs(jitter), reports })
+    }
+
+    /// Get the delay before the next cycle, the interval plus a part of the jitter picked by `seed`, so that several
+    /// daemons do not poll Stratz at the same second
+    ///
+    /// # Arguments
+    ///
+    /// * `seed` - Any number changing between cycles, such as the nanoseconds of the clock
+    pub fn next_delay(&self, seed: u32) -> Duration {
+        let jitter_millis = self.jitter.as_millis() as u64;
+        let jitter = match jitter_millis {
+            0 => 0,
+            _ => seed as u64 % (jitter_millis + 1)
+        };
+
+        self.interval + Duration::from_millis(jitter)
+    }
+
+}
+
+/// Poll new matches on the schedule until SIGTERM or Ctrl-C. A cycle in progress is always completed, so that its
+/// lease is released and its checkpoint saved before exiting. The metrics of the cycles are served to Prometheus when
+/// `METRICS_PORT` is set, and the admins alerted when a component fails several cycles in a row.
+///
+/// The summary reports are posted by the first cycle after the start of each day or week in `REPORT_TIMEZONE`. A
+/// report which fails is retried by the next cycle.
+///
+/// # Arguments
+///
+/// * `poller` - The poller, reused by every cycle
+/// * `schedule` - The schedule of the cycles
+pub async fn run(poller: &Poller, schedule: &Schedule) -> Result<(), Error> {
+    // Both signals are registered once, so a signal received during a cycle stops the daemon once the cycle is done
+    let mut terminate = signal(SignalKind::terminate())?;
+    let mut interrupt = signal(SignalKind::interrupt())?;
+    if let Some(port) = poller.run.metrics_port {
+        tokio::spawn(async move {
+            if let Err(e) = metrics::serve(port).await {
+                log::error!(port = port; "Failed to serve metrics: {}", e);
+            }
+        });
+    }
+    log::info!("Polling every {:?} with up to {:?} of jitter", schedule.interval, schedule.jitter);
+
+    // The time each report is due next. The periods which ended before the daemon started are not reported
+    let timezone = poller.publisher.templates.report_timezone;
+    let mut reports = Vec::new();
+    for period in schedule.reports.iter() {
+        reports.push((*period, period.next_start(chrono::Utc::now(), timezone)?));
+    }
+
+    loop {
+        let guild_id = poller.guild.id;
+        let started = Instant::now();
+        let result = poller.process(&Deadline::none()).await;
+        let elapsed = started.elapsed();
+        metrics::record_cycle(elapsed, result.as_ref().ok());
+        poller.watch(&result).await;
+        let elapsed_ms = elapsed.as_millis() as u64;
+        match result {
+            Ok(outcome) if outcome.has_failures() => log::warn!(
+                guild_id = guild_id, elapsed_ms = elapsed_ms; "Cycle completed with failed deliveries: {:?}", outcome
+            ),
+            Ok(outcome) => log::info!(guild_id = guild_id, elapsed_ms = elapsed_ms; "Cycle completed: {:?}", outcome),
+            // A failed cycle is retried by the next one
+            Err(e) => log::error!(guild_id = guild_id, elapsed_ms = elapsed_ms; "Cycle failed: {}", e)
+        }
+
+        for (period, due_at) in reports.iter_mut() {
+            let now = chrono::Utc::now();
+            if now < *due_at {
+                continue;
+            }
+            match poller.report(period.as_str()).await {
+                Ok(_) => {
+                    log::info!(guild_id = guild_id; "Posted the {} report", period.as_str());
+                    *due_at = period.next_start(now, timezone)?;
+                },
+                Err(e) => log::error!(guild_id = guild_id; "Failed to post the {} report: {}", period.as_str(), e)
+            }
+        }
+
+        let delay = schedule.next_delay(chrono::Utc::now().timestamp_subsec_nanos());
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => {},
+            _ = terminate.recv() => break,
+            _ = interrupt.recv() => break
+        }
+    }
+    log::info!("Stopping the daemon");
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::time::Duration;
+    use super::Schedule;
+
+    #[test]
+    fn test_next_delay() {
+        let schedule = Schedule {
+            interval: Duration::from_secs(300),
+            jitter: Duration::from_secs(30),
+            reports: Vec::new()
+        };
+        assert_eq!(schedule.next_delay(0), Duration::from_secs(300));
+        assert_eq!(schedule.next_delay(1500), Duration::from_millis(301500));
+        assert_eq!(schedule.next_delay(30000), Duration::from_secs(330));
+        assert_eq!(schedule.next_delay(30001), Duration::from_secs(300));
+
+        let schedule = Schedule { interval: Duration::from_secs(60), jitter: Duration::ZERO, reports: Vec::new() };
+        assert_eq!(schedule.next_delay(123456789), Duration::from_secs(60));
+    }
+
+}
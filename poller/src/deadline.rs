@@ -0,0 +1,79 @@
+use chrono::{DateTime, Duration, Utc};
+
+/// The time a run stops taking new matches, leaving a margin before the Lambda deadline to deliver the match in
+/// progress and save the checkpoint. The matches left are published by the next run.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Deadline {
+    pub stop_at: Option<DateTime<Utc>>
+}
+
+impl Deadline {
+
+    /// Get the Deadline of a run without time limit, such as a daemon cycle or a CLI command
+    pub fn none() -> Deadline {
+        Deadline { stop_at: None }
+    }
+
+    /// Get the Deadline of a Lambda invocation
+    ///
+    /// # Arguments
+    ///
+    /// * `deadline_ms` - The deadline of the invocation from the Lambda context, in milliseconds since the epoch
+    /// * `margin` - The time kept before the deadline to finish the run
+    pub fn from_lambda(deadline_ms: u64, margin: Duration) -> Deadline {
+        let stop_at = i64::try_from(deadline_ms).ok()
+            .and_then(DateTime::from_timestamp_millis)
+            .map(|deadline| deadline - margin);
+
+        Deadline { stop_at }
+    }
+
+    /// Get the Deadline stopping at `stop_at` at the latest, e.g. before the lease of the guild expires
+    ///
+    /// # Arguments
+    ///
+    /// * `stop_at` - The latest time the run stops taking new matches
+    pub fn at_most(&self, stop_at: DateTime<Utc>) -> Deadline {
+        let stop_at = match self.stop_at {
+            Some(current) => current.min(stop_at),
+            None => stop_at
+        };
+
+        Deadline { stop_at: Some(stop_at) }
+    }
+
+    /// Check whether the run must stop taking new matches at `now`
+    pub fn is_reached(&self, now: DateTime<Utc>) -> bool {
+        self.stop_at.is_some_and(|stop_at| now >= stop_at)
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use chrono::{Duration, TimeZone, Utc};
+    use super::Deadline;
+
+    #[test]
+    fn test_from_lambda() {
+        let deadline = Deadline::from_lambda(1650000030000, Duration::seconds(5));
+        assert_eq!(deadline.stop_at, Some(Utc.timestamp_opt(1650000025, 0).unwrap()));
+        assert!(!deadline.is_reached(Utc.timestamp_opt(1650000024, 0).unwrap()));
+        assert!(deadline.is_reached(Utc.timestamp_opt(1650000025, 0).unwrap()));
+
+        assert!(!Deadline::none().is_reached(Utc.timestamp_opt(1650000025, 0).unwrap()));
+    }
+
+    #[test]
+    fn test_at_most() {
+        let lease_end = Utc.timestamp_opt(1650000020, 0).unwrap();
+        assert_eq!(Deadline::none().at_most(lease_end).stop_at, Some(lease_end));
+
+        let deadline = Deadline::from_lambda(1650000030000, Duration::seconds(5));
+        assert_eq!(deadline.at_most(lease_end).stop_at, Some(lease_end));
+        let later_lease_end = Utc.timestamp_opt(1650000090, 0).unwrap();
+        assert_eq!(deadline.at_most(later_lease_end).stop_at, Some(Utc.timestamp_opt(1650000025, 0).unwrap()));
+    }
+
+}
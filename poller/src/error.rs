@@ -0,0 +1,81 @@
+use std::fmt;
+use lambda_runtime::Error;
+
+/// Errors of the poller, by the part of the pipeline they come from. They convert into `lambda_runtime::Error` like
+/// any other error, and can be told apart again with `downcast_ref`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PollerError {
+    /// The Dota2 data provider failed, or answered without the data requested
+    Provider(String),
+    /// The state store failed, or holds a state another run changed
+    State(String),
+    /// A destination, such as Kook or Discord, failed
+    Sink { sink: String, message: String },
+    /// A match misses data it cannot be published without. Only this match fails, it is quarantined and the run goes
+    /// on with the other matches.
+    DataQuality(String),
+    /// The configuration is incomplete or invalid, with the error of every field
+    Config(Vec<String>)
+}
+
+impl PollerError {
+
+    /// Wrap an error raised while delivering to `sink`
+    ///
+    /// # Arguments
+    ///
+    /// * `sink` - The name of the destination, such as `kook` or `discord`
+    /// * `error` - The error raised by the destination
+    pub fn sink(sink: &str, error: Error) -> PollerError {
+        PollerError::Sink { sink: sink.to_string(), message: error.to_string() }
+    }
+
+    /// Check whether `error` only concerns a single match, which is quarantined rather than aborting the run
+    pub fn is_match_failure(error: &Error) -> bool {
+        matches!(error.downcast_ref::<PollerError>(), Some(PollerError::DataQuality(_)))
+    }
+
+}
+
+impl fmt::Display for PollerError {
+
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PollerError::Provider(message) => write!(f, "Provider error: {}", message),
+            PollerError::State(message) => write!(f, "State error: {}", message),
+            PollerError::Sink { sink, message } => write!(f, "Sink error on {}: {}", sink, message),
+            PollerError::DataQuality(message) => write!(f, "Data quality error: {}", message),
+            PollerError::Config(errors) => write!(f, "Invalid configuration: {}", errors.join("; "))
+        }
+    }
+
+}
+
+impl std::error::Error for PollerError {}
+
+#[cfg(test)]
+mod tests {
+
+    use lambda_runtime::Error;
+    use super::PollerError;
+
+    #[test]
+    fn test_display() {
+        assert_eq!(PollerError::Provider("No guild 117311".to_string()).to_string(), "Provider error: No guild 117311");
+        let error = PollerError::sink("kook", "Timed out".into());
+        assert_eq!(error.to_string(), "Sink error on kook: Timed out");
+        let error = PollerError::Config(vec!["Missing KOOK_TOKEN".to_string(), "Invalid POLL_TAKE: 0".to_string()]);
+        assert_eq!(error.to_string(), "Invalid configuration: Missing KOOK_TOKEN; Invalid POLL_TAKE: 0");
+    }
+
+    #[test]
+    fn test_is_match_failure() {
+        let error: Error = PollerError::DataQuality("Missing end of match".to_string()).into();
+        assert!(PollerError::is_match_failure(&error));
+        let error: Error = PollerError::State("Checkpoint changed".to_string()).into();
+        assert!(!PollerError::is_match_failure(&error));
+        let error: Error = "Invalid PLAYER_LIST: some".into();
+        assert!(!PollerError::is_match_failure(&error));
+    }
+
+}
@@ -0,0 +1,115 @@
+use chrono::{DateTime, Utc};
+use lambda_runtime::Error;
+use serde::Deserialize;
+use serde_json::Value;
+use crate::poller::DEFAULT_BACKFILL_TAKE;
+
+/// The actions an event can trigger instead of the scheduled polling, by their key in the payload
+const ACTIONS: [&str; 3] = ["republish", "backfill", "report"];
+
+/// Enum of the events the Lambda function handles
+#[derive(Deserialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PollerEvent {
+    /// Poll new matches, on the schedule of EventBridge or on any payload which is not an action
+    #[serde(skip)]
+    Scheduled,
+    /// Publish a match of the match history again to every sink, e.g. `{"republish": 6789012345}`
+    Republish(MatchId),
+    /// Publish the matches of a guild which were never seen, e.g. `{"backfill": {"guild": 117311, "take": 50}}`
+    Backfill(BackfillEvent),
+    /// Post a guild summary report, e.g. `{"report": "weekly"}`
+    Report(String)
+}
+
+/// Struct containing the options of a backfill event, the guild of the configuration by default
+#[derive(Deserialize, Debug, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct BackfillEvent {
+    pub guild: Option<i64>,
+    #[serde(default = "default_take")]
+    pub take: i64,
+    /// The earliest end of the matches, every match fetched by default
+    pub since: Option<DateTime<Utc>>
+}
+
+/// A match id, written as a number or as a string
+#[derive(Deserialize, Debug, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum MatchId {
+    Number(i64),
+    Text(String)
+}
+
+impl PollerEvent {
+
+    /// Get the PollerEvent of a Lambda `payload`, an object with a single action key such as `republish`
+    /// Otherwise, the scheduled polling, as EventBridge schedules send their own payload
+    ///
+    /// # Arguments
+    ///
+    /// * `payload` - The payload of the Lambda event
+    pub fn parse(payload: Value) -> Result<PollerEvent, Error> {
+        let is_action = payload.as_object()
+            .is_some_and(|object| object.len() == 1 && ACTIONS.iter().any(|action| object.contains_key(*action)));
+        if !is_action {
+            return Ok(PollerEvent::Scheduled);
+        }
+
+        let description = payload.to_string();
+        serde_json::from_value(payload).map_err(|e| format!("Invalid event {}: {}", description, e).into())
+    }
+
+}
+
+impl std::fmt::Display for MatchId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MatchId::Number(match_id) => write!(f, "{}", match_id),
+            MatchId::Text(match_id) => write!(f, "{}", match_id)
+        }
+    }
+}
+
+/// The number of latest matches fetched by a backfill event without `take`
+fn default_take() -> i64 {
+    DEFAULT_BACKFILL_TAKE
+}
+
+#[cfg(test)]
+mod tests {
+
+    use chrono::{TimeZone, Utc};
+    use serde_json::json;
+    use super::{BackfillEvent, MatchId, PollerEvent};
+
+    #[test]
+    fn test_parse() {
+        let scheduled = json!({ "source": "aws.events", "detail-type": "Scheduled Event", "detail": {} });
+        assert_eq!(PollerEvent::parse(scheduled).unwrap(), PollerEvent::Scheduled);
+        assert_eq!(PollerEvent::parse(json!({})).unwrap(), PollerEvent::Scheduled);
+
+        assert_eq!(PollerEvent::parse(json!({ "republish": 6789012345i64 })).unwrap(), PollerEvent::Republish(MatchId::Number(6789012345)));
+        let event = PollerEvent::parse(json!({ "republish": "6789012345" })).unwrap();
+        assert!(matches!(event, PollerEvent::Republish(match_id) if match_id.to_string() == "6789012345"));
+
+        assert_eq!(
+            PollerEvent::parse(json!({ "backfill": { "guild": 117311, "take": 20 } })).unwrap(),
+            PollerEvent::Backfill(BackfillEvent { guild: Some(117311), take: 20, since: None })
+        );
+        assert_eq!(
+            PollerEvent::parse(json!({ "backfill": { "since": "2024-05-01T00:00:00Z" } })).unwrap(),
+            PollerEvent::Backfill(BackfillEvent { guild: None, take: 50, since: Some(Utc.with_ymd_and_hms(2024, 5, 1, 0, 0, 0).unwrap()) })
+        );
+        assert_eq!(PollerEvent::parse(json!({ "report": "weekly" })).unwrap(), PollerEvent::Report("weekly".to_string()));
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!(PollerEvent::parse(json!({ "republish": null })).is_err());
+        assert!(PollerEvent::parse(json!({ "backfill": { "guild": "NanTuan" } })).is_err());
+        assert!(PollerEvent::parse(json!({ "backfill": { "guilds": [117311] } })).is_err());
+        assert!(PollerEvent::parse(json!({ "report": 7 })).is_err());
+    }
+
+}
@@ -0,0 +1,104 @@
+use lambda_runtime::Error;
+use crate::{config::StorageConfig, provider::stratz};
+
+const DEFAULT_BACKFILL: usize = 5;
+
+/// What the first run of a guild, without any checkpoint, does with the matches fetched
+#[derive(Debug, PartialEq, Eq)]
+pub enum Bootstrap {
+    /// Record the newest match as the checkpoint without publishing anything
+    StartFromNow,
+    /// Publish the given number of newest matches
+    Backfill(usize)
+}
+
+impl Bootstrap {
+
+    /// Build the policy from the `storage.bootstrap_policy` and `storage.bootstrap_backfill` settings, starting from
+    /// now by default
+    pub fn from_config(storage: &StorageConfig) -> Result<Bootstrap, Error> {
+        let policy = storage.bootstrap_policy.value().map(String::as_str);
+        Bootstrap::parse(policy, storage.bootstrap_backfill.value().copied())
+    }
+
+    /// Parse the policy, `now` or `backfill`, and the number of matches published by a backfill, 5 by default
+    ///
+    /// # Arguments
+    ///
+    /// * `policy` - The value of `BOOTSTRAP_POLICY`, if any
+    /// * `backfill` - The value of `BOOTSTRAP_BACKFILL`, if any
+    pub fn parse(policy: Option<&str>, backfill: Option<usize>) -> Result<Bootstrap, Error> {
+        match policy.map(|policy| policy.trim().to_lowercase()).as_deref() {
+            None | Some("now") => Ok(Bootstrap::StartFromNow),
+            Some("backfill") => Ok(Bootstrap::Backfill(backfill.unwrap_or(DEFAULT_BACKFILL))),
+            Some(_) => Err(format!("Invalid BOOTSTRAP_POLICY: {}", policy.unwrap_or_default()).into())
+        }
+    }
+
+    /// Split the matches fetched on the first run into the matches only recorded as processed and the matches to be
+    /// published, both sorted by the end of the match then by id
+    ///
+    /// # Arguments
+    ///
+    /// * `guild_matches` - The matches fetched from Stratz
+    pub fn split(
+        &self,
+        mut guild_matches: Vec<stratz::api::Match>
+    ) -> (Vec<stratz::api::Match>, Vec<stratz::api::Match>) {
+        guild_matches.sort_by_key(|guild_match| (guild_match.end_date_time, guild_match.id));
+        let count = match self {
+            Bootstrap::StartFromNow => 0,
+            Bootstrap::Backfill(count) => *count
+        };
+        let backfill = guild_matches.split_off(guild_matches.len().saturating_sub(count));
+
+        (guild_matches, backfill)
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::provider::stratz;
+    use crate::publisher::fixtures::ended_match;
+    use super::Bootstrap;
+
+    fn match_ids(guild_matches: &[stratz::api::Match]) -> Vec<i64> {
+        guild_matches.iter().map(|guild_match| guild_match.id.unwrap()).collect()
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Bootstrap::parse(None, None).unwrap(), Bootstrap::StartFromNow);
+        assert_eq!(Bootstrap::parse(Some("now"), Some(3)).unwrap(), Bootstrap::StartFromNow);
+        assert_eq!(Bootstrap::parse(Some("backfill"), None).unwrap(), Bootstrap::Backfill(5));
+        assert_eq!(Bootstrap::parse(Some(" Backfill "), Some(2)).unwrap(), Bootstrap::Backfill(2));
+        assert!(Bootstrap::parse(Some("everything"), None).is_err());
+    }
+
+    #[test]
+    fn test_split_start_from_now() {
+        let guild_matches = vec![ended_match(102, 1650003000), ended_match(101, 1650001000)];
+        let (skipped, backfill) = Bootstrap::StartFromNow.split(guild_matches);
+        assert_eq!(match_ids(&skipped), vec![101, 102]);
+        assert!(backfill.is_empty());
+    }
+
+    #[test]
+    fn test_split_backfill() {
+        let guild_matches = vec![
+            ended_match(103, 1650002000),
+            ended_match(101, 1650001000),
+            ended_match(102, 1650003000)
+        ];
+        let (skipped, backfill) = Bootstrap::Backfill(2).split(guild_matches);
+        assert_eq!(match_ids(&skipped), vec![101]);
+        assert_eq!(match_ids(&backfill), vec![103, 102]);
+
+        let (skipped, backfill) = Bootstrap::Backfill(5).split(vec![ended_match(101, 1650001000)]);
+        assert!(skipped.is_empty());
+        assert_eq!(match_ids(&backfill), vec![101]);
+    }
+
+}
@@ -0,0 +1,182 @@
+use chrono::{DateTime, Duration, Utc};
+use lambda_runtime::Error;
+use crate::{config::StorageConfig, provider::stratz, store::state::StateStore};
+use crate::settings::parse_or;
+
+const DEFAULT_RETENTION_DAYS: i64 = 7;
+
+/// Deduplication of fetched matches against the set of match ids already seen.
+///
+/// Match ids do not arrive in order: a long game ending after a short one can have a smaller id, so a match is new
+/// whenever its id was never seen, whatever the latest match id processed. Seen ids are kept for `retention`, and
+/// matches which ended longer than `retention` ago are never considered new, so an expired id is never published
+/// again.
+#[derive(Debug)]
+pub struct Dedup {
+    pub retention: Duration
+}
+
+impl Dedup {
+
+    /// Build the deduplication from the `storage.seen_retention_days` setting, 7 days by default
+    pub fn from_config(storage: &StorageConfig) -> Result<Dedup, Error> {
+        let days = parse_or(&storage.seen_retention_days, "SEEN_RETENTION_DAYS", DEFAULT_RETENTION_DAYS)?;
+
+        Ok(Dedup { retention: Duration::days(days) })
+    }
+
+    /// Get the time until which a match id marked as seen at `now` is kept
+    pub fn expires_at(&self, now: DateTime<Utc>) -> DateTime<Utc> {
+        now + self.retention
+    }
+
+    /// Keep the matches among `guild_matches` which were never seen, sorted by the end of the match then by id
+    ///
+    /// Deployments which only kept the latest match id processed have no seen ids yet. While the checkpoint match
+    /// itself is not marked as seen, the matches up to the checkpoint are marked as seen instead of being published.
+    /// The seen marker of the checkpoint is refreshed on every run, so that it only expires when the poller did not run
+    /// for the whole retention, and the matches up to the checkpoint all ended before the retention by then.
+    ///
+    /// # Arguments
+    ///
+    /// * `store` - The state store keeping the seen match ids
+    /// * `checkpoint` - The latest match id processed, if any
+    /// * `guild_matches` - The matches fetched from Stratz
+    /// * `now` - The time of the run
+    pub async fn new_matches(
+        &self,
+        store: &dyn StateStore,
+        checkpoint: Option<i64>,
+        guild_matches: Vec<stratz::api::Match>,
+        now: DateTime<Utc>
+    ) -> Result<Vec<stratz::api::Match>, Error> {
+        let seed_checkpoint = match checkpoint {
+            Some(checkpoint) if !store.is_seen(checkpoint).await? => Some(checkpoint),
+            _ => None
+        };
+        let oldest_end = (now - self.retention).timestamp();
+
+        let mut new_matches = Vec::new();
+        for guild_match in guild_matches {
+            let match_id = match guild_match.id {
+                Some(match_id) => match_id,
+                None => continue
+            };
+            if guild_match.end_date_time.is_some_and(|end_date_time| end_date_time < oldest_end) {
+                continue;
+            }
+            if seed_checkpoint.is_some_and(|checkpoint| match_id <= checkpoint) {
+                store.mark_seen(match_id, self.expires_at(now)).await?;
+                continue;
+            }
+            if !store.is_seen(match_id).await? {
+                new_matches.push(guild_match);
+            }
+        }
+        if let Some(checkpoint) = checkpoint {
+            store.mark_seen(checkpoint, self.expires_at(now)).await?;
+        }
+
+        new_matches.sort_by_key(|guild_match| (guild_match.end_date_time, guild_match.id));
+        Ok(new_matches)
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use chrono::{DateTime, Duration, Utc};
+    use crate::provider::stratz;
+    use crate::publisher::fixtures::ended_match;
+    use crate::store::{memory::MemoryStore, state::StateStore};
+    use super::Dedup;
+
+    fn guild_match(match_id: i64, end: DateTime<Utc>) -> stratz::api::Match {
+        ended_match(match_id, end.timestamp())
+    }
+
+    fn match_ids(guild_matches: &[stratz::api::Match]) -> Vec<i64> {
+        guild_matches.iter().map(|guild_match| guild_match.id.unwrap()).collect()
+    }
+
+    fn dedup() -> Dedup {
+        Dedup { retention: Duration::days(7) }
+    }
+
+    #[tokio::test]
+    async fn test_out_of_order_arrival() {
+        let store = MemoryStore::default();
+        let now = Utc::now();
+
+        // A short game with a larger id ends first and is published
+        let first_run = vec![guild_match(101, now - Duration::minutes(50))];
+        let new_matches = dedup().new_matches(&store, None, first_run, now).await.unwrap();
+        assert_eq!(match_ids(&new_matches), vec![101]);
+        store.mark_seen(101, dedup().expires_at(now)).await.unwrap();
+
+        // A long game with a smaller id ends later and is still published
+        let second_run = vec![guild_match(101, now - Duration::minutes(50)), guild_match(100, now - Duration::minutes(5))];
+        let new_matches = dedup().new_matches(&store, Some(101), second_run, now).await.unwrap();
+        assert_eq!(match_ids(&new_matches), vec![100]);
+    }
+
+    #[tokio::test]
+    async fn test_sorted_by_end() {
+        let store = MemoryStore::default();
+        let now = Utc::now();
+        let guild_matches = vec![
+            guild_match(103, now - Duration::minutes(30)),
+            guild_match(100, now - Duration::minutes(10)),
+            guild_match(102, now - Duration::minutes(60))
+        ];
+
+        let new_matches = dedup().new_matches(&store, None, guild_matches, now).await.unwrap();
+        assert_eq!(match_ids(&new_matches), vec![102, 103, 100]);
+    }
+
+    #[tokio::test]
+    async fn test_expired_matches_are_ignored() {
+        let store = MemoryStore::default();
+        let now = Utc::now();
+
+        let guild_matches = vec![guild_match(100, now - Duration::days(8))];
+        let new_matches = dedup().new_matches(&store, None, guild_matches, now).await.unwrap();
+        assert!(new_matches.is_empty());
+
+        // An id whose seen marker expired is not published again either
+        store.mark_seen(101, now - Duration::seconds(1)).await.unwrap();
+        assert!(!store.is_seen(101).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_seed_from_checkpoint() {
+        let store = MemoryStore::default();
+        let now = Utc::now();
+        let guild_matches = vec![
+            guild_match(100, now - Duration::minutes(90)),
+            guild_match(101, now - Duration::minutes(60)),
+            guild_match(102, now - Duration::minutes(30))
+        ];
+
+        let new_matches = dedup().new_matches(&store, Some(101), guild_matches, now).await.unwrap();
+        assert_eq!(match_ids(&new_matches), vec![102]);
+        assert!(store.is_seen(100).await.unwrap());
+        assert!(store.is_seen(101).await.unwrap());
+        assert!(!store.is_seen(102).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_refresh_checkpoint() {
+        let store = MemoryStore::default();
+        let now = Utc::now();
+        store.mark_seen(101, now + Duration::minutes(1)).await.unwrap();
+
+        let guild_matches = vec![guild_match(100, now - Duration::minutes(5))];
+        let new_matches = dedup().new_matches(&store, Some(101), guild_matches, now).await.unwrap();
+        assert_eq!(match_ids(&new_matches), vec![100]);
+        let expires_at = store.with_state(|state| state.seen.get(&101).copied()).unwrap();
+        assert_eq!(expires_at, Some(dedup().expires_at(now).timestamp()));
+    }
+
+}
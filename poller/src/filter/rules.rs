@@ -0,0 +1,320 @@
+use std::collections::HashSet;
+use std::fmt;
+use lambda_runtime::Error;
+use crate::{config::FiltersConfig, provider::stratz};
+use crate::publisher::publisher::{MatchResult, get_match_result, is_guild_member};
+use crate::publisher::utils::{transform_match_result, transform_lobby_type, transform_game_mode};
+
+const DEFAULT_MIN_GUILD_MEMBERS: usize = 1;
+
+/// Leaver statuses meaning the player left the match for good
+const ABANDONED_LEAVER_STATUSES: [stratz::api::LeaverStatus; 4] = [
+    stratz::api::LeaverStatus::DISCONNECTED_TOO_LONG,
+    stratz::api::LeaverStatus::ABANDONED,
+    stratz::api::LeaverStatus::AFK,
+    stratz::api::LeaverStatus::NEVER_CONNECTED_TOO_LONG
+];
+
+/// Enum of the rules a match has to pass to be published
+#[derive(Debug)]
+pub enum Rule {
+    LobbyTypes(Vec<stratz::api::LobbyType>),
+    GameModes(Vec<stratz::api::GameMode>),
+    MinDurationSeconds(i64),
+    MinGuildMembers(usize),
+    ExcludeAbandoned,
+    MatchResults(Vec<MatchResult>)
+}
+
+/// Struct explaining why a match is not published
+#[derive(Debug, PartialEq, Eq)]
+pub struct SkipReason(pub String);
+
+impl fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Rule {
+
+    /// Check `guild_match` against the rule, returning the reason to skip it when the rule is not satisfied
+    ///
+    /// # Arguments
+    ///
+    /// * `guild_match` - The match fetched from Stratz
+    /// * `guild_members` - The steam account ids of guild members
+    pub fn check(&self, guild_match: &stratz::api::Match, guild_members: &HashSet<i64>) -> Option<SkipReason> {
+        match self {
+            Rule::LobbyTypes(lobby_types) => {
+                let lobby_type = guild_match.lobby_type.as_ref()?;
+                if lobby_types.contains(lobby_type) {
+                    return None;
+                }
+                Some(SkipReason(format!("lobby type {} is not published", transform_lobby_type(lobby_type))))
+            },
+            Rule::GameModes(game_modes) => {
+                let game_mode = guild_match.game_mode.as_ref()?;
+                if game_modes.contains(game_mode) {
+                    return None;
+                }
+                Some(SkipReason(format!("game mode {} is not published", transform_game_mode(game_mode))))
+            },
+            Rule::MinDurationSeconds(min_duration_seconds) => {
+                let duration_seconds = guild_match.duration_seconds.unwrap_or_default();
+                if duration_seconds >= *min_duration_seconds {
+                    return None;
+                }
+                Some(SkipReason(format!(
+                    "duration of {}s is shorter than {}s", duration_seconds, min_duration_seconds
+                )))
+            },
+            Rule::MinGuildMembers(min_guild_members) => {
+                let guild_members_count = guild_match.players.as_ref()
+                    .map(|players| players.iter().flatten().filter(|player| is_guild_member(player, guild_members)).count())
+                    .unwrap_or_default();
+                if guild_members_count >= *min_guild_members {
+                    return None;
+                }
+                Some(SkipReason(format!(
+                    "{} guild members played, at least {} required", guild_members_count, min_guild_members
+                )))
+            },
+            Rule::ExcludeAbandoned => {
+                let players = guild_match.players.as_ref()?;
+                let is_abandoned = players.iter().flatten().any(|player| {
+                    player.leaver_status.as_ref()
+                        .map(|leaver_status| ABANDONED_LEAVER_STATUSES.contains(leaver_status))
+                        .unwrap_or(false)
+                });
+                if !is_abandoned {
+                    return None;
+                }
+                Some(SkipReason("match was abandoned by a leaver".to_string()))
+            },
+            Rule::MatchResults(match_results) => {
+                let players = guild_match.players.as_ref()?;
+                let match_result = get_match_result(players, guild_members).ok()?;
+                if match_results.contains(&match_result) {
+                    return None;
+                }
+                Some(SkipReason(format!("result {} is not published", transform_match_result(&match_result))))
+            }
+        }
+    }
+
+}
+
+/// Rule engine deciding which matches get published
+#[derive(Debug)]
+pub struct MatchFilter {
+    pub rules: Vec<Rule>
+}
+
+impl MatchFilter {
+
+    /// Build the rules from the `filters` settings. Every rule is optional, except the minimum number of guild members
+    /// which defaults to 1.
+    pub fn from_config(filters: &FiltersConfig) -> Result<MatchFilter, Error> {
+        let mut rules = Vec::new();
+
+        if let Some(values) = filters.lobby_types.value() {
+            let lobby_types = parse_list(values, "FILTER_LOBBY_TYPES", parse_enum::<stratz::api::LobbyType>)?;
+            rules.push(Rule::LobbyTypes(lobby_types));
+        }
+
+        if let Some(values) = filters.game_modes.value() {
+            let game_modes = parse_list(values, "FILTER_GAME_MODES", parse_enum::<stratz::api::GameMode>)?;
+            rules.push(Rule::GameModes(game_modes));
+        }
+
+        if let Some(min_duration_seconds) = filters.min_duration_seconds.value() {
+            rules.push(Rule::MinDurationSeconds(*min_duration_seconds));
+        }
+
+        let min_guild_members = filters.min_guild_members.value().copied().unwrap_or(DEFAULT_MIN_GUILD_MEMBERS);
+        rules.push(Rule::MinGuildMembers(min_guild_members));
+
+        if filters.exclude_abandoned.value() == Some(&true) {
+            rules.push(Rule::ExcludeAbandoned);
+        }
+
+        if let Some(values) = filters.match_results.value() {
+            let match_results = parse_list(values, "FILTER_MATCH_RESULTS", parse_match_result)?;
+            rules.push(Rule::MatchResults(match_results));
+        }
+
+        Ok(MatchFilter { rules })
+    }
+
+    /// Evaluate every rule against `guild_match`, returning the reason of the first rule not satisfied
+    ///
+    /// # Arguments
+    ///
+    /// * `guild_match` - The match fetched from Stratz
+    /// * `guild_members` - The steam account ids of guild members
+    pub fn skip_reason(&self, guild_match: &stratz::api::Match, guild_members: &HashSet<i64>) -> Option<SkipReason> {
+        self.rules.iter().find_map(|rule| rule.check(guild_match, guild_members))
+    }
+
+}
+
+/// Parse a list of values
+///
+/// # Arguments
+///
+/// * `values` - The values of the list
+/// * `name` - The name of the environmental variable, used in error messages
+/// * `parse` - The parser of a single value
+fn parse_list<T>(values: &[String], name: &str, parse: fn(&str) -> Option<T>) -> Result<Vec<T>, Error> {
+    values.iter()
+        .map(|item| item.trim())
+        .filter(|item| !item.is_empty())
+        .map(|item| parse(item).ok_or_else(|| format!("Invalid {}: {}", name, item).into()))
+        .collect()
+}
+
+/// Parse a Stratz enum from its GraphQL name, such as `RANKED` or `ALL_PICK_RANKED`
+fn parse_enum<T: serde::de::DeserializeOwned + fmt::Debug>(value: &str) -> Option<T> {
+    let parsed = serde_json::from_value::<T>(serde_json::Value::String(value.to_uppercase())).ok()?;
+
+    // Unknown names are deserialized into the `Other` variant by graphql_client
+    match format!("{:?}", parsed).starts_with("Other(") {
+        true => None,
+        false => Some(parsed)
+    }
+}
+
+/// Parse a MatchResult from its published name, such as `victory` or `clash`
+fn parse_match_result(value: &str) -> Option<MatchResult> {
+    match value.to_lowercase().as_str() {
+        "victory" => Some(MatchResult::Victory),
+        "defeat" => Some(MatchResult::Defeat),
+        "clash" => Some(MatchResult::Both),
+        "cancelled" => Some(MatchResult::None),
+        _ => None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::collections::HashSet;
+    use serde_json::json;
+    use crate::provider::stratz;
+    use crate::publisher::{fixtures, publisher::MatchResult};
+    use super::{Rule, MatchFilter, SkipReason, parse_enum, parse_match_result};
+
+    fn guild_match(lobby_type: &str, game_mode: &str, duration_seconds: i64, players: serde_json::Value) -> stratz::api::Match {
+        let mut guild_match = fixtures::guild_match(players);
+        guild_match.lobby_type = serde_json::from_value(json!(lobby_type)).ok();
+        guild_match.game_mode = serde_json::from_value(json!(game_mode)).ok();
+        guild_match.duration_seconds = Some(duration_seconds);
+        guild_match
+    }
+
+    fn player(is_victory: bool, leaver_status: &str) -> serde_json::Value {
+        json!({ "isVictory": is_victory, "isRadiant": true, "leaverStatus": leaver_status })
+    }
+
+    fn ranked_win() -> stratz::api::Match {
+        guild_match("RANKED", "ALL_PICK_RANKED", 2400, json!([player(true, "NONE"), player(true, "NONE")]))
+    }
+
+    #[test]
+    fn test_lobby_types() {
+        let rule = Rule::LobbyTypes(vec![stratz::api::LobbyType::RANKED]);
+        assert_eq!(rule.check(&ranked_win(), &HashSet::new()), None);
+
+        let bots = guild_match("COOP_VS_BOTS", "ALL_PICK", 2400, json!([player(true, "NONE")]));
+        assert_eq!(rule.check(&bots, &HashSet::new()), Some(SkipReason("lobby type Bots is not published".to_string())));
+    }
+
+    #[test]
+    fn test_game_modes() {
+        let rule = Rule::GameModes(vec![stratz::api::GameMode::ALL_PICK_RANKED]);
+        assert_eq!(rule.check(&ranked_win(), &HashSet::new()), None);
+
+        let turbo = guild_match("UNRANKED", "TURBO", 1200, json!([player(true, "NONE")]));
+        assert_eq!(rule.check(&turbo, &HashSet::new()), Some(SkipReason("game mode Turbo is not published".to_string())));
+    }
+
+    #[test]
+    fn test_min_duration_seconds() {
+        let rule = Rule::MinDurationSeconds(600);
+        assert_eq!(rule.check(&ranked_win(), &HashSet::new()), None);
+
+        let short = guild_match("RANKED", "ALL_PICK_RANKED", 180, json!([player(false, "NONE")]));
+        assert_eq!(rule.check(&short, &HashSet::new()), Some(SkipReason("duration of 180s is shorter than 600s".to_string())));
+    }
+
+    #[test]
+    fn test_min_guild_members() {
+        let rule = Rule::MinGuildMembers(2);
+        assert_eq!(rule.check(&ranked_win(), &HashSet::new()), None);
+
+        let solo = guild_match("RANKED", "ALL_PICK_RANKED", 2400, json!([player(true, "NONE")]));
+        assert_eq!(rule.check(&solo, &HashSet::new()), Some(SkipReason("1 guild members played, at least 2 required".to_string())));
+    }
+
+    #[test]
+    fn test_min_guild_members_ignores_randoms() {
+        let rule = Rule::MinGuildMembers(2);
+        let players = json!([
+            { "isVictory": true, "isRadiant": true, "steamAccount": { "id": 1, "isAnonymous": false } },
+            { "isVictory": true, "isRadiant": true, "steamAccount": { "id": 2, "isAnonymous": false } }
+        ]);
+        let guild_match = guild_match("RANKED", "ALL_PICK_RANKED", 2400, players);
+        assert_eq!(rule.check(&guild_match, &HashSet::from([1, 2])), None);
+        assert_eq!(
+            rule.check(&guild_match, &HashSet::from([1])),
+            Some(SkipReason("1 guild members played, at least 2 required".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_exclude_abandoned() {
+        let rule = Rule::ExcludeAbandoned;
+        let disconnected = guild_match("RANKED", "ALL_PICK_RANKED", 2400, json!([player(true, "DISCONNECTED")]));
+        assert_eq!(rule.check(&disconnected, &HashSet::new()), None);
+
+        let abandoned = guild_match("RANKED", "ALL_PICK_RANKED", 900, json!([player(false, "ABANDONED")]));
+        assert_eq!(rule.check(&abandoned, &HashSet::new()), Some(SkipReason("match was abandoned by a leaver".to_string())));
+    }
+
+    #[test]
+    fn test_match_results() {
+        let rule = Rule::MatchResults(vec![MatchResult::Victory]);
+        assert_eq!(rule.check(&ranked_win(), &HashSet::new()), None);
+
+        let defeat = guild_match("RANKED", "ALL_PICK_RANKED", 2400, json!([player(false, "NONE")]));
+        assert_eq!(rule.check(&defeat, &HashSet::new()), Some(SkipReason("result Defeat is not published".to_string())));
+    }
+
+    #[test]
+    fn test_skip_reason_reports_first_failing_rule() {
+        let match_filter = MatchFilter {
+            rules: vec![Rule::MinDurationSeconds(600), Rule::MinGuildMembers(3)]
+        };
+        assert_eq!(
+            match_filter.skip_reason(&ranked_win(), &HashSet::new()),
+            Some(SkipReason("2 guild members played, at least 3 required".to_string()))
+        );
+        assert_eq!(MatchFilter { rules: Vec::new() }.skip_reason(&ranked_win(), &HashSet::new()), None);
+    }
+
+    #[test]
+    fn test_parse_enum() {
+        assert_eq!(parse_enum::<stratz::api::LobbyType>("ranked"), Some(stratz::api::LobbyType::RANKED));
+        assert_eq!(parse_enum::<stratz::api::GameMode>("TURBO"), Some(stratz::api::GameMode::TURBO));
+        assert_eq!(parse_enum::<stratz::api::LobbyType>("NOT_A_LOBBY"), None);
+    }
+
+    #[test]
+    fn test_parse_match_result() {
+        assert_eq!(parse_match_result("Victory"), Some(MatchResult::Victory));
+        assert_eq!(parse_match_result("clash"), Some(MatchResult::Both));
+        assert_eq!(parse_match_result("draw"), None);
+    }
+
+}
@@ -0,0 +1,19 @@
+//! The poller of a Dota2 guild, shared by the `bootstrap` binary run by AWS Lambda or as a daemon, and the
+//! `poller-cli` binary of operators
+
+pub mod alert;
+pub mod config;
+pub mod daemon;
+pub mod deadline;
+pub mod error;
+pub mod event;
+pub mod filter;
+pub mod outcome;
+pub mod poller;
+pub mod provider;
+pub mod utils;
+pub mod publisher;
+pub mod report;
+pub mod settings;
+pub mod store;
+pub mod telemetry;
@@ -1,107 +1,107 @@
-mod config;
-mod provider;
-mod utils;
-mod publisher;
-
-use provider::stratz::api::StratzClient;
-use publisher::publisher::Publisher;
-use utils::dynamo::DynamoClient;
+use std::time::Instant;
+use poller::daemon;
+use poller::deadline::Deadline;
+use poller::event::PollerEvent;
+use poller::outcome::{ErrorMode, RunOutcome};
+use poller::poller::Poller;
+use poller::publisher::publisher::SINKS;
+use poller::telemetry::{logger::JsonLogger, metrics};
 use lambda_runtime::LambdaEvent;
 use lambda_runtime::{Error, service_fn};
-use aws_sdk_dynamodb;
 use serde_json::{json, Value};
 
-const TAKE: i64 = 5;
-const GUILD_ID: i64 = 117311;
-const GUILD_TABLE_NAME: &str = "Guilds";
-
-/// The entry point of AWS Lambda Function
+/// The entry point of AWS Lambda Function, or of the daemon with `--daemon` or `RUN_MODE=daemon`
 #[tokio::main]
 async fn main() -> Result<(), Error> {
-    let func = service_fn(handler);
+    JsonLogger::init()?;
+
+    // The poller, with its HTTP client and state store, is shared by every invocation or cycle
+    let poller = Poller::from_env().await?;
+    if is_daemon(&poller) {
+        return daemon::run(&poller, &poller.run.schedule).await;
+    }
+
+    let poller = &poller;
+    let func = service_fn(move |event| handler(poller, event));
     lambda_runtime::run(func).await?;
 
     Ok(())
 }
 
-async fn handler(_event: LambdaEvent<Value>) -> Result<Value, Error> {
-    match process().await {
-        Ok(_) => Ok(json!({"message": format!("Success")})),
-        Err(e) => Ok(json!({"message": format!("Failure: {}", e)}))
-    }
+/// Check whether the poller runs as a daemon, from the `--daemon` flag or the `run.mode` setting
+fn is_daemon(poller: &Poller) -> bool {
+    poller.run.daemon || std::env::args().skip(1).any(|arg| arg == "--daemon")
 }
 
-async fn process() -> Result<(), Error> {
-    // initialize Stratz client as Dota2 data provider
-    let stratz_client = StratzClient {
-        client: reqwest::Client::new()
-    };
-
-    // Fetch matches using Stratz client
-    let response = stratz_client.fetch_matches(GUILD_ID, TAKE).await?;
-
-    // Extract useful information from the matches fetched
-    let data = response.data.unwrap();
-    let guild = data.guild.unwrap();
-    let guild_id = guild.id.unwrap();
-    let guild_name = guild.name.unwrap();
-    let guild_logo = guild.logo.unwrap();
-    let guild_matches = guild.matches.unwrap();
-
-    // Initialize AWS DynamoDB client for matches id validations
-    let dynamo_client = DynamoClient {
-        client: aws_sdk_dynamodb::Client::new(&aws_config::load_from_env().await)
+/// Poll new matches on a scheduled event, or run the action of a manual event: `{"republish": match_id}`,
+/// `{"backfill": {"guild": id, "take": n}}` or `{"report": "weekly"}`
+/// 
+/// A polling, republishing or backfilling run answers with the number of matches seen, published, skipped, quarantined,
+/// failed per sink and deferred to the next run as the deadline of the invocation was near. A run which fails, even on
+/// a single delivery, fails the invocation so that AWS counts the error and retries it, unless `ERROR_MODE` is
+/// `swallow`.
+/// 
+/// The metrics of a polling, republishing or backfilling run are printed in the CloudWatch Embedded Metric Format, a
+/// report not being a cycle. The admins are alerted when a component fails several scheduled runs in a row.
+async fn handler(poller: &Poller, event: LambdaEvent<Value>) -> Result<Value, Error> {
+    let error_mode = &poller.run.error_mode;
+    let request_id = event.context.request_id.clone();
+    let deadline = Deadline::from_lambda(event.context.deadline, poller.run.deadline_margin);
+    let started = Instant::now();
+    let result = match PollerEvent::parse(event.payload) {
+        Ok(PollerEvent::Report(period)) => {
+            let result = poller.report(&period).await;
+            // The responses of Stratz fetching the guild of the report are not the metrics of a cycle, so they are
+            // dropped instead of being printed with the next cycle which runs in the same process
+            metrics::take();
+            result.map(|_| json!({"message": "Success", "report": period}))
+        },
+        Ok(event) => {
+            let result = match event {
+                PollerEvent::Republish(match_id) => poller.republish(&match_id.to_string(), &SINKS).await,
+                PollerEvent::Backfill(backfill) => {
+                    let guild_id = backfill.guild.unwrap_or(poller.guild.id);
+                    let since = backfill.since.unwrap_or(chrono::DateTime::UNIX_EPOCH);
+                    poller.backfill(guild_id, since, backfill.take, &deadline).await
+                },
+                _ => {
+                    let result = poller.process(&deadline).await;
+                    // Only scheduled cycles count toward alerts, manual runs being watched by whoever started them
+                    poller.watch(&result).await;
+                    result
+                }
+            };
+            metrics::record_cycle(started.elapsed(), result.as_ref().ok());
+            let metrics = metrics::take().to_emf(&poller.run.metrics_namespace, poller.guild.id, chrono::Utc::now());
+            println!("{}", metrics);
+            respond(result)
+        },
+        Err(e) => Err(e)
     };
 
-    // Get the latest matches we already processed
-    let current_match_id = get_current_match_id(&dynamo_client).await?;
-    let mut latest_match_id = current_match_id;
-    
-    // Iterate through the fetched matches and check if they are newer compared to the latest matches in
-    // our database.
-    //
-    // If so, we will prepare the data and publish them.
-    // Otherwise, we will continue to next match.
-    for guild_match in guild_matches.into_iter().rev() {
-        let guild_match = guild_match.unwrap();
-        let match_id = guild_match.id.unwrap();
-        if match_id <= current_match_id {
-            continue;
-        } else {
-            // if match_id > current_match_id, then we publish the data and after the publish succeeds, we update 
-            // the latest_match_id
-            Publisher::publish(guild_id, &guild_name, &guild_logo, guild_match).await?;
-            if match_id > latest_match_id {
-                latest_match_id = match_id;
-            }
-        }
+    if let Err(e) = &result {
+        log::error!(request_id = request_id.as_str(); "Run failed: {}", e);
     }
 
-    // Update the latest match id in database to the newest match id we just fetched
-    if latest_match_id > current_match_id {
-        save_new_current_match_id(&dynamo_client, latest_match_id).await?;
+    match (result, error_mode) {
+        (Ok(response), _) => Ok(response),
+        (Err(e), ErrorMode::Swallow) => Ok(json!({"message": format!("Failure: {}", e)})),
+        (Err(e), ErrorMode::Raise) => Err(e)
     }
-
-    Ok(())
 }
 
-/// The wrapper of AWS DynamoDB GetItem operation to get the latest match id we already processed.
-/// 
-/// # Arguments
-/// 
-/// * `client` - AWS DynamoDB client
-async fn get_current_match_id(client: &DynamoClient) -> Result<i64, Error> {
-    let item = client.get_item(GUILD_TABLE_NAME, GUILD_ID).await?;
-    Ok(item.item().unwrap().get("match_id").unwrap().as_n().unwrap().parse::<i64>().unwrap())
+/// Build the response of a run from its outcome, failing when a delivery failed
+fn respond(result: Result<RunOutcome, Error>) -> Result<Value, Error> {
+    match result {
+        Ok(outcome) if outcome.has_failures() => Err(failed_deliveries(&outcome)),
+        Ok(outcome) => Ok(json!({"message": "Success", "outcome": outcome})),
+        Err(e) => Err(e)
+    }
 }
 
-/// The wrapper of AWS DynamoDB PutItem operation to put the latest match id we just process
-/// 
-/// # Arguments
-/// 
-/// * `client` - AWS DynamoDB client
-/// * `match_id` - The latest match id to be put in DynamoDB table
-async fn save_new_current_match_id(client: &DynamoClient, match_id: i64) -> Result<(), Error> {
-    client.put_item(GUILD_TABLE_NAME, GUILD_ID, match_id).await?;
-    Ok(())
+/// Build the error of a run which failed to deliver some matches, carrying the outcome of the run
+fn failed_deliveries(outcome: &RunOutcome) -> Error {
+    let outcome = serde_json::to_string(outcome).unwrap_or_default();
+    format!("Failed deliveries: {}", outcome).into()
 }
+
@@ -0,0 +1,153 @@
+use std::collections::{BTreeMap, BTreeSet};
+use lambda_runtime::Error;
+use serde::Serialize;
+use crate::config::RunConfig;
+
+/// Struct counting what a polling run did with the matches it fetched
+#[derive(Serialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct RunOutcome {
+    /// Matches never seen before
+    pub seen: usize,
+    /// Matches delivered to every sink
+    pub published: usize,
+    /// Matches skipped by the filtering rules or left out by the bootstrap
+    pub skipped: usize,
+    /// Matches put in quarantine because of broken data
+    pub quarantined: usize,
+    /// Matches which failed to be delivered, per sink
+    pub failed: BTreeMap<String, usize>,
+    /// Matches left to the next run as the deadline was near
+    pub deferred: usize,
+    /// Ids of the matches which failed to be delivered to any sink
+    #[serde(skip)]
+    pub failed_match_ids: BTreeSet<String>,
+    /// Ids of the matches left to the next run
+    #[serde(skip)]
+    pub deferred_match_ids: BTreeSet<String>,
+    /// The last error of each sink which failed
+    #[serde(skip)]
+    pub sink_errors: BTreeMap<String, String>,
+    /// The sinks which received a delivery
+    #[serde(skip)]
+    pub delivered_sinks: BTreeSet<String>
+}
+
+impl RunOutcome {
+
+    /// Record that `match_ids` failed to be delivered to `sink`
+    ///
+    /// # Arguments
+    ///
+    /// * `sink` - The name of the destination, such as `kook` or `discord`
+    /// * `match_ids` - The ids of the matches which were not delivered
+    /// * `error` - The error raised by the destination
+    pub fn record_failure(&mut self, sink: &str, match_ids: &[&str], error: &Error) {
+        let ids = match_ids.join(", ");
+        log::error!(sink = sink, match_ids = ids.as_str(); "Failed to deliver matches {} to {}: {}", ids, sink, error);
+        *self.failed.entry(sink.to_string()).or_default() += match_ids.len();
+        self.failed_match_ids.extend(match_ids.iter().map(|match_id| match_id.to_string()));
+        self.sink_errors.insert(sink.to_string(), error.to_string());
+    }
+
+    /// Record that `sink` received a delivery
+    ///
+    /// # Arguments
+    ///
+    /// * `sink` - The name of the destination, such as `kook` or `discord`
+    pub fn record_delivery(&mut self, sink: &str) {
+        self.delivered_sinks.insert(sink.to_string());
+    }
+
+    /// Record that `match_ids` were left to the next run, as the deadline was near
+    ///
+    /// # Arguments
+    ///
+    /// * `match_ids` - The ids of the matches which were not delivered
+    pub fn record_deferred(&mut self, match_ids: &[&str]) {
+        let ids = match_ids.join(", ");
+        log::warn!(match_ids = ids.as_str(); "Deadline near, leaving matches {} to the next run", ids);
+        self.deferred += match_ids.len();
+        self.deferred_match_ids.extend(match_ids.iter().map(|match_id| match_id.to_string()));
+    }
+
+    /// Check whether a match failed to be delivered to any sink
+    pub fn is_failed(&self, match_id: &str) -> bool {
+        self.failed_match_ids.contains(match_id)
+    }
+
+    /// Check whether a match still has to be delivered by a later run, as it failed or was deferred
+    pub fn is_pending(&self, match_id: &str) -> bool {
+        self.is_failed(match_id) || self.deferred_match_ids.contains(match_id)
+    }
+
+    /// Check whether any delivery failed
+    pub fn has_failures(&self) -> bool {
+        !self.failed.is_empty()
+    }
+
+}
+
+/// Enum of the ways the handler reports a failed run
+#[derive(Debug, PartialEq, Eq)]
+pub enum ErrorMode {
+    /// Fail the invocation, so that AWS counts the error and retries
+    Raise,
+    /// Succeed with a failure message, as the poller used to
+    Swallow
+}
+
+impl ErrorMode {
+
+    /// Get the ErrorMode from the `error_mode` setting of the `[run]` section, `raise` or `swallow`
+    /// Otherwise, failed runs fail the invocation
+    pub fn from_config(run: &RunConfig) -> Result<ErrorMode, Error> {
+        match run.error_mode.value() {
+            None => Ok(ErrorMode::Raise),
+            Some(value) => match value.trim().to_lowercase().as_str() {
+                "raise" => Ok(ErrorMode::Raise),
+                "swallow" => Ok(ErrorMode::Swallow),
+                _ => Err(format!("Invalid ERROR_MODE: {}", value).into())
+            }
+        }
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use serde_json::json;
+    use super::RunOutcome;
+
+    #[test]
+    fn test_record_failure() {
+        let mut outcome = RunOutcome { seen: 3, published: 1, ..Default::default() };
+        assert!(!outcome.has_failures());
+
+        outcome.record_failure("kook", &["6789012345", "6789012346"], &"Timed out".into());
+        outcome.record_failure("discord", &["6789012346"], &"Rate limited".into());
+        assert!(outcome.has_failures());
+        assert!(outcome.is_failed("6789012345"));
+        assert!(!outcome.is_failed("6789012347"));
+        assert_eq!(serde_json::to_value(&outcome).unwrap(), json!({
+            "seen": 3,
+            "published": 1,
+            "skipped": 0,
+            "quarantined": 0,
+            "failed": { "discord": 1, "kook": 2 },
+            "deferred": 0
+        }));
+    }
+
+    #[test]
+    fn test_record_deferred() {
+        let mut outcome = RunOutcome::default();
+        outcome.record_deferred(&["6789012345", "6789012346"]);
+        assert_eq!(outcome.deferred, 2);
+        assert!(!outcome.has_failures());
+        assert!(!outcome.is_failed("6789012345"));
+        assert!(outcome.is_pending("6789012345"));
+        assert!(!outcome.is_pending("6789012347"));
+    }
+
+}
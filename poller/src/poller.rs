@@ -0,0 +1,555 @@
+use std::collections::{BTreeMap, HashSet};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use chrono::{DateTime, Utc};
+use lambda_runtime::Error;
+use serde_json::Value;
+use crate::alert::{ComponentHealth, component_results};
+use crate::{config, deadline::Deadline, error::PollerError, outcome::RunOutcome};
+use crate::filter::rules::MatchFilter;
+use crate::provider::stratz::{self, api::StratzClient};
+use crate::publisher::publisher::{Publisher, PublishRecord};
+use crate::report::summary::{GuildReport, ReportPeriod, summarize_members};
+use crate::settings::{GuildSettings, RunSettings, Settings, StorageSettings};
+use crate::store::{dry_run::DryRunStore, state::{StateStore, open}};
+
+const LOOKUP_TAKE: i64 = 50;
+pub const DEFAULT_BACKFILL_TAKE: i64 = 50;
+
+/// Struct running the polling and reports of the guild, keeping its HTTP client and state store across runs so that
+/// connections are reused
+pub struct Poller {
+    pub store: Box<dyn StateStore>,
+    pub stratz_client: StratzClient,
+    pub publisher: Publisher,
+    pub guild: GuildSettings,
+    pub filters: MatchFilter,
+    pub storage: StorageSettings,
+    pub run: RunSettings,
+    /// The health of the components as last seen by this process, used when the state store cannot be read
+    health: Mutex<BTreeMap<String, ComponentHealth>>
+}
+
+impl Poller {
+
+    /// Build the poller from the configuration file of `CONFIG_FILE` and the environment
+    pub async fn from_env() -> Result<Poller, Error> {
+        let config_file = config::config_file().map(PathBuf::from);
+        Poller::from_settings(Settings::load(config_file.as_deref())?).await
+    }
+
+    /// Build the poller from validated settings, opening the state store they choose
+    ///
+    /// # Arguments
+    ///
+    /// * `settings` - The configuration of the poller
+    pub async fn from_settings(settings: Settings) -> Result<Poller, Error> {
+        let Settings { guild, provider, sinks, players, filters, templates, storage, run } = settings;
+        let client = reqwest::Client::new();
+
+        Ok(Poller {
+            store: open(&storage).await?,
+            stratz_client: StratzClient { client: client.clone(), jwt: provider.stratz_jwt },
+            publisher: Publisher { client, sinks, players, templates, dry_run: run.dry_run.clone() },
+            guild,
+            filters,
+            storage,
+            run,
+            health: Mutex::new(BTreeMap::new())
+        })
+    }
+
+    /// Poll new matches while holding the lease of the guild, so that overlapping runs do not publish the same matches
+    /// twice. A run which cannot take the lease leaves the guild to the run holding it, and a run holding it stops
+    /// taking new matches before the lease expires.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `deadline` - The time the run stops taking new matches, leaving them to the next run
+    pub async fn process(&self, deadline: &Deadline) -> Result<RunOutcome, Error> {
+        let dry_run_store = DryRunStore::new(self.store.as_ref());
+        let store = self.run_store(&dry_run_store);
+        let Some((lease_owner, lease_deadline)) = self.acquire_lease(store, self.guild.id).await? else {
+            return Ok(RunOutcome::default());
+        };
+
+        let result = self.poll(store, &deadline.at_most(lease_deadline)).await;
+        self.release_lease(store, self.guild.id, &lease_owner).await;
+
+        result
+    }
+
+    /// Publish the matches of a guild which ended since `since` and were never seen, while holding the lease of the
+    /// guild. The checkpoint is left as is.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `guild_id` - The id of the guild, usually the guild of the configuration
+    /// * `since` - The earliest end of the matches to be published
+    /// * `take` - The number of latest matches of the guild fetched from Stratz
+    /// * `deadline` - The time the run stops taking new matches, leaving them to the next run
+    pub async fn backfill(
+        &self,
+        guild_id: i64,
+        since: DateTime<Utc>,
+        take: i64,
+        deadline: &Deadline
+    ) -> Result<RunOutcome, Error> {
+        let dry_run_store = DryRunStore::new(self.store.as_ref());
+        let store = self.run_store(&dry_run_store);
+        let Some((lease_owner, lease_deadline)) = self.acquire_lease(store, guild_id).await? else {
+            return Ok(RunOutcome::default());
+        };
+
+        let result = self.poll_since(store, guild_id, since, take, &deadline.at_most(lease_deadline)).await;
+        self.release_lease(store, guild_id, &lease_owner).await;
+
+        result
+    }
+
+    /// Publish a match of the match history again to `sinks`, whatever the delivery ledger says
+    /// 
+    /// # Arguments
+    /// 
+    /// * `match_id` - The id of the match
+    /// * `sinks` - The names of the destinations, such as `kook` or `discord`
+    pub async fn republish(&self, match_id: &str, sinks: &[&str]) -> Result<RunOutcome, Error> {
+        let publish_record = self.store.get_match(match_id).await?
+            .ok_or_else(|| format!("Match {} not found in the match history", match_id))?;
+
+        let dry_run_store = DryRunStore::new(self.store.as_ref());
+        let mut outcome = RunOutcome::default();
+        self.publisher.republish(self.run_store(&dry_run_store), &publish_record, sinks, &mut outcome).await?;
+        if !outcome.is_failed(match_id) {
+            outcome.published = 1;
+        }
+
+        Ok(outcome)
+    }
+
+    /// Get the message every sink would receive for a match, without sending anything or touching the stored state.
+    /// The match is read from the match history, or fetched from Stratz among the latest matches of the guild.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `match_id` - The id of the match
+    pub async fn preview(&self, match_id: &str) -> Result<Vec<(&'static str, Value)>, Error> {
+        let publish_record = match self.store.get_match(match_id).await? {
+            Some(publish_record) => publish_record,
+            None => {
+                let guild = self.stratz_client.fetch_guild(self.guild.id, LOOKUP_TAKE).await?;
+                let (guild_id, guild_name, guild_logo) = guild_profile(&guild, self.guild.id)?;
+                let guild_members = guild_members(&guild, self.guild.id)?;
+                let guild_match = guild.matches.unwrap_or_default().into_iter()
+                    .flatten()
+                    .find(|guild_match| guild_match.id.map(|id| id.to_string()).as_deref() == Some(match_id))
+                    .ok_or_else(|| {
+                        format!("Match {} not found among the latest matches of guild {}", match_id, self.guild.id)
+                    })?;
+                self.publisher.extract_record(&guild_members, guild_id, &guild_name, &guild_logo, guild_match)?
+            }
+        };
+
+        self.publisher.preview(&publish_record)
+    }
+
+    /// Get the checkpoint of the guild, the latest match id processed, if any
+    pub async fn checkpoint(&self) -> Result<Option<i64>, Error> {
+        self.store.get_checkpoint(self.guild.id).await
+    }
+
+    /// Move the checkpoint of the guild to `match_id`, e.g. to bootstrap a guild without running the bootstrap policy.
+    /// Matches are deduplicated against the seen match ids, not the checkpoint, so this neither skips nor replays
+    /// matches: `republish` and `backfill` replay them. The new checkpoint is marked as seen, so it is not taken for the
+    /// checkpoint of an older deployment.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `match_id` - The latest match id to be considered processed
+    pub async fn set_checkpoint(&self, match_id: i64) -> Result<(), Error> {
+        let current_match_id = self.store.get_checkpoint(self.guild.id).await?;
+        save_new_current_match_id(self.store.as_ref(), self.guild.id, current_match_id, match_id).await?;
+        self.store.mark_seen(match_id, self.storage.dedup.expires_at(chrono::Utc::now())).await
+    }
+
+    /// Track the health of every component a polling cycle used and alert the admins once a component failed
+    /// `ALERT_THRESHOLD` cycles in a row, then once more when it recovers. The health is kept in the state store so
+    /// that it survives Lambda invocations, and in memory when the state store is the component failing. Nothing is
+    /// tracked in a dry run.
+    ///
+    /// # Arguments
+    ///
+    /// * `result` - The result of the polling cycle
+    pub async fn watch(&self, result: &Result<RunOutcome, Error>) {
+        if self.run.dry_run.is_some() {
+            return;
+        }
+
+        for (component, error) in component_results(result) {
+            let cached = self.health.lock().ok().and_then(|health| health.get(&component).copied());
+            let health = match self.store.get_health(&component).await {
+                Ok(health) => health,
+                Err(e) => {
+                    log::warn!(component = component.as_str(); "Failed to read health of {}: {}", component, e);
+                    cached.unwrap_or_default()
+                }
+            };
+
+            let (health, alert) = health.next(&component, error.as_deref(), self.run.alert_threshold);
+            if let Ok(mut cache) = self.health.lock() {
+                cache.insert(component.clone(), health);
+            }
+            if let Err(e) = self.store.save_health(&component, &health).await {
+                log::warn!(component = component.as_str(); "Failed to save health of {}: {}", component, e);
+            }
+            if let Some(alert) = alert {
+                log::warn!(component = component.as_str(); "{}", alert.text());
+                self.publisher.publish_alert(&alert).await;
+            }
+        }
+    }
+
+    /// Get the state store a run writes to: the state store itself, or `dry_run_store` keeping the writes of a dry
+    /// run in memory
+    fn run_store<'a>(&'a self, dry_run_store: &'a DryRunStore<'a>) -> &'a dyn StateStore {
+        match self.run.dry_run {
+            Some(_) => dry_run_store,
+            None => self.store.as_ref()
+        }
+    }
+
+    /// Poll new matches, process them and publish them until `deadline`, keeping the state in `store`
+    async fn poll(&self, store: &dyn StateStore, deadline: &Deadline) -> Result<RunOutcome, Error> {
+
+        // Fetch matches using Stratz client
+        let guild = self.stratz_client.fetch_guild(self.guild.id, self.guild.take).await?;
+
+        // Extract useful information from the matches fetched
+        let (guild_id, guild_name, guild_logo) = guild_profile(&guild, self.guild.id)?;
+        let guild_members = guild_members(&guild, self.guild.id)?;
+        let guild_matches = guild.matches.unwrap_or_default();
+
+        // Get the latest matches we already processed
+        let current_match_id = store.get_checkpoint(self.guild.id).await?;
+        let mut seen_match_ids = Vec::new();
+        let mut outcome = RunOutcome::default();
+        let mut guild_matches: Vec<_> = guild_matches.into_iter().flatten().collect();
+
+        // Without a checkpoint, this is the first run of the guild: the matches left out by the bootstrap policy are
+        // only recorded as processed
+        if current_match_id.is_none() {
+            let bootstrap = &self.storage.bootstrap;
+            log::info!(
+                guild_id = self.guild.id; "No checkpoint found for guild {}, bootstrapping with {:?}", self.guild.id, bootstrap
+            );
+            let (skipped_matches, backfill_matches) = bootstrap.split(guild_matches);
+            for match_id in skipped_matches.into_iter().filter_map(|guild_match| guild_match.id) {
+                outcome.skipped += 1;
+                seen_match_ids.push(match_id);
+            }
+            guild_matches = backfill_matches;
+        }
+
+        // Keep the fetched matches whose ids were never seen, oldest first. Match ids do not arrive in order, so a
+        // match with a smaller id than the latest match processed can still be new.
+        let now = chrono::Utc::now();
+        let new_matches = self.storage.dedup.new_matches(store, current_match_id, guild_matches, now).await?;
+
+        let guild_profile = (guild_id, guild_name.as_str(), guild_logo.as_str());
+        self.publish_matches(
+            store, guild_profile, &guild_members, new_matches, deadline, &mut seen_match_ids, &mut outcome
+        ).await?;
+
+        // Update the latest match id in database to the newest match id we just processed. The matches still pending
+        // are not marked as seen and are left out, as an unseen checkpoint would be taken for the checkpoint of an older
+        // deployment and mark the matches up to it as seen
+        let latest_match_id = seen_match_ids.into_iter()
+            .filter(|match_id| !outcome.is_pending(&match_id.to_string()))
+            .max();
+        let is_newer = |latest_match_id: &i64| Some(*latest_match_id) > current_match_id;
+        if let Some(latest_match_id) = latest_match_id.filter(is_newer) {
+            save_new_current_match_id(store, self.guild.id, current_match_id, latest_match_id).await?;
+        }
+
+        Ok(outcome)
+    }
+
+    /// Publish the matches of guild `requested_id` ending since `since` which were never seen, oldest first, until
+    /// `deadline`, keeping the state in `store`
+    async fn poll_since(
+        &self,
+        store: &dyn StateStore,
+        requested_id: i64,
+        since: DateTime<Utc>,
+        take: i64,
+        deadline: &Deadline
+    ) -> Result<RunOutcome, Error> {
+        let guild = self.stratz_client.fetch_guild(requested_id, take).await?;
+        let (guild_id, guild_name, guild_logo) = guild_profile(&guild, requested_id)?;
+        let guild_members = guild_members(&guild, requested_id)?;
+
+        let mut new_matches = Vec::new();
+        for guild_match in guild.matches.unwrap_or_default().into_iter().flatten() {
+            let (Some(match_id), Some(end)) = (guild_match.id, guild_match.end_date_time) else { continue };
+            if end >= since.timestamp() && !store.is_seen(match_id).await? {
+                new_matches.push(guild_match);
+            }
+        }
+        new_matches.sort_by_key(|guild_match| (guild_match.end_date_time, guild_match.id));
+        log::info!(guild_id = guild_id; "Backfilling {} matches of guild {} since {}", new_matches.len(), guild_id, since);
+
+        let mut outcome = RunOutcome::default();
+        let guild_profile = (guild_id, guild_name.as_str(), guild_logo.as_str());
+        self.publish_matches(
+            store, guild_profile, &guild_members, new_matches, deadline, &mut Vec::new(), &mut outcome
+        ).await?;
+
+        Ok(outcome)
+    }
+
+    /// Prepare the data of each match unless a filtering rule skips it, publish the prepared matches and remember the
+    /// matches processed. After the record is built or the match is skipped, the match counts as seen. Once the
+    /// `deadline` is reached, the matches left are deferred to the next run.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `store` - The state store of the run
+    /// * `guild_profile` - The id, name and logo of the guild
+    /// * `guild_members` - The steam account ids of guild members
+    /// * `new_matches` - The matches never seen before, oldest first
+    /// * `deadline` - The time the run stops taking new matches
+    /// * `seen_match_ids` - The ids of the matches seen by the run, extended with the ids of `new_matches`
+    /// * `outcome` - The outcome of the run
+    #[allow(clippy::too_many_arguments)]
+    async fn publish_matches(
+        &self,
+        store: &dyn StateStore,
+        guild_profile: (i64, &str, &str),
+        guild_members: &HashSet<i64>,
+        new_matches: Vec<stratz::api::Match>,
+        deadline: &Deadline,
+        seen_match_ids: &mut Vec<i64>,
+        outcome: &mut RunOutcome
+    ) -> Result<(), Error> {
+        let (guild_id, guild_name, guild_logo) = guild_profile;
+
+        let now = chrono::Utc::now();
+        let mut publish_records = Vec::new();
+
+        for guild_match in new_matches {
+            let Some(match_id) = guild_match.id else { continue };
+            if deadline.is_reached(chrono::Utc::now()) {
+                outcome.record_deferred(&[&match_id.to_string()]);
+                continue;
+            }
+            match self.filters.skip_reason(&guild_match, guild_members) {
+                Some(reason) => {
+                    log::info!(guild_id = guild_id, match_id = match_id; "Skipping match {}: {}", match_id, reason);
+                    outcome.skipped += 1;
+                },
+                None => {
+                    let publish_record = self.publisher.build_record(
+                        store, guild_members, guild_id, guild_name, guild_logo, guild_match
+                    ).await;
+                    match publish_record {
+                        Ok(publish_record) => publish_records.push(publish_record),
+                        // A match with broken data must not block the matches after it
+                        Err(e) if PollerError::is_match_failure(&e) => {
+                            log::warn!(guild_id = guild_id, match_id = match_id; "Quarantining match {}: {}", match_id, e);
+                            store.quarantine_match(match_id, &e.to_string(), now).await?;
+                            outcome.quarantined += 1;
+                        },
+                        Err(e) => return Err(e)
+                    }
+                }
+            }
+            seen_match_ids.push(match_id);
+        }
+
+        // Publish the prepared matches, batched into a digest when there are many of them
+        outcome.seen = seen_match_ids.len();
+        self.publisher.publish_all(store, &publish_records, deadline, outcome).await?;
+
+        // Keep the published matches for reports and later queries. A match which failed to be delivered somewhere or
+        // was deferred is not seen yet, so the next run retries the sinks the delivery ledger says it is missing from
+        let delivered_records: Vec<&PublishRecord> = publish_records.iter()
+            .filter(|publish_record| !outcome.is_pending(&publish_record.match_id))
+            .collect();
+        for publish_record in delivered_records.iter() {
+            store.record_match(publish_record).await?;
+        }
+        outcome.published = delivered_records.len();
+
+        // Remember the matches we just processed
+        for match_id in seen_match_ids.iter().filter(|match_id| !outcome.is_pending(&match_id.to_string())) {
+            store.mark_seen(*match_id, self.storage.dedup.expires_at(now)).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Take the lease of guild `guild_id` for this run and get the owner id it was taken with, along with the time the
+    /// run stops taking new matches, leaving the deadline margin before the lease expires
+    /// Otherwise, None when another run holds the lease
+    async fn acquire_lease(
+        &self,
+        store: &dyn StateStore,
+        guild_id: i64
+    ) -> Result<Option<(String, DateTime<Utc>)>, Error> {
+        let lease_owner = lease_owner();
+        let expires_at = chrono::Utc::now() + chrono::Duration::seconds(self.storage.lease_seconds);
+        if !store.acquire_lease(&lease_name(guild_id), &lease_owner, expires_at).await? {
+            log::info!(guild_id = guild_id; "Guild {} is being processed by another run, exiting", guild_id);
+            return Ok(None);
+        }
+
+        Ok(Some((lease_owner, expires_at - self.run.deadline_margin)))
+    }
+
+    /// Release the lease of guild `guild_id` taken by this run. A failure is only logged, so that it does not hide the
+    /// outcome of the run, and the lease left behind expires on its own.
+    async fn release_lease(&self, store: &dyn StateStore, guild_id: i64, lease_owner: &str) {
+        if let Err(e) = store.release_lease(&lease_name(guild_id), lease_owner).await {
+            log::error!(guild_id = guild_id; "Failed to release the lease of guild {}: {}", guild_id, e);
+        }
+    }
+
+    /// Aggregate the published matches of the last complete day or week per guild member and post the summary report,
+    /// on a report event in Lambda or on the schedule of the daemon
+    /// 
+    /// # Arguments
+    /// 
+    /// * `period` - The period of the report, `daily` or `weekly`
+    pub async fn report(&self, period: &str) -> Result<(), Error> {
+        let period = ReportPeriod::parse(period)?;
+        let timezone = self.publisher.templates.report_timezone;
+        let (start, end) = period.bounds(chrono::Utc::now(), timezone)?;
+
+        // The guild is fetched from Stratz for its name and logo, the matches are read from the history
+        let guild = self.stratz_client.fetch_guild(self.guild.id, self.guild.take).await?;
+        let (guild_id, guild_name, guild_logo) = guild_profile(&guild, self.guild.id)?;
+
+        let publish_records = self.store.matches_of_guild(
+            &guild_id.to_string(), start, end - chrono::Duration::seconds(1)
+        ).await?;
+
+        let report = GuildReport {
+            guild_id: guild_id.to_string(),
+            guild_name,
+            guild_logo,
+            period,
+            start,
+            end,
+            timezone,
+            members: summarize_members(&publish_records)
+        };
+        self.publisher.publish_report(&report).await?;
+
+        Ok(())
+    }
+
+}
+
+/// Get the id, name and logo of a guild fetched from Stratz
+/// Otherwise, a provider error naming the missing field
+/// 
+/// # Arguments
+/// 
+/// * `guild` - The guild fetched from Stratz
+/// * `requested_id` - The id of the guild requested from Stratz
+fn guild_profile(guild: &stratz::api::Guild, requested_id: i64) -> Result<(i64, String, String), Error> {
+    let missing = |field: &str| PollerError::Provider(format!("Missing {} of guild {}", field, requested_id));
+    let guild_id = guild.id.ok_or_else(|| missing("id"))?;
+    let guild_name = guild.name.clone().ok_or_else(|| missing("name"))?;
+    let guild_logo = guild.logo.clone().ok_or_else(|| missing("logo"))?;
+
+    Ok((guild_id, guild_name, guild_logo))
+}
+
+/// Get the steam account ids of the members of a guild fetched from Stratz
+/// Otherwise, a provider error when no member is listed, as every player would then be taken for a guild member
+/// 
+/// # Arguments
+/// 
+/// * `guild` - The guild fetched from Stratz
+/// * `requested_id` - The id of the guild requested from Stratz
+fn guild_members(guild: &stratz::api::Guild, requested_id: i64) -> Result<HashSet<i64>, Error> {
+    let guild_members: HashSet<i64> = guild.members.iter()
+        .flatten()
+        .flatten()
+        .filter_map(|member| member.steam_account_id)
+        .collect();
+    if guild_members.is_empty() {
+        return Err(PollerError::Provider(format!("No members listed for guild {}", requested_id)).into());
+    }
+
+    Ok(guild_members)
+}
+
+/// Get the name of the lease of the guild `guild_id`
+fn lease_name(guild_id: i64) -> String {
+    format!("guild#{}", guild_id)
+}
+
+/// Build an owner id for the lease, unique to this run
+fn lease_owner() -> String {
+    let nanos = chrono::Utc::now().timestamp_nanos_opt().unwrap_or_default();
+    format!("{}-{}", std::process::id(), nanos)
+}
+
+/// Save the latest match id we just processed to the state store, unless another run moved the checkpoint since we
+/// read it
+/// 
+/// # Arguments
+/// 
+/// * `store` - The state store keeping checkpoints
+/// * `guild_id` - The id of the guild
+/// * `current_match_id` - The checkpoint read at the start of the run, if any
+/// * `match_id` - The latest match id to be saved
+async fn save_new_current_match_id(
+    store: &dyn StateStore,
+    guild_id: i64,
+    current_match_id: Option<i64>,
+    match_id: i64
+) -> Result<(), Error> {
+    if !store.save_checkpoint(guild_id, current_match_id, match_id).await? {
+        return Err(PollerError::State(format!("Checkpoint of guild {} was changed by another run", guild_id)).into());
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use serde_json::json;
+    use crate::error::PollerError;
+    use crate::provider::stratz;
+    use super::guild_members;
+
+    fn guild(members: serde_json::Value) -> stratz::api::Guild {
+        serde_json::from_value(json!({
+            "id": 117311,
+            "name": "Guild",
+            "logo": "logo",
+            "members": members,
+            "matches": []
+        })).unwrap()
+    }
+
+    #[test]
+    fn test_guild_members() {
+        let members = guild(json!([{ "steamAccountId": 86000 }, { "steamAccountId": null }, null]));
+        assert_eq!(guild_members(&members, 117311).unwrap(), [86000].into());
+    }
+
+    #[test]
+    fn test_guild_members_empty() {
+        for members in [json!([]), json!(null), json!([{ "steamAccountId": null }])] {
+            let error = guild_members(&guild(members), 117311).unwrap_err();
+            let error = error.downcast_ref::<PollerError>().unwrap();
+            assert_eq!(error.to_string(), "Provider error: No members listed for guild 117311");
+        }
+    }
+
+}
@@ -1,5 +1,5 @@
 use lambda_runtime::Error;
-use crate::config;
+use crate::{error::PollerError, telemetry::metrics};
 use graphql_client::GraphQLQuery;
 
 type Short = i16;
@@ -7,22 +7,27 @@ type Long = i64;
 type Byte = u8;
 
 #[derive(GraphQLQuery)]
-#[graphql(schema_path="src/provider/stratz/assets/stratz_schema.gql", query_path="src/provider/stratz/assets/latest_guild_matches.gql", response_derives="Clone,Debug")]
+#[graphql(schema_path="src/provider/stratz/assets/stratz_schema.gql", query_path="src/provider/stratz/assets/latest_guild_matches.gql", response_derives="Clone,Debug,PartialEq")]
 struct MatchesQuery;
 pub type Response = graphql_client::Response<matches_query::ResponseData>;
-pub use matches_query::ResponseData;
 pub use matches_query::Variables as Variable;
 pub use matches_query::LobbyTypeEnum as LobbyType;
 pub use matches_query::GameModeEnumType as GameMode;
-pub use matches_query::MatchesQueryGuild as Guild;
 pub use matches_query::MatchesQueryGuildMatches as Match;
 pub use matches_query::MatchesQueryGuildMatchesPlayers as Player;
-pub use matches_query::MatchesQueryGuildMatchesPlayersHero as Hero;
-pub use matches_query::MatchesQueryGuildMatchesPlayersSteamAccount as Steam;
+pub use matches_query::LeaverStatusEnum as LeaverStatus;
+pub use matches_query::MatchesQueryGuild as Guild;
+#[allow(unused_imports)]
+pub use matches_query::{
+    ResponseData,
+    MatchesQueryGuildMatchesPlayersHero as Hero,
+    MatchesQueryGuildMatchesPlayersSteamAccount as Steam
+};
 
 /// Stratz API Client as Dota data provider
 pub struct StratzClient {
-    pub client: reqwest::Client
+    pub client: reqwest::Client,
+    pub jwt: String
 }
 
 impl StratzClient {
@@ -36,15 +41,39 @@ impl StratzClient {
     pub async fn fetch_matches(&self, guild_id: i64, take: i64) -> Result<Response, Error> {
         let vars = Variable { guild_id, take };
         let body = MatchesQuery::build_query(vars);
-        let response = self.client.post(StratzClient::api_url()).json(&body).send().await?;
-        let data = response.json::<Response>().await?;
+        let response = self.client.post(self.api_url()).json(&body).send().await
+            .map_err(|e| PollerError::Provider(format!("Failed to fetch matches of guild {}: {}", guild_id, e)))?;
+        metrics::record_stratz_status(response.status().as_u16());
+        log::debug!(guild_id = guild_id, status = response.status().as_u16(); "Stratz answered {}", response.status());
+        let data = response.json::<Response>().await
+            .map_err(|e| PollerError::Provider(format!("Invalid matches of guild {}: {}", guild_id, e)))?;
 
         Ok(data)
     }
 
+    /// Fetch a Dota2 guild with its members and latest matches based on guild_id and take
+    /// Otherwise, a provider error when Stratz answers without the guild
+    /// 
+    /// # Arguments
+    /// 
+    /// * `guild_id` - The guild_id we will use to get matches from
+    /// * `take` - The number of matches to be fetched
+    pub async fn fetch_guild(&self, guild_id: i64, take: i64) -> Result<Guild, Error> {
+        let response = self.fetch_matches(guild_id, take).await?;
+        if let Some(errors) = response.errors.filter(|errors| !errors.is_empty()) {
+            let messages: Vec<String> = errors.iter().map(|error| error.message.to_string()).collect();
+            return Err(PollerError::Provider(format!("Stratz failed on guild {}: {}", guild_id, messages.join(", "))).into());
+        }
+        let guild = response.data
+            .and_then(|data| data.guild)
+            .ok_or_else(|| PollerError::Provider(format!("No guild {} returned by Stratz", guild_id)))?;
+
+        Ok(guild)
+    }
+
     /// Get the API URL of Stratz API
-    fn api_url() -> String {
-        format!("https://api.stratz.com/graphql?jwt={}", &config::stratz_jwt())
+    fn api_url(&self) -> String {
+        format!("https://api.stratz.com/graphql?jwt={}", &self.jwt)
     }
 
 }
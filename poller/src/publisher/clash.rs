@@ -0,0 +1,176 @@
+use serde::{Serialize, Deserialize};
+use lambda_runtime::Error;
+use crate::store::state::StateStore;
+use super::publisher::PlayerStats;
+
+const RADIANT: &str = "Radiant";
+const DIRE: &str = "Dire";
+
+/// Struct to contain the head-to-head record between two guild members who played against each other
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct HeadToHead {
+    pub winner: String,
+    pub winner_wins: i64,
+    pub loser: String,
+    pub loser_wins: i64
+}
+
+/// Struct to contain the per-side result of a match where guild members played against each other
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Clash {
+    pub winning_side: String,
+    pub winners: Vec<String>,
+    pub losing_side: String,
+    pub losers: Vec<String>,
+    pub head_to_head: Vec<HeadToHead>
+}
+
+impl Clash {
+
+    /// Split guild members into winners and losers. Returns None unless guild members played on both sides.
+    ///
+    /// # Arguments
+    ///
+    /// * `radiant` - The stats of players on Radiant
+    /// * `dire` - The stats of players on Dire
+    pub fn from_teams(radiant: &[PlayerStats], dire: &[PlayerStats]) -> Option<Clash> {
+        let radiant = guild_members(radiant);
+        let dire = guild_members(dire);
+        if radiant.is_empty() || dire.is_empty() {
+            return None;
+        }
+
+        let did_radiant_win = radiant.iter().any(|player_stats| player_stats.is_victory);
+        let (winning_side, winners, losing_side, losers) = match did_radiant_win {
+            true => (RADIANT, radiant, DIRE, dire),
+            false => (DIRE, dire, RADIANT, radiant)
+        };
+
+        Some(Clash {
+            winning_side: winning_side.to_string(),
+            winners: winners.iter().map(|player_stats| player_stats.name.to_string()).collect(),
+            losing_side: losing_side.to_string(),
+            losers: losers.iter().map(|player_stats| player_stats.name.to_string()).collect(),
+            head_to_head: Vec::new()
+        })
+    }
+
+    /// Get the head-to-head framing of the clash
+    ///
+    /// # Examples
+    ///
+    /// Alice & Carol's Radiant beat Bob's Dire
+    pub fn headline(&self) -> String {
+        format!(
+            "{}'s {} beat {}'s {}",
+            join_names(&self.winners), self.winning_side, join_names(&self.losers), self.losing_side
+        )
+    }
+
+}
+
+/// Get the stats of guild members among `players_stats`
+pub fn guild_members(players_stats: &[PlayerStats]) -> Vec<&PlayerStats> {
+    players_stats.iter().filter(|player_stats| player_stats.is_guild_member).collect()
+}
+
+/// Record a win of every winner over every loser in the head-to-head records and return the updated records
+///
+/// # Arguments
+///
+/// * `store` - The state store keeping head-to-head records
+/// * `winners` - The stats of players on the winning side
+/// * `losers` - The stats of players on the losing side
+/// * `match_id` - The id of the match, counted once per pair
+pub async fn record_head_to_head(
+    store: &dyn StateStore,
+    winners: &[PlayerStats],
+    losers: &[PlayerStats],
+    match_id: i64
+) -> Result<Vec<HeadToHead>, Error> {
+    let mut head_to_head = Vec::new();
+    for winner in guild_members(winners) {
+        for loser in guild_members(losers) {
+            // Anonymous players cannot be told apart between matches
+            let (winner_id, loser_id) = match (winner.steam_account_id, loser.steam_account_id) {
+                (Some(winner_id), Some(loser_id)) => (winner_id, loser_id),
+                _ => continue
+            };
+
+            let (winner_wins, loser_wins) = store.increment_head_to_head(winner_id, loser_id, match_id).await?;
+            head_to_head.push(HeadToHead {
+                winner: winner.name.to_string(),
+                winner_wins,
+                loser: loser.name.to_string(),
+                loser_wins
+            });
+        }
+    }
+
+    Ok(head_to_head)
+}
+
+/// Get the id of the head-to-head record of two players, the same whichever order they are given in
+pub fn pair_id(steam_account_id: i64, other_steam_account_id: i64) -> String {
+    let (low, high) = match steam_account_id < other_steam_account_id {
+        true => (steam_account_id, other_steam_account_id),
+        false => (other_steam_account_id, steam_account_id)
+    };
+
+    format!("{}#{}", low, high)
+}
+
+/// Join names as `Alice`, `Alice & Bob` or `Alice, Bob & Carol`
+fn join_names(names: &[String]) -> String {
+    match names.split_last() {
+        None => String::new(),
+        Some((last, [])) => last.to_string(),
+        Some((last, rest)) => format!("{} & {}", rest.join(", "), last)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::publisher::fixtures::player_stats;
+    use super::{Clash, join_names, pair_id};
+
+    #[test]
+    fn test_from_teams() {
+        let mut alice = player_stats(86, "Rubick", "Alice", (6, 5, 16), 11230);
+        let mut carol = player_stats(5, "Crystal Maiden", "Carol", (1, 7, 12), 7000);
+        let bob = player_stats(11, "Shadow Fiend", "Bob", (16, 4, 8), 24510);
+        alice.is_victory = false;
+        carol.is_victory = false;
+
+        let clash = Clash::from_teams(&[alice, carol], &[bob]).unwrap();
+        assert_eq!(clash.winning_side, "Dire");
+        assert_eq!(clash.winners, vec!["Bob".to_string()]);
+        assert_eq!(clash.losing_side, "Radiant");
+        assert_eq!(clash.losers, vec!["Alice".to_string(), "Carol".to_string()]);
+        assert_eq!(clash.headline(), "Bob's Dire beat Alice & Carol's Radiant");
+    }
+
+    #[test]
+    fn test_from_teams_requires_both_sides() {
+        let alice = player_stats(86, "Rubick", "Alice", (6, 5, 16), 11230);
+        let mut random = player_stats(14, "Pudge", "Random", (3, 9, 7), 9870);
+        random.is_guild_member = false;
+        assert_eq!(Clash::from_teams(&[alice], &[random]), None);
+    }
+
+    #[test]
+    fn test_join_names() {
+        let names = ["Alice", "Bob", "Carol"].map(String::from);
+        assert_eq!(join_names(&names[..1]), "Alice");
+        assert_eq!(join_names(&names[..2]), "Alice & Bob");
+        assert_eq!(join_names(&names), "Alice, Bob & Carol");
+    }
+
+    #[test]
+    fn test_pair_id() {
+        assert_eq!(pair_id(200, 100), "100#200");
+        assert_eq!(pair_id(100, 200), pair_id(200, 100));
+    }
+
+}
@@ -0,0 +1,116 @@
+use crate::config::DigestConfig;
+use super::publisher::PublishRecord;
+
+/// Struct containing the options deciding when matches are batched into a digest
+#[derive(Debug, Default)]
+pub struct DigestOptions {
+    pub threshold: Option<usize>,
+    pub window_minutes: Option<i64>
+}
+
+impl DigestOptions {
+
+    /// Build the options from the `[templates.digest]` section. Without a threshold, digests are disabled.
+    pub fn from_config(digest: &DigestConfig) -> DigestOptions {
+        DigestOptions {
+            threshold: digest.threshold.value().copied(),
+            window_minutes: digest.window_minutes.value().copied()
+        }
+    }
+
+}
+
+/// Split `publish_records` into the records published one card each and the records batched into a digest.
+///
+/// Records are batched when more than `threshold` of them are found in one run. With a window, only records ending
+/// within `window_minutes` of the newest record count towards the threshold and go into the digest, so an old match
+/// found alongside a new session still gets its own card.
+///
+/// # Arguments
+///
+/// * `publish_records` - The data POJOs to be published, oldest first
+/// * `options` - The options deciding when matches are batched into a digest
+pub fn split_digest<'a>(
+    publish_records: &'a [PublishRecord],
+    options: &DigestOptions
+) -> (Vec<&'a PublishRecord>, Vec<&'a PublishRecord>) {
+    let all_single = (publish_records.iter().collect(), Vec::new());
+    let threshold = match options.threshold {
+        Some(threshold) => threshold,
+        None => return all_single
+    };
+    let newest_end = match publish_records.iter().map(|publish_record| publish_record.end).max() {
+        Some(newest_end) => newest_end,
+        None => return all_single
+    };
+
+    let in_window = |publish_record: &PublishRecord| match options.window_minutes {
+        Some(window_minutes) => newest_end - publish_record.end <= chrono::Duration::minutes(window_minutes),
+        None => true
+    };
+    let (digest_records, single_records): (Vec<&PublishRecord>, Vec<&PublishRecord>) = publish_records.iter()
+        .partition(|publish_record| in_window(publish_record));
+    if digest_records.len() <= threshold {
+        return all_single;
+    }
+
+    (single_records, digest_records)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::publisher::fixtures::publish_record;
+    use crate::publisher::publisher::{MatchResult, PublishRecord};
+    use super::{DigestOptions, split_digest};
+
+    fn session(ends_minutes_ago: &[i64]) -> Vec<PublishRecord> {
+        ends_minutes_ago.iter().enumerate().map(|(index, minutes_ago)| {
+            let mut record = publish_record(MatchResult::Victory);
+            record.match_id = index.to_string();
+            record.end -= chrono::Duration::minutes(*minutes_ago);
+            record
+        }).collect()
+    }
+
+    fn match_ids(publish_records: &[&PublishRecord]) -> Vec<String> {
+        publish_records.iter().map(|publish_record| publish_record.match_id.to_string()).collect()
+    }
+
+    #[test]
+    fn test_split_digest_disabled() {
+        let records = session(&[120, 80, 40, 0]);
+        let (single, digest) = split_digest(&records, &DigestOptions::default());
+        assert_eq!(match_ids(&single), vec!["0", "1", "2", "3"]);
+        assert!(digest.is_empty());
+    }
+
+    #[test]
+    fn test_split_digest_threshold() {
+        let records = session(&[120, 80, 40, 0]);
+        let options = DigestOptions { threshold: Some(3), window_minutes: None };
+        let (single, digest) = split_digest(&records, &options);
+        assert!(single.is_empty());
+        assert_eq!(match_ids(&digest), vec!["0", "1", "2", "3"]);
+
+        let options = DigestOptions { threshold: Some(4), window_minutes: None };
+        let (single, digest) = split_digest(&records, &options);
+        assert_eq!(single.len(), 4);
+        assert!(digest.is_empty());
+    }
+
+    #[test]
+    fn test_split_digest_window() {
+        let records = session(&[1440, 80, 40, 0]);
+        let options = DigestOptions { threshold: Some(2), window_minutes: Some(90) };
+        let (single, digest) = split_digest(&records, &options);
+        assert_eq!(match_ids(&single), vec!["0"]);
+        assert_eq!(match_ids(&digest), vec!["1", "2", "3"]);
+
+        let options = DigestOptions { threshold: Some(2), window_minutes: Some(60) };
+        let (single, digest) = split_digest(&records, &options);
+        assert_eq!(single.len(), 4);
+        assert!(digest.is_empty());
+    }
+
+}
@@ -0,0 +1,84 @@
+use std::path::PathBuf;
+use lambda_runtime::Error;
+use serde_json::Value;
+use crate::config::RunConfig;
+
+/// Enum of where a dry run writes the payloads it would have sent
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DryRunOutput {
+    /// Print every payload to stdout
+    Stdout,
+    /// Write every payload to a file of the directory, along with the scoreboards
+    Directory(PathBuf)
+}
+
+impl DryRunOutput {
+
+    /// Get the DryRunOutput from the `dry_run` and `dry_run_dir` settings of the `[run]` section, printing to stdout
+    /// unless a directory is set
+    /// Otherwise, None when `dry_run` is not `true` and payloads are sent
+    pub fn from_config(run: &RunConfig) -> Option<DryRunOutput> {
+        let dry_run = run.dry_run.value().copied().unwrap_or(false);
+        let output = match run.dry_run_dir.value() {
+            Some(dir) => DryRunOutput::Directory(dir.to_path_buf()),
+            None => DryRunOutput::Stdout
+        };
+
+        dry_run.then_some(output)
+    }
+
+    /// Write the JSON `payload` a sink would have received
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the payload, such as `6789012345.kook`, used as file name
+    /// * `payload` - The payload of the sink
+    pub fn write(&self, name: &str, payload: &Value) -> Result<(), Error> {
+        let content = serde_json::to_string_pretty(payload)?;
+        match self {
+            DryRunOutput::Stdout => println!("==> {}.json <==\n{}", name, content),
+            DryRunOutput::Directory(dir) => {
+                std::fs::create_dir_all(dir)?;
+                std::fs::write(dir.join(format!("{}.json", name)), content)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Write a PNG scoreboard a sink would have received. Images are only written to a directory.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the image, such as `6789012345.scoreboard`, used as file name
+    /// * `png` - The PNG image
+    pub fn write_png(&self, name: &str, png: &[u8]) -> Result<(), Error> {
+        if let DryRunOutput::Directory(dir) = self {
+            std::fs::create_dir_all(dir)?;
+            std::fs::write(dir.join(format!("{}.png", name)), png)?;
+        }
+
+        Ok(())
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use serde_json::json;
+    use super::DryRunOutput;
+
+    #[test]
+    fn test_write_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = DryRunOutput::Directory(dir.path().join("payloads"));
+        output.write("6789012345.kook", &json!({ "target_id": "3193188266865676" })).unwrap();
+        output.write_png("6789012345.scoreboard", &[137, 80, 78, 71]).unwrap();
+
+        let content = std::fs::read_to_string(dir.path().join("payloads/6789012345.kook.json")).unwrap();
+        assert_eq!(serde_json::from_str::<serde_json::Value>(&content).unwrap(), json!({ "target_id": "3193188266865676" }));
+        assert_eq!(std::fs::read(dir.path().join("payloads/6789012345.scoreboard.png")).unwrap(), vec![137, 80, 78, 71]);
+    }
+
+}
@@ -0,0 +1,145 @@
+use std::path::PathBuf;
+use chrono::TimeZone;
+use serde_json::json;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use crate::provider::stratz;
+use crate::settings::{SinkSettings, TemplateSettings};
+use super::{digest::DigestOptions, highlights::HighlightThresholds};
+use super::publisher::{PublishRecord, MatchResult, PlayerStats, PlayerList, Publisher};
+
+/// Build a publisher listing `players` with the default templates and placeholder sinks
+pub fn publisher(players: PlayerList) -> Publisher {
+    Publisher {
+        client: reqwest::Client::new(),
+        sinks: SinkSettings {
+            kook_token: "TestingToken".to_string(),
+            kook_target_id: "3193188266865676".to_string(),
+            report_kook_target_id: "3193188266865676".to_string(),
+            discord_webhook_url: "http://localhost/webhook".to_string(),
+            report_discord_webhook_url: "http://localhost/webhook".to_string(),
+            alert_kook_target_id: None,
+            alert_discord_webhook_url: None
+        },
+        players,
+        templates: TemplateSettings {
+            hero_assets_dir: PathBuf::from("assets/heroes"),
+            highlights: HighlightThresholds::default(),
+            streak_threshold: 3,
+            digest: DigestOptions::default(),
+            report_timezone: chrono_tz::Tz::UTC
+        },
+        dry_run: None
+    }
+}
+
+/// Build a ranked 40:00 match of `players` won by Radiant, as fetched from Stratz
+pub fn guild_match(players: serde_json::Value) -> stratz::api::Match {
+    serde_json::from_value(json!({
+        "id": 6789012345i64,
+        "lobbyType": "RANKED",
+        "gameMode": "ALL_PICK_RANKED",
+        "durationSeconds": 2400,
+        "endDateTime": 1650000000,
+        "didRadiantWin": true,
+        "stats": null,
+        "players": players
+    })).unwrap()
+}
+
+/// Build a match without players, with the given id and end timestamp
+pub fn ended_match(match_id: i64, end: i64) -> stratz::api::Match {
+    let mut guild_match = guild_match(json!([]));
+    guild_match.id = Some(match_id);
+    guild_match.end_date_time = Some(end);
+    guild_match
+}
+
+/// Build the PlayerStats of a player with the given hero, name, K/D/A and net worth
+pub fn player_stats(hero_id: i16, hero: &str, name: &str, kda: (u8, u8, u8), networth: i64) -> PlayerStats {
+    PlayerStats {
+        hero_id,
+        hero_display_name: hero.to_string(),
+        name: name.to_string(),
+        kills: kda.0,
+        deaths: kda.1,
+        assists: kda.2,
+        networth,
+        kill_times: Vec::new(),
+        steam_account_id: Some(hero_id as i64 * 1000),
+        is_guild_member: true,
+        party_id: None,
+        stack: None,
+        is_victory: true
+    }
+}
+
+/// Build a ranked 25:51 match with two Radiant players and one Dire player
+pub fn publish_record(match_result: MatchResult) -> PublishRecord {
+    PublishRecord {
+        match_id: "6789012345".to_string(),
+        guild_id: "117311".to_string(),
+        guild_name: "NanTuan".to_string(),
+        guild_logo: "logo".to_string(),
+        match_result,
+        lobby_type: stratz::api::LobbyType::RANKED,
+        game_mode: stratz::api::GameMode::ALL_PICK_RANKED,
+        player_stats_radiant: vec![
+            player_stats(86, "Rubick", "Alice", (6, 5, 16), 11230),
+            player_stats(11, "Shadow Fiend", "Bob the Very Long Named Player", (16, 4, 8), 24510)
+        ],
+        player_stats_dire: vec![
+            player_stats(14, "Pudge", "Carol", (3, 9, 7), 9870)
+        ],
+        duration_seconds: 1551,
+        duration_field: "25:51".to_string(),
+        did_radiant_win: Some(true),
+        radiant_networth_leads: vec![0, 1200, -800, 3500, 9000],
+        highlights: Vec::new(),
+        clash: None,
+        streaks: Vec::new(),
+        end: chrono::Utc.timestamp_opt(1650000000, 0).unwrap()
+    }
+}
+
+/// Start an HTTP server on a free local port answering every request with `500 Internal Server Error`, and get the
+/// URL of a webhook served by it, so a sink fails without depending on the network of the host
+pub async fn failing_server() -> String {
+    let listener = TcpListener::bind(("127.0.0.1", 0)).await.unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                read_request(&mut stream).await;
+                let response = "HTTP/1.1 500 Internal Server Error\r\ncontent-length: 0\r\nconnection: close\r\n\r\n";
+                let _ = stream.write_all(response.as_bytes()).await;
+            });
+        }
+    });
+
+    format!("http://{}/webhook", address)
+}
+
+/// Read a whole HTTP request from `stream`, its body included, so the answer does not reset the connection
+async fn read_request(stream: &mut TcpStream) {
+    let mut request = Vec::new();
+    let mut buffer = [0u8; 8192];
+    loop {
+        let read = match stream.read(&mut buffer).await {
+            Ok(0) | Err(_) => return,
+            Ok(read) => read
+        };
+        request.extend_from_slice(&buffer[..read]);
+
+        let text = String::from_utf8_lossy(&request);
+        let Some(header_end) = text.find("\r\n\r\n") else { continue };
+        let content_length = text[..header_end].lines()
+            .filter_map(|line| line.split_once(':'))
+            .find(|(name, _)| name.eq_ignore_ascii_case("content-length"))
+            .and_then(|(_, value)| value.trim().parse::<usize>().ok())
+            .unwrap_or_default();
+        if request.len() >= header_end + 4 + content_length {
+            return;
+        }
+    }
+}
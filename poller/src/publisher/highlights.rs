@@ -0,0 +1,328 @@
+use serde::{Serialize, Deserialize};
+use crate::{config::HighlightsConfig, provider::stratz};
+use super::publisher::{PublishRecord, PlayerStats, MatchResult};
+
+const DEFAULT_KILLS: u8 = 20;
+const DEFAULT_KDA: f64 = 10.0;
+const DEFAULT_DEATHLESS_PARTICIPATION: u8 = 10;
+const DEFAULT_COMEBACK_NETWORTH: i64 = 10000;
+const DEFAULT_LONG_GAME_MINUTES: i64 = 60;
+const DEFAULT_SHORT_GAME_MINUTES: i64 = 20;
+
+/// Kills within this many seconds of the previous kill extend a multi-kill
+const MULTI_KILL_WINDOW_SECONDS: i64 = 18;
+const RAMPAGE_KILLS: usize = 5;
+
+/// Enum of notable performances called out on the card
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum Highlight {
+    Rampage { name: String, hero: String },
+    KillCount { name: String, hero: String, kills: u8 },
+    Deathless { name: String, hero: String, kills: u8, assists: u8 },
+    ExtremeKda { name: String, hero: String, kda: f64 },
+    Comeback { deficit: i64 },
+    LongGame { duration: String },
+    ShortGame { duration: String }
+}
+
+/// Struct containing the thresholds of the highlight detectors
+#[derive(Debug)]
+pub struct HighlightThresholds {
+    pub kills: u8,
+    pub kda: f64,
+    pub deathless_participation: u8,
+    pub comeback_networth: i64,
+    pub long_game_minutes: i64,
+    pub short_game_minutes: i64
+}
+
+impl Default for HighlightThresholds {
+    fn default() -> Self {
+        HighlightThresholds {
+            kills: DEFAULT_KILLS,
+            kda: DEFAULT_KDA,
+            deathless_participation: DEFAULT_DEATHLESS_PARTICIPATION,
+            comeback_networth: DEFAULT_COMEBACK_NETWORTH,
+            long_game_minutes: DEFAULT_LONG_GAME_MINUTES,
+            short_game_minutes: DEFAULT_SHORT_GAME_MINUTES
+        }
+    }
+}
+
+impl HighlightThresholds {
+
+    /// Build the thresholds from the `[templates.highlights]` section, falling back to the defaults
+    pub fn from_config(highlights: &HighlightsConfig) -> HighlightThresholds {
+        let defaults = HighlightThresholds::default();
+
+        HighlightThresholds {
+            kills: highlights.kills.value().copied().unwrap_or(defaults.kills),
+            kda: highlights.kda.value().copied().unwrap_or(defaults.kda),
+            deathless_participation: highlights.deathless_participation.value()
+                .copied()
+                .unwrap_or(defaults.deathless_participation),
+            comeback_networth: highlights.comeback_networth.value().copied().unwrap_or(defaults.comeback_networth),
+            long_game_minutes: highlights.long_game_minutes.value().copied().unwrap_or(defaults.long_game_minutes),
+            short_game_minutes: highlights.short_game_minutes.value().copied().unwrap_or(defaults.short_game_minutes)
+        }
+    }
+
+}
+
+/// Run every highlight detector over `publish_record`. Only guild members are called out.
+///
+/// # Arguments
+///
+/// * `publish_record` - The data POJO to be published
+/// * `thresholds` - The thresholds of the detectors
+pub fn detect_highlights(publish_record: &PublishRecord, thresholds: &HighlightThresholds) -> Vec<Highlight> {
+    let mut highlights = Vec::new();
+
+    let players_stats = publish_record.player_stats_radiant.iter()
+        .chain(publish_record.player_stats_dire.iter())
+        .filter(|player_stats| player_stats.is_guild_member);
+    for player_stats in players_stats {
+        highlights.extend(detect_rampage(player_stats));
+        highlights.extend(detect_kill_count(player_stats, thresholds));
+        highlights.extend(detect_deathless(player_stats, thresholds));
+        highlights.extend(detect_extreme_kda(player_stats, thresholds));
+    }
+
+    highlights.extend(detect_comeback(publish_record, thresholds));
+    highlights.extend(detect_game_length(publish_record, thresholds));
+
+    highlights
+}
+
+/// Detect a player getting 5 kills in a row, each within the multi-kill window of the previous one
+fn detect_rampage(player_stats: &PlayerStats) -> Option<Highlight> {
+    let mut streak = 0;
+    let mut previous_time: Option<i64> = None;
+    for time in player_stats.kill_times.iter() {
+        streak = match previous_time {
+            Some(previous_time) if time - previous_time <= MULTI_KILL_WINDOW_SECONDS => streak + 1,
+            _ => 1
+        };
+        previous_time = Some(*time);
+
+        if streak >= RAMPAGE_KILLS {
+            return Some(Highlight::Rampage {
+                name: player_stats.name.to_string(),
+                hero: player_stats.hero_display_name.to_string()
+            });
+        }
+    }
+
+    None
+}
+
+/// Detect a player reaching the kill count threshold
+fn detect_kill_count(player_stats: &PlayerStats, thresholds: &HighlightThresholds) -> Option<Highlight> {
+    if player_stats.kills < thresholds.kills {
+        return None;
+    }
+
+    Some(Highlight::KillCount {
+        name: player_stats.name.to_string(),
+        hero: player_stats.hero_display_name.to_string(),
+        kills: player_stats.kills
+    })
+}
+
+/// Detect a player finishing without dying while taking part in enough kills
+fn detect_deathless(player_stats: &PlayerStats, thresholds: &HighlightThresholds) -> Option<Highlight> {
+    let participation = player_stats.kills.saturating_add(player_stats.assists);
+    if player_stats.deaths > 0 || participation < thresholds.deathless_participation {
+        return None;
+    }
+
+    Some(Highlight::Deathless {
+        name: player_stats.name.to_string(),
+        hero: player_stats.hero_display_name.to_string(),
+        kills: player_stats.kills,
+        assists: player_stats.assists
+    })
+}
+
+/// Detect a player whose (kills + assists) / deaths reaches the KDA threshold. Deathless games are left to
+/// `detect_deathless`.
+fn detect_extreme_kda(player_stats: &PlayerStats, thresholds: &HighlightThresholds) -> Option<Highlight> {
+    if player_stats.deaths == 0 {
+        return None;
+    }
+
+    let kda = (player_stats.kills as f64 + player_stats.assists as f64) / player_stats.deaths as f64;
+    if kda < thresholds.kda {
+        return None;
+    }
+
+    Some(Highlight::ExtremeKda {
+        name: player_stats.name.to_string(),
+        hero: player_stats.hero_display_name.to_string(),
+        kda
+    })
+}
+
+/// Detect a victory after trailing by at least the comeback threshold in net worth
+fn detect_comeback(publish_record: &PublishRecord, thresholds: &HighlightThresholds) -> Option<Highlight> {
+    if publish_record.match_result != MatchResult::Victory {
+        return None;
+    }
+
+    let did_radiant_win = publish_record.did_radiant_win?;
+    let leads = publish_record.radiant_networth_leads.iter();
+    let deficit = match did_radiant_win {
+        true => leads.map(|lead| -lead).max()?,
+        false => leads.copied().max()?
+    };
+    if deficit < thresholds.comeback_networth {
+        return None;
+    }
+
+    Some(Highlight::Comeback { deficit })
+}
+
+/// Detect games lasting longer or shorter than the game length thresholds. Short games are only called out in ranked
+/// and all pick games, as turbo games and early surrenders are short anyway.
+fn detect_game_length(publish_record: &PublishRecord, thresholds: &HighlightThresholds) -> Option<Highlight> {
+    let minutes = publish_record.duration_seconds / 60;
+    if minutes >= thresholds.long_game_minutes {
+        return Some(Highlight::LongGame { duration: publish_record.duration_field.to_string() });
+    }
+    let is_standard_game = matches!(publish_record.lobby_type, stratz::api::LobbyType::RANKED)
+        || matches!(publish_record.game_mode, stratz::api::GameMode::ALL_PICK | stratz::api::GameMode::ALL_PICK_RANKED);
+    if is_standard_game && minutes < thresholds.short_game_minutes {
+        return Some(Highlight::ShortGame { duration: publish_record.duration_field.to_string() });
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::provider::stratz;
+    use crate::publisher::fixtures::{player_stats, publish_record};
+    use crate::publisher::publisher::MatchResult;
+    use super::{
+        Highlight, HighlightThresholds, detect_highlights, detect_rampage, detect_kill_count, detect_deathless,
+        detect_extreme_kda, detect_comeback, detect_game_length
+    };
+
+    #[test]
+    fn test_detect_rampage() {
+        let mut rampage = player_stats(11, "Shadow Fiend", "Bob", (12, 2, 4), 24510);
+        rampage.kill_times = vec![300, 1200, 1205, 1211, 1220, 1234, 1251];
+        assert_eq!(
+            detect_rampage(&rampage),
+            Some(Highlight::Rampage { name: "Bob".to_string(), hero: "Shadow Fiend".to_string() })
+        );
+
+        let mut ultra_kill = player_stats(11, "Shadow Fiend", "Bob", (12, 2, 4), 24510);
+        ultra_kill.kill_times = vec![1200, 1205, 1211, 1220, 1260, 1265];
+        assert_eq!(detect_rampage(&ultra_kill), None);
+    }
+
+    #[test]
+    fn test_detect_kill_count() {
+        let thresholds = HighlightThresholds::default();
+        let carry = player_stats(8, "Juggernaut", "Alice", (21, 3, 5), 30000);
+        assert_eq!(
+            detect_kill_count(&carry, &thresholds),
+            Some(Highlight::KillCount { name: "Alice".to_string(), hero: "Juggernaut".to_string(), kills: 21 })
+        );
+        assert_eq!(detect_kill_count(&player_stats(8, "Juggernaut", "Alice", (19, 3, 5), 30000), &thresholds), None);
+    }
+
+    #[test]
+    fn test_detect_deathless() {
+        let thresholds = HighlightThresholds::default();
+        let support = player_stats(5, "Crystal Maiden", "Carol", (2, 0, 14), 9000);
+        assert_eq!(
+            detect_deathless(&support, &thresholds),
+            Some(Highlight::Deathless { name: "Carol".to_string(), hero: "Crystal Maiden".to_string(), kills: 2, assists: 14 })
+        );
+
+        // Not dying without taking part in fights is not notable
+        assert_eq!(detect_deathless(&player_stats(5, "Crystal Maiden", "Carol", (0, 0, 3), 4000), &thresholds), None);
+        assert_eq!(detect_deathless(&player_stats(5, "Crystal Maiden", "Carol", (2, 1, 14), 9000), &thresholds), None);
+    }
+
+    #[test]
+    fn test_detect_extreme_kda() {
+        let thresholds = HighlightThresholds::default();
+        let mid = player_stats(13, "Puck", "Dave", (14, 2, 8), 20000);
+        assert_eq!(
+            detect_extreme_kda(&mid, &thresholds),
+            Some(Highlight::ExtremeKda { name: "Dave".to_string(), hero: "Puck".to_string(), kda: 11.0 })
+        );
+        assert_eq!(detect_extreme_kda(&player_stats(13, "Puck", "Dave", (14, 0, 8), 20000), &thresholds), None);
+        assert_eq!(detect_extreme_kda(&player_stats(13, "Puck", "Dave", (6, 5, 16), 20000), &thresholds), None);
+    }
+
+    #[test]
+    fn test_detect_comeback() {
+        let thresholds = HighlightThresholds::default();
+        let mut record = publish_record(MatchResult::Victory);
+        record.did_radiant_win = Some(true);
+        record.radiant_networth_leads = vec![0, -3000, -12500, -4000, 8000];
+        assert_eq!(detect_comeback(&record, &thresholds), Some(Highlight::Comeback { deficit: 12500 }));
+
+        // Dire victory after radiant led
+        record.did_radiant_win = Some(false);
+        assert_eq!(detect_comeback(&record, &thresholds), None);
+        record.radiant_networth_leads = vec![0, 11000, -2000];
+        assert_eq!(detect_comeback(&record, &thresholds), Some(Highlight::Comeback { deficit: 11000 }));
+
+        // Throws are not comebacks
+        record.match_result = MatchResult::Defeat;
+        assert_eq!(detect_comeback(&record, &thresholds), None);
+    }
+
+    #[test]
+    fn test_detect_game_length() {
+        let thresholds = HighlightThresholds::default();
+        let mut record = publish_record(MatchResult::Victory);
+        assert_eq!(detect_game_length(&record, &thresholds), None);
+
+        record.duration_seconds = 4330;
+        record.duration_field = "72:10".to_string();
+        assert_eq!(detect_game_length(&record, &thresholds), Some(Highlight::LongGame { duration: "72:10".to_string() }));
+
+        record.duration_seconds = 1015;
+        record.duration_field = "16:55".to_string();
+        assert_eq!(detect_game_length(&record, &thresholds), Some(Highlight::ShortGame { duration: "16:55".to_string() }));
+    }
+
+    #[test]
+    fn test_detect_short_game_boundary() {
+        let thresholds = HighlightThresholds::default();
+        let mut record = publish_record(MatchResult::Victory);
+        record.duration_seconds = 1199;
+        record.duration_field = "19:59".to_string();
+        assert_eq!(detect_game_length(&record, &thresholds), Some(Highlight::ShortGame { duration: "19:59".to_string() }));
+
+        record.duration_seconds = 1200;
+        record.duration_field = "20:00".to_string();
+        assert_eq!(detect_game_length(&record, &thresholds), None);
+
+        // Turbo games are short anyway, unlike unranked all pick games
+        record.duration_seconds = 1199;
+        record.lobby_type = stratz::api::LobbyType::UNRANKED;
+        record.game_mode = stratz::api::GameMode::TURBO;
+        assert_eq!(detect_game_length(&record, &thresholds), None);
+        record.game_mode = stratz::api::GameMode::ALL_PICK;
+        assert!(detect_game_length(&record, &thresholds).is_some());
+    }
+
+    #[test]
+    fn test_detect_highlights() {
+        let thresholds = HighlightThresholds { kills: 15, ..HighlightThresholds::default() };
+        let record = publish_record(MatchResult::Victory);
+        assert_eq!(
+            detect_highlights(&record, &thresholds),
+            vec![Highlight::KillCount { name: "Bob the Very Long Named Player".to_string(), hero: "Shadow Fiend".to_string(), kills: 16 }]
+        );
+    }
+
+}
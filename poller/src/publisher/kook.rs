@@ -2,31 +2,47 @@ use std::collections::HashMap;
 
 use lambda_runtime::Error;
 use reqwest::header::AUTHORIZATION;
+use reqwest::multipart::{Form, Part};
 use serde::{Serialize, Deserialize};
-use crate::config::kook_token;
+use serde_json::{json, Value};
 use super::{publisher::
     {PublishRecord, MatchResult, PlayerStats}, 
-    utils::{transform_match_result, transform_lobby_type, transform_game_mode}
+    clash::Clash,
+    utils::{transform_match_result, transform_lobby_type, transform_game_mode, transform_highlight, transform_clash,
+        transform_player_name, transform_digest_title, transform_digest_players, transform_report_title,
+        transform_member_summary, transform_streak}
 };
+use crate::{alert::Alert, report::summary::GuildReport};
 
 const CREATE_MESSAGE_ENDPOINT: &str = "https://www.kookapp.cn/api/v3/message/create";
+const CREATE_ASSET_ENDPOINT: &str = "https://www.kookapp.cn/api/v3/asset/create";
 const TOKEN_TYPE: &str = "Bot";
 
 const TEXT_TYPE_KMARKDOWN: &str = "kmarkdown";
 const ELEMENT_TYPE_PLAIN_TEXT: &str = "plain-text";
+const ELEMENT_TYPE_IMAGE: &str = "image";
 const MODULE_TYPE_SECTION: &str = "section";
 const MODULE_TYPE_DIVIDER: &str = "divider";
 const MODULE_TYPE_CONTEXT: &str = "context";
+const MODULE_TYPE_CONTAINER: &str = "container";
 const CARD_TYPE_CARD: &str = "card";
 const MESSAGE_TYPE_CARD: &str = "10";
 const CARD_SIZE_LARGE: &str = "lg";
+const CARD_THEME_DIGEST: &str = "info";
+const CARD_THEME_REPORT: &str = "primary";
+const CARD_THEME_ALERT: &str = "danger";
+const CARD_THEME_RECOVERED: &str = "success";
+const SCOREBOARD_FILE_NAME: &str = "scoreboard.png";
 
 /// Struct to serialize and deserialize Element of Kook Module
 #[derive(Serialize, Deserialize, Debug, Clone)]
 struct Element {
     #[serde(rename = "type")]
     element_type: String,
-    content: String
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    src: Option<String>
 }
 
 /// Struct to serialize and deserialize Text of Kook Module
@@ -64,6 +80,27 @@ struct CardMessage {
     pub cards: Vec<Card>
 }
 
+/// Struct to deserialize the data of Kook asset/create response
+#[derive(Deserialize, Debug)]
+struct Asset {
+    url: String
+}
+
+/// Struct to deserialize Kook asset/create response
+#[derive(Deserialize, Debug)]
+struct AssetResponse {
+    code: i64,
+    message: String,
+    data: Option<Asset>
+}
+
+/// Struct to deserialize Kook message/create response
+#[derive(Deserialize, Debug)]
+struct MessageResponse {
+    code: i64,
+    message: String
+}
+
 /// Struct containing data needed to format Header module
 pub struct HeaderModuleData<'a> {
     guild_name: &'a String,
@@ -84,7 +121,8 @@ pub struct BodyModuleData<'a> {
 
 /// Kook Publisher
 pub struct KookPublisher {
-    pub client: reqwest::Client
+    pub client: reqwest::Client,
+    pub token: String
 }
 
 impl KookPublisher {
@@ -95,7 +133,68 @@ impl KookPublisher {
     /// 
     /// * `target_id` - The id of target, a.k.a the id of channel
     /// * `publish_record` - The data POJO to be published
-    pub async fn publish(&self, target_id: &str, publish_record: &PublishRecord) -> Result<(), Error> {
+    /// * `scoreboard` - The PNG scoreboard uploaded to Kook and shown below the players, if any
+    pub async fn publish(
+        &self,
+        target_id: &str,
+        publish_record: &PublishRecord,
+        scoreboard: Option<&[u8]>
+    ) -> Result<(), Error> {
+        // A scoreboard failing to upload leaves the card without the image rather than dropping the message
+        let scoreboard_url = match scoreboard {
+            Some(scoreboard) => match self.upload_asset(scoreboard).await {
+                Ok(scoreboard_url) => Some(scoreboard_url),
+                Err(e) => {
+                    let match_id = publish_record.match_id.as_str();
+                    log::error!(match_id = match_id; "Failed to upload the scoreboard of match {}: {}", match_id, e);
+                    None
+                }
+            },
+            None => None
+        };
+        let card = self.build_card(publish_record, scoreboard_url.as_deref());
+
+        self.send_card(target_id, card).await
+    }
+
+    /// Get the message `publish` would send to Kook for `publish_record`, without sending it
+    ///
+    /// # Arguments
+    ///
+    /// * `target_id` - The id of target, a.k.a the id of channel
+    /// * `publish_record` - The data POJO to be published
+    /// * `scoreboard_url` - The url of the scoreboard shown below the players, if any
+    pub fn preview(&self, target_id: &str, publish_record: &PublishRecord, scoreboard_url: Option<&str>) -> Value {
+        self.card_payload(target_id, self.build_card(publish_record, scoreboard_url))
+    }
+
+    /// Get the message `publish_digest` would send to Kook for `publish_records`, without sending it
+    ///
+    /// # Arguments
+    ///
+    /// * `target_id` - The id of target, a.k.a the id of channel
+    /// * `publish_records` - The data POJOs to be summarized, oldest first
+    pub fn preview_digest(&self, target_id: &str, publish_records: &[&PublishRecord]) -> Option<Value> {
+        self.build_digest_card(publish_records).map(|card| self.card_payload(target_id, card))
+    }
+
+    /// Get the message `publish_report` would send to Kook for `report`, without sending it
+    ///
+    /// # Arguments
+    ///
+    /// * `target_id` - The id of target, a.k.a the id of channel
+    /// * `report` - The guild summary report
+    pub fn preview_report(&self, target_id: &str, report: &GuildReport) -> Value {
+        self.card_payload(target_id, self.build_report_card(report))
+    }
+
+    /// Build the Kook card of `publish_record`
+    ///
+    /// # Arguments
+    ///
+    /// * `publish_record` - The data POJO to be published
+    /// * `scoreboard_url` - The url of the uploaded scoreboard shown below the players, if any
+    fn build_card(&self, publish_record: &PublishRecord, scoreboard_url: Option<&str>) -> Card {
         // Construct HeaderModuleData for data to format Header Module of Kook Card Message
         let header_module_data = HeaderModuleData {
             guild_name: &publish_record.guild_name,
@@ -114,20 +213,149 @@ impl KookPublisher {
             dire: &self.get_players_stats(&publish_record.player_stats_dire)
         };
 
+        // Construct modules of Kook Card, with the uploaded scoreboard below the players
+        let mut modules = vec![self.get_header_module(&header_module_data)];
+        if let Some(clash) = publish_record.clash.as_ref() {
+            modules.push(self.get_clash_module(clash));
+        }
+        if !publish_record.highlights.is_empty() {
+            modules.push(self.get_highlights_module(publish_record));
+        }
+        if !publish_record.streaks.is_empty() {
+            modules.push(self.get_streaks_module(publish_record));
+        }
+        modules.push(self.get_divider_module());
+        modules.push(self.get_body_module(&body_module_data));
+        if let Some(scoreboard_url) = scoreboard_url {
+            modules.push(self.get_image_module(scoreboard_url));
+        }
+        modules.push(self.get_divider_module());
+        modules.push(self.get_footer_module());
+
         // Construct Card of Kook Card Message
-        let card = Card {
+        Card {
             card_type: CARD_TYPE_CARD.to_string(),
             theme: self.match_card_theme(&publish_record.match_result),
             size: CARD_SIZE_LARGE.to_string(),
-            modules: vec![
-                self.get_header_module(&header_module_data), 
-                self.get_divider_module(),
-                self.get_body_module(&body_module_data),
-                self.get_divider_module(),
-                self.get_footer_module()
-            ]
+            modules
+        }
+    }
+
+    /// Format several `publish_records` into a single compact card and publish it to Kook
+    ///
+    /// # Arguments
+    /// 
+    /// * `target_id` - The id of target, a.k.a the id of channel
+    /// * `publish_records` - The data POJOs to be summarized, oldest first
+    pub async fn publish_digest(&self, target_id: &str, publish_records: &[&PublishRecord]) -> Result<(), Error> {
+        match self.build_digest_card(publish_records) {
+            Some(card) => self.send_card(target_id, card).await,
+            None => Ok(())
+        }
+    }
+
+    /// Build the compact Kook card summarizing `publish_records`
+    /// Otherwise, None when there is no record
+    ///
+    /// # Arguments
+    ///
+    /// * `publish_records` - The data POJOs to be summarized, oldest first
+    fn build_digest_card(&self, publish_records: &[&PublishRecord]) -> Option<Card> {
+        let first_record = publish_records.first()?;
+
+        let mut modules = vec![self.get_digest_header_module(first_record, publish_records.len())];
+        modules.push(self.get_divider_module());
+        for publish_record in publish_records.iter() {
+            modules.push(self.get_digest_entry_module(publish_record));
+        }
+        modules.push(self.get_divider_module());
+        modules.push(self.get_footer_module());
+
+        Some(Card {
+            card_type: CARD_TYPE_CARD.to_string(),
+            theme: CARD_THEME_DIGEST.to_string(),
+            size: CARD_SIZE_LARGE.to_string(),
+            modules
+        })
+    }
+
+    /// Format `report` into a card listing the stats of every guild member and publish it to Kook
+    ///
+    /// # Arguments
+    /// 
+    /// * `target_id` - The id of target, a.k.a the id of channel
+    /// * `report` - The guild summary report
+    pub async fn publish_report(&self, target_id: &str, report: &GuildReport) -> Result<(), Error> {
+        self.send_card(target_id, self.build_report_card(report)).await
+    }
+
+    /// Build the Kook card listing the stats of every guild member in `report`
+    ///
+    /// # Arguments
+    ///
+    /// * `report` - The guild summary report
+    fn build_report_card(&self, report: &GuildReport) -> Card {
+        let mut modules = vec![self.get_report_header_module(report)];
+        modules.push(self.get_divider_module());
+        if report.members.is_empty() {
+            modules.push(self.get_kmarkdown_module("No games played".to_string()));
+        }
+        for summary in report.members.iter() {
+            modules.push(self.get_kmarkdown_module(transform_member_summary(summary).join("\n")));
+        }
+        modules.push(self.get_divider_module());
+        modules.push(self.get_footer_module());
+
+        Card {
+            card_type: CARD_TYPE_CARD.to_string(),
+            theme: CARD_THEME_REPORT.to_string(),
+            size: CARD_SIZE_LARGE.to_string(),
+            modules
+        }
+    }
+
+    /// Publish an operational `alert` to the admins on Kook, in red while a component fails and in green once it
+    /// recovers
+    ///
+    /// # Arguments
+    ///
+    /// * `target_id` - The id of target, a.k.a the id of the admin channel
+    /// * `alert` - The alert about a component
+    pub async fn publish_alert(&self, target_id: &str, alert: &Alert) -> Result<(), Error> {
+        self.send_card(target_id, self.build_alert_card(alert)).await
+    }
+
+    /// Build the Kook card of `alert`
+    fn build_alert_card(&self, alert: &Alert) -> Card {
+        let theme = match alert {
+            Alert::Failing { .. } => CARD_THEME_ALERT,
+            Alert::Recovered { .. } => CARD_THEME_RECOVERED
         };
 
+        Card {
+            card_type: CARD_TYPE_CARD.to_string(),
+            theme: theme.to_string(),
+            size: CARD_SIZE_LARGE.to_string(),
+            modules: vec![self.get_kmarkdown_module(alert.text())]
+        }
+    }
+
+    /// Get the Kook Card Message of `card` for `target_id` as JSON, with the cards left as JSON rather than the text
+    /// sent to Kook so that it reads easily
+    fn card_payload(&self, target_id: &str, card: Card) -> Value {
+        let card_message = CardMessage {
+            cards: vec!(card)
+        };
+
+        json!({
+            "type": MESSAGE_TYPE_CARD,
+            "target_id": target_id,
+            "content": card_message.cards
+        })
+    }
+
+    /// Send `card` as a Kook Card Message to `target_id`
+    async fn send_card(&self, target_id: &str, card: Card) -> Result<(), Error> {
         // Construct Kook Card Message
         let card_message = CardMessage {
             cards: vec!(card)
@@ -140,14 +368,44 @@ impl KookPublisher {
         params.insert("target_id", target_id);
         params.insert("content", &serde_card_message);
 
-        // publish the message
-        self.client.post(CREATE_MESSAGE_ENDPOINT)
-            .header(AUTHORIZATION, format!("{} {}", TOKEN_TYPE, &kook_token()))
+        // publish the message, Kook answering rejected messages such as an invalid token with a non-zero code
+        let response = self.client.post(CREATE_MESSAGE_ENDPOINT)
+            .header(AUTHORIZATION, format!("{} {}", TOKEN_TYPE, &self.token))
             .json(&params)
             .send()
+            .await?
+            .json::<MessageResponse>()
+            .await?;
+
+        match response.code {
+            0 => Ok(()),
+            _ => Err(format!("Failed to send message to Kook: {}", response.message).into())
+        }
+    }
+
+    /// Upload a PNG file to Kook and return the url of the uploaded asset
+    ///
+    /// # Arguments
+    ///
+    /// * `file` - The PNG file to be uploaded
+    async fn upload_asset(&self, file: &[u8]) -> Result<String, Error> {
+        let part = Part::bytes(file.to_vec())
+            .file_name(SCOREBOARD_FILE_NAME)
+            .mime_str("image/png")?;
+        let form = Form::new().part("file", part);
+
+        let response = self.client.post(CREATE_ASSET_ENDPOINT)
+            .header(AUTHORIZATION, format!("{} {}", TOKEN_TYPE, &self.token))
+            .multipart(form)
+            .send()
+            .await?
+            .json::<AssetResponse>()
             .await?;
 
-        Ok(())
+        match response.data {
+            Some(asset) if response.code == 0 => Ok(asset.url),
+            _ => Err(format!("Failed to upload asset to Kook: {}", response.message).into())
+        }
     }
 
     /// Get the content for the Kook Card Message header
@@ -160,7 +418,7 @@ impl KookPublisher {
             &format!("**{} - {} - {}** *{}*\n", data.match_result, data.lobby_type, data.game_mode, data.duration) 
         );
 
-        return header_content;
+        header_content
     }
 
     /// Get the text for the Kook Card Message header
@@ -192,17 +450,171 @@ impl KookPublisher {
         }
     }
 
+    /// Get the header module of a digest
+    /// 
+    /// # Arguments
+    /// 
+    /// * `publish_record` - Any of the summarized data POJOs, to read the guild from
+    /// * `count` - The number of summarized matches
+    /// 
+    /// # Examples
+    /// 
+    /// [SampleGuild](SampleGuildLink) - **5 new matches**
+    fn get_digest_header_module(&self, publish_record: &PublishRecord, count: usize) -> Module {
+        Module {
+            module_type: MODULE_TYPE_SECTION.to_string(),
+            text: Some(Text {
+                text_type: TEXT_TYPE_KMARKDOWN.to_string(),
+                content: format!(
+                    "[{}](https://stratz.com/guilds/{}) - **{} new matches**",
+                    publish_record.guild_name, publish_record.guild_id, count
+                )
+            }),
+            elements: Vec::new()
+        }
+    }
+
+    /// Get the header module of a summary report
+    /// 
+    /// # Arguments
+    /// 
+    /// * `report` - The guild summary report
+    /// 
+    /// # Examples
+    /// 
+    /// [SampleGuild](SampleGuildLink) - **Weekly report 2024-01-08 - 2024-01-14**
+    fn get_report_header_module(&self, report: &GuildReport) -> Module {
+        self.get_kmarkdown_module(format!(
+            "[{}](https://stratz.com/guilds/{}) - **{}**",
+            report.guild_name, report.guild_id, transform_report_title(report)
+        ))
+    }
+
+    /// Get a section module showing `content` as kmarkdown
+    fn get_kmarkdown_module(&self, content: String) -> Module {
+        Module {
+            module_type: MODULE_TYPE_SECTION.to_string(),
+            text: Some(Text {
+                text_type: TEXT_TYPE_KMARKDOWN.to_string(),
+                content
+            }),
+            elements: Vec::new()
+        }
+    }
+
+    /// Get the module summarizing a single match of a digest
+    /// 
+    /// # Arguments
+    /// 
+    /// * `publish_record` - The data POJO summarized in the digest
+    /// 
+    /// # Examples
+    /// 
+    /// [Victory - Ranked - All Draft (25:51)](SampleMatchLink)
+    /// Player1 - Rubick - 6/5/16
+    fn get_digest_entry_module(&self, publish_record: &PublishRecord) -> Module {
+        let mut content = format!(
+            "**[{}](https://stratz.com/matches/{})**\n",
+            transform_digest_title(publish_record), publish_record.match_id
+        );
+        for line in transform_digest_players(publish_record) {
+            content.push_str(&format!("{}\n", line));
+        }
+
+        Module {
+            module_type: MODULE_TYPE_SECTION.to_string(),
+            text: Some(Text {
+                text_type: TEXT_TYPE_KMARKDOWN.to_string(),
+                content
+            }),
+            elements: Vec::new()
+        }
+    }
+
+    /// Get clash module showing which guild members won and which lost against each other
+    /// 
+    /// # Arguments
+    /// 
+    /// * `clash` - The per-side result of the match
+    /// 
+    /// # Examples
+    /// 
+    /// **Player2's Dire beat Player1's Radiant**
+    /// Won: Player2
+    /// Lost: Player1
+    /// Head-to-head: Player2 3 - 1 Player1
+    fn get_clash_module(&self, clash: &Clash) -> Module {
+        let mut content = format!("**{}**\n", clash.headline());
+        for line in transform_clash(clash) {
+            content.push_str(&format!("{}\n", line));
+        }
+
+        Module {
+            module_type: MODULE_TYPE_SECTION.to_string(),
+            text: Some(Text {
+                text_type: TEXT_TYPE_KMARKDOWN.to_string(),
+                content
+            }),
+            elements: Vec::new()
+        }
+    }
+
+    /// Get highlights module calling out notable performances of the match
+    /// 
+    /// # Arguments
+    /// 
+    /// * `publish_record` - The data POJO to be published
+    /// 
+    /// # Examples
+    /// 
+    /// > Rampage! Player2 on Shadow Fiend
+    /// > Comeback from a 12.5k net worth deficit
+    fn get_highlights_module(&self, publish_record: &PublishRecord) -> Module {
+        let mut content = String::new();
+        for highlight in publish_record.highlights.iter() {
+            content.push_str(&format!("> {}\n", transform_highlight(highlight)));
+        }
+
+        Module {
+            module_type: MODULE_TYPE_SECTION.to_string(),
+            text: Some(Text {
+                text_type: TEXT_TYPE_KMARKDOWN.to_string(),
+                content
+            }),
+            elements: Vec::new()
+        }
+    }
+
+    /// Get streaks module calling out the win and loss streaks of guild members
+    /// 
+    /// # Arguments
+    /// 
+    /// * `publish_record` - The data POJO to be published
+    /// 
+    /// # Examples
+    /// 
+    /// > Player1 is on a 6-game win streak (best 8)
+    /// > Player2 ended a 5-game losing streak
+    fn get_streaks_module(&self, publish_record: &PublishRecord) -> Module {
+        let mut content = String::new();
+        for streak_event in publish_record.streaks.iter() {
+            content.push_str(&format!("> {}\n", transform_streak(streak_event)));
+        }
+
+        self.get_kmarkdown_module(content)
+    }
+
     /// Get the content for the Kook Card Message body
     fn get_body_content(&self, data: &BodyModuleData) -> String {
         let mut body_content = String::new();
-        if data.radiant.len() > 0 {
+        if !data.radiant.is_empty() {
             body_content.push_str(&format!("**Radiant**\n{}", data.radiant));
         }
-        if data.dire.len() > 0 {
+        if !data.dire.is_empty() {
             body_content.push_str(&format!("**Dire**\n{}", data.dire));
         }
 
-        return body_content;
+        body_content
     }
 
     // Get the text for the Kook Card Message body
@@ -242,11 +654,25 @@ impl KookPublisher {
         }
     }
 
+    /// Get a container module showing the image at `src`
+    fn get_image_module(&self, src: &str) -> Module {
+        Module {
+            module_type: MODULE_TYPE_CONTAINER.to_string(),
+            text: None,
+            elements: vec![Element {
+                element_type: ELEMENT_TYPE_IMAGE.to_string(),
+                content: None,
+                src: Some(src.to_string())
+            }]
+        }
+    }
+
     /// Get a Stratz appriciation text as a Kook Card Message footer element
     fn get_stratz_appriciation_element(&self) -> Element {
         Element {
             element_type: ELEMENT_TYPE_PLAIN_TEXT.to_string(),
-            content: "Powered by Stratz".to_string()
+            content: Some("Powered by Stratz".to_string()),
+            src: None
         }
     }
 
@@ -272,7 +698,7 @@ impl KookPublisher {
             MatchResult::None => "none"
         };
 
-        return card_theme.to_string();
+        card_theme.to_string()
     }
 
     /// Get players stats in string literal
@@ -280,18 +706,18 @@ impl KookPublisher {
     /// # Arguments
     /// 
     /// * `players_stats` - A vector of PlayerStats containing stats of a player
-    fn get_players_stats(&self, players_stats: &Vec<PlayerStats>) -> String {
+    fn get_players_stats(&self, players_stats: &[PlayerStats]) -> String {
         let mut result = String::new();
         for player_stats in players_stats.iter() {
             let line = format!(
                 "{} - {} - [{}/{}/{}]\n",
-                &player_stats.name, player_stats.hero_display_name, player_stats.kills, player_stats.deaths, player_stats.assists
+                transform_player_name(player_stats), player_stats.hero_display_name, player_stats.kills, player_stats.deaths, player_stats.assists
             );
 
             result.push_str(&line);
         }
 
-        return result;
+        result
     }
 
 }
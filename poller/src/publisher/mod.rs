@@ -1,4 +1,13 @@
 pub mod webhook;
+#[allow(clippy::module_inception)]
 pub mod publisher;
 pub mod kook;
+pub mod clash;
+pub mod digest;
+pub mod dry_run;
+pub mod highlights;
+pub mod scoreboard;
+pub mod streaks;
 pub mod utils;
+#[cfg(test)]
+pub mod fixtures;
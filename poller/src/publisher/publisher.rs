@@ -1,16 +1,36 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use lambda_runtime::Error;
-use crate::{provider::stratz, config};
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
+use crate::{
+    alert::Alert, provider::stratz, config::PlayersConfig, deadline::Deadline, error::PollerError, outcome::RunOutcome,
+    store::state::StateStore, report::summary::GuildReport, settings::{SinkSettings, TemplateSettings}
+};
+use super::dry_run::DryRunOutput;
 
-use super::{kook::KookPublisher, webhook::WebhookPublisher};
+use super::{
+    kook::KookPublisher, webhook::WebhookPublisher, scoreboard::ScoreboardRenderer,
+    highlights::{Highlight, detect_highlights},
+    clash::{Clash, record_head_to_head},
+    digest::split_digest,
+    streaks::{StreakEvent, record_streaks}
+};
 
-const MINIMUM_PLAYERS: usize = 1;
 const RADIANT: &str = "Radiant";
 const DIRE: &str = "Dire";
-const KOOK_TARGET_ID: &str = "3193188266865676";
+const MAX_DIGEST_ENTRIES: usize = 20;
+const SINK_KOOK: &str = "kook";
+const SINK_DISCORD: &str = "discord";
+const SCOREBOARD_FILE_NAME: &str = "scoreboard.png";
+const ANONYMOUS: &str = "Anonymous";
+const UNKNOWN_HERO: &str = "Unknown Hero";
+const UNKNOWN: &str = "UNKNOWN";
+
+/// The names of the destinations every match is published to
+pub const SINKS: [&str; 2] = [SINK_KOOK, SINK_DISCORD];
 
 /// Enum to match Match Result
-#[derive(Debug, PartialEq, Eq, Hash)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
 pub enum MatchResult {
     None,
     Victory,
@@ -18,17 +38,50 @@ pub enum MatchResult {
     Both
 }
 
+/// Enum to match which players are listed on the card
+#[derive(Debug, PartialEq, Eq)]
+pub enum PlayerList {
+    GuildMembers,
+    All
+}
+
+impl PlayerList {
+
+    /// Get the PlayerList from the `list` setting of the `[players]` section, `guild` or `all`
+    /// Otherwise, every player of the match is listed
+    pub fn from_config(players: &PlayersConfig) -> Result<PlayerList, Error> {
+        match players.list.value() {
+            None => Ok(PlayerList::All),
+            Some(value) => match value.trim().to_lowercase().as_str() {
+                "all" => Ok(PlayerList::All),
+                "guild" => Ok(PlayerList::GuildMembers),
+                _ => Err(format!("Invalid PLAYER_LIST: {}", value).into())
+            }
+        }
+    }
+
+}
+
 /// Struct to contain Player stats of a match
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct PlayerStats {
+    pub steam_account_id: Option<i64>,
+    pub is_guild_member: bool,
+    pub party_id: Option<u8>,
+    pub stack: Option<u8>,
+    pub is_victory: bool,
     pub hero_id: i16,
     pub hero_display_name: String,
     pub name: String,
     pub kills: u8,
     pub deaths: u8,
-    pub assists: u8
+    pub assists: u8,
+    pub networth: i64,
+    pub kill_times: Vec<i64>
 }
 
 /// Struct to contain data to be published
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct PublishRecord {
     pub match_id: String,
     pub guild_id: String,
@@ -39,66 +92,136 @@ pub struct PublishRecord {
     pub game_mode: stratz::api::GameMode,
     pub player_stats_radiant: Vec<PlayerStats>,
     pub player_stats_dire: Vec<PlayerStats>,
+    pub duration_seconds: i64,
     pub duration_field: String,
+    pub did_radiant_win: Option<bool>,
+    pub radiant_networth_leads: Vec<i64>,
+    pub highlights: Vec<Highlight>,
+    pub clash: Option<Clash>,
+    #[serde(default)]
+    pub streaks: Vec<StreakEvent>,
     pub end: chrono::DateTime<chrono::Utc>
 }
 
-/// Struct for the entry point of publishers
-pub struct Publisher;
+/// Struct for the entry point of publishers, sharing its HTTP client with every publisher
+pub struct Publisher {
+    pub client: reqwest::Client,
+    pub sinks: SinkSettings,
+    pub players: PlayerList,
+    pub templates: TemplateSettings,
+    /// Where payloads are written instead of being sent, None to send them
+    pub dry_run: Option<DryRunOutput>
+}
 
 impl Publisher {
 
-    /// Extract useful information to PublishRecord and pass it to different publishers, such as
-    /// - Discord Webhook
-    /// - Kook Bot
+    /// Extract useful information of a match to PublishRecord, keep the head-to-head records of guild members who
+    /// played against each other and extend the win and loss streaks of guild members. A match built again, as its
+    /// delivery failed or was deferred, is counted only once.
     /// 
     /// # Arguments
     /// 
+    /// - `store` - The state store keeping head-to-head records and streaks
+    /// - `guild_members` - The steam account ids of guild members
     /// - `guild_id` - The id of Dota2 guild
     /// - `guild_name` - The name of Dota2 guild
     /// - `guild_logo` - The logo url of Dota2 guild
     /// - `guild_match` - The match result of a Dota2 match
-    pub async fn publish(
+    pub async fn build_record(
+        &self,
+        store: &dyn StateStore,
+        guild_members: &HashSet<i64>,
         guild_id: i64,
         guild_name: &str,
         guild_logo: &str,
         guild_match: stratz::api::Match
-    ) -> Result<(), Error> {
-        let match_id = guild_match.id.unwrap();
-        let players = guild_match.players.unwrap();
-        if players.len() < MINIMUM_PLAYERS {
-            return Ok(());
+    ) -> Result<PublishRecord, Error> {
+        let mut publish_record = self.extract_record(guild_members, guild_id, guild_name, guild_logo, guild_match)?;
+        let match_id = publish_record.match_id.parse::<i64>()?;
+
+        // When guild members played against each other, keep their head-to-head records
+        if let Some(clash) = publish_record.clash.as_mut() {
+            let (winners, losers) = match clash.winning_side.as_str() {
+                RADIANT => (&publish_record.player_stats_radiant, &publish_record.player_stats_dire),
+                _ => (&publish_record.player_stats_dire, &publish_record.player_stats_radiant)
+            };
+            clash.head_to_head = record_head_to_head(store, winners, losers, match_id).await?;
+        }
+
+        // Extend the win and loss streaks of guild members and call out the notable ones
+        for players_stats in [&publish_record.player_stats_radiant, &publish_record.player_stats_dire] {
+            let streak_events = record_streaks(store, players_stats, match_id, self.templates.streak_threshold).await?;
+            publish_record.streaks.extend(streak_events);
         }
+
+        Ok(publish_record)
+    }
+
+    /// Extract useful information of a match to PublishRecord, without touching any stored state
+    /// 
+    /// Partial data is tolerated: unknown heroes, lobby types or game modes and missing K/D/A are published as such.
+    /// A match without its id, duration, end or the side of a player fails with a data quality error instead.
+    /// 
+    /// # Arguments
+    /// 
+    /// - `guild_members` - The steam account ids of guild members
+    /// - `guild_id` - The id of Dota2 guild
+    /// - `guild_name` - The name of Dota2 guild
+    /// - `guild_logo` - The logo url of Dota2 guild
+    /// - `guild_match` - The match result of a Dota2 match
+    pub fn extract_record(
+        &self,
+        guild_members: &HashSet<i64>,
+        guild_id: i64,
+        guild_name: &str,
+        guild_logo: &str,
+        guild_match: stratz::api::Match
+    ) -> Result<PublishRecord, Error> {
+        let match_id = guild_match.id.ok_or_else(|| missing("match id"))?;
+        let players = guild_match.players.ok_or_else(|| missing("players"))?;
     
-        let match_result = get_match_result(&players).unwrap();
-        let lobby_type = guild_match.lobby_type.unwrap();
-        let game_mode = guild_match.game_mode.unwrap();
+        let match_result = get_match_result(&players, guild_members)?;
+        let lobby_type = guild_match.lobby_type
+            .unwrap_or_else(|| stratz::api::LobbyType::Other(UNKNOWN.to_string()));
+        let game_mode = guild_match.game_mode
+            .unwrap_or_else(|| stratz::api::GameMode::Other(UNKNOWN.to_string()));
     
-        let duration_num = guild_match.duration_seconds.unwrap();
-        let duration = chrono::Duration::seconds(duration_num);
+        let duration_seconds = guild_match.duration_seconds.ok_or_else(|| missing("duration"))?;
+        let duration = chrono::Duration::seconds(duration_seconds);
     
-        let end_num = i64::try_from(guild_match.end_date_time.unwrap()).unwrap();
-        let end_naive_date_time = chrono::NaiveDateTime::from_timestamp(end_num, 0);
-        let end = chrono::DateTime::<chrono::Utc>::from_utc(end_naive_date_time, chrono::Utc);
+        let end_num = guild_match.end_date_time.ok_or_else(|| missing("end"))?;
+        let end = chrono::DateTime::<chrono::Utc>::from_timestamp(end_num, 0)
+            .ok_or_else(|| PollerError::DataQuality(format!("Invalid end of match: {}", end_num)))?;
         
         let mins = duration.num_seconds() / 60;
         let secs = duration.num_seconds() % 60;
         let duration_field = format!("{}:{:02}", &mins, &secs);
     
-        let players_by_team = get_players_by_team(&players).unwrap();
-        let radiant_players = players_by_team.get(&RADIANT.to_string()).unwrap();
+        let mut players_by_team = get_players_by_team(&players)?;
+        let radiant_players = players_by_team.remove(RADIANT).unwrap_or_default();
         let mut radiant_player_stats = Vec::new();
         for player in radiant_players.iter() {
-            radiant_player_stats.push(get_player_stats(player).unwrap())
+            radiant_player_stats.push(get_player_stats(player, guild_members, guild_match.did_radiant_win)?)
         }
     
-        let dire_players = players_by_team.get(&DIRE.to_string()).unwrap();
+        let dire_players = players_by_team.remove(DIRE).unwrap_or_default();
         let mut dire_player_stats = Vec::new();
         for player in dire_players.iter() {
-            dire_player_stats.push(get_player_stats(player).unwrap())
+            dire_player_stats.push(get_player_stats(player, guild_members, guild_match.did_radiant_win)?)
+        }
+
+        if self.players == PlayerList::GuildMembers {
+            radiant_player_stats.retain(|player_stats| player_stats.is_guild_member);
+            dire_player_stats.retain(|player_stats| player_stats.is_guild_member);
         }
+        assign_stacks(&mut radiant_player_stats, &mut dire_player_stats);
         
-        let publish_record = PublishRecord {
+        let radiant_networth_leads = guild_match.stats.as_ref()
+            .and_then(|stats| stats.radiant_networth_leads.as_ref())
+            .map(|leads| leads.iter().flatten().copied().collect())
+            .unwrap_or_default();
+
+        let mut publish_record = PublishRecord {
             match_id: match_id.to_string(),
             guild_id: guild_id.to_string(),
             guild_name: guild_name.to_string(),
@@ -108,35 +231,401 @@ impl Publisher {
             game_mode,
             player_stats_radiant: radiant_player_stats,
             player_stats_dire: dire_player_stats,
+            duration_seconds,
             duration_field,
+            did_radiant_win: guild_match.did_radiant_win,
+            radiant_networth_leads,
+            highlights: Vec::new(),
+            clash: None,
+            streaks: Vec::new(),
             end
         };
-    
-        let kook_publisher = KookPublisher {
-            client: reqwest::Client::new()
-        };
-        kook_publisher.publish(&KOOK_TARGET_ID, &publish_record).await?;
-    
-        let webhook_publisher = WebhookPublisher {
-            client: webhook::client::WebhookClient::new(&config::discord_webhook_url())
-        };
-        webhook_publisher.publish(&publish_record).await?;
+        publish_record.highlights = detect_highlights(&publish_record, &self.templates.highlights);
+
+        // When guild members played against each other, split them per side
+        publish_record.clash = Clash::from_teams(&publish_record.player_stats_radiant, &publish_record.player_stats_dire);
+
+        Ok(publish_record)
+    }
+
+    /// Pass `publish_records` to different publishers, such as
+    /// - Discord Webhook
+    /// - Kook Bot
+    /// 
+    /// Matches are published one card each, unless the digest options batch them into a single summary. Once the
+    /// `deadline` is reached, the matches left are deferred to the next run.
+    /// 
+    /// # Arguments
+    /// 
+    /// - `store` - The state store keeping the delivery ledger
+    /// - `publish_records` - The data POJOs to be published, oldest first
+    /// - `deadline` - The time the run stops publishing
+    /// - `outcome` - The outcome of the run, recording the deliveries which failed or were deferred
+    pub async fn publish_all(
+        &self,
+        store: &dyn StateStore,
+        publish_records: &[PublishRecord],
+        deadline: &Deadline,
+        outcome: &mut RunOutcome
+    ) -> Result<(), Error> {
+        let (single_records, digest_records) = split_digest(publish_records, &self.templates.digest);
+        for publish_record in single_records {
+            if deadline.is_reached(chrono::Utc::now()) {
+                outcome.record_deferred(&[&publish_record.match_id]);
+                continue;
+            }
+            self.publish(store, publish_record, outcome).await?;
+        }
+        if !digest_records.is_empty() {
+            if deadline.is_reached(chrono::Utc::now()) {
+                outcome.record_deferred(&match_ids(&digest_records));
+                return Ok(());
+            }
+            self.publish_digest(store, &digest_records, outcome).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Pass a single `publish_record` to different publishers, skipping the ones the delivery ledger says it was
+    /// already delivered to. A publisher failing is recorded in `outcome` and does not stop the other ones.
+    /// 
+    /// # Arguments
+    /// 
+    /// - `store` - The state store keeping the delivery ledger
+    /// - `publish_record` - The data POJO to be published
+    /// - `outcome` - The outcome of the run, recording the deliveries which failed
+    pub async fn publish(
+        &self,
+        store: &dyn StateStore,
+        publish_record: &PublishRecord,
+        outcome: &mut RunOutcome
+    ) -> Result<(), Error> {
+        let match_id = &publish_record.match_id;
+
+        // Render the scoreboard once and attach it to every destination, the message is sent without it otherwise
+        let scoreboard = self.render_scoreboard(publish_record);
+        if let (Some(output), Some(scoreboard)) = (&self.dry_run, &scoreboard) {
+            output.write_png(&format!("{}.scoreboard", match_id), scoreboard)?;
+        }
+        for sink in SINKS {
+            if store.is_delivered(match_id, sink).await? {
+                continue;
+            }
+            match self.deliver(sink, publish_record, scoreboard.as_deref()).await {
+                Ok(_) => {
+                    log::info!(match_id = match_id.as_str(), sink = sink; "Delivered match {} to {}", match_id, sink);
+                    outcome.record_delivery(sink);
+                    store.mark_delivered(match_id, sink).await?
+                },
+                Err(e) => outcome.record_failure(sink, &[match_id], &e)
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Pass a single `publish_record` to `sinks` again, whatever the delivery ledger says, e.g. after a message was
+    /// deleted by mistake. A publisher failing is recorded in `outcome` and does not stop the other ones.
+    /// 
+    /// # Arguments
+    /// 
+    /// - `store` - The state store keeping the delivery ledger
+    /// - `publish_record` - The data POJO to be published
+    /// - `sinks` - The names of the destinations, such as `kook` or `discord`
+    /// - `outcome` - The outcome of the run, recording the deliveries which failed
+    pub async fn republish(
+        &self,
+        store: &dyn StateStore,
+        publish_record: &PublishRecord,
+        sinks: &[&str],
+        outcome: &mut RunOutcome
+    ) -> Result<(), Error> {
+        let match_id = &publish_record.match_id;
+        let scoreboard = self.render_scoreboard(publish_record);
+        for sink in sinks {
+            match self.deliver(sink, publish_record, scoreboard.as_deref()).await {
+                Ok(_) => {
+                    log::info!(match_id = match_id.as_str(), sink = sink; "Delivered match {} to {}", match_id, sink);
+                    outcome.record_delivery(sink);
+                    store.mark_delivered(match_id, sink).await?
+                },
+                Err(e) => outcome.record_failure(sink, &[match_id], &e)
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Get the message every publisher would send for `publish_record`, by sink, without sending anything. The
+    /// scoreboard is only uploaded when sending, so Kook shows it by its file name.
+    /// 
+    /// # Arguments
+    /// 
+    /// - `publish_record` - The data POJO to be published
+    pub fn preview(&self, publish_record: &PublishRecord) -> Result<Vec<(&'static str, Value)>, Error> {
+        SINKS.iter()
+            .map(|sink| Ok((*sink, self.payload(sink, publish_record)?)))
+            .collect()
+    }
+
+    /// Get the message `sink` would receive for `publish_record`
+    /// 
+    /// # Arguments
+    /// 
+    /// - `sink` - The name of the destination, `kook` or `discord`
+    /// - `publish_record` - The data POJO to be published
+    fn payload(&self, sink: &str, publish_record: &PublishRecord) -> Result<Value, Error> {
+        match sink {
+            SINK_KOOK => {
+                let kook_publisher = self.kook_publisher();
+                Ok(kook_publisher.preview(&self.sinks.kook_target_id, publish_record, Some(SCOREBOARD_FILE_NAME)))
+            },
+            SINK_DISCORD => self.webhook_publisher(&self.sinks.discord_webhook_url).preview(publish_record, true),
+            _ => Err(format!("Invalid sink: {}", sink).into())
+        }
+    }
+
+    /// Deliver a single `publish_record` with its `scoreboard` to `sink`, or write what it would receive in a dry run
+    /// 
+    /// # Arguments
+    /// 
+    /// - `sink` - The name of the destination, `kook` or `discord`
+    /// - `publish_record` - The data POJO to be published
+    /// - `scoreboard` - The PNG scoreboard of the match, if it could be rendered
+    async fn deliver(
+        &self,
+        sink: &str,
+        publish_record: &PublishRecord,
+        scoreboard: Option<&[u8]>
+    ) -> Result<(), Error> {
+        if let Some(output) = &self.dry_run {
+            let name = format!("{}.{}", publish_record.match_id, sink);
+            return output.write(&name, &self.payload(sink, publish_record)?);
+        }
+
+        match sink {
+            SINK_KOOK => {
+                let kook_publisher = self.kook_publisher();
+                kook_publisher.publish(&self.sinks.kook_target_id, publish_record, scoreboard).await
+            },
+            SINK_DISCORD => {
+                let webhook_publisher = self.webhook_publisher(&self.sinks.discord_webhook_url);
+                webhook_publisher.publish(publish_record, scoreboard).await
+            },
+            _ => Err(format!("Invalid sink: {}", sink).into())
+        }
+    }
+
+    /// Pass a guild summary `report` to different publishers, posting to the report channels when configured
+    /// 
+    /// # Arguments
+    /// 
+    /// - `report` - The guild summary report
+    pub async fn publish_report(&self, report: &GuildReport) -> Result<(), Error> {
+        let kook_publisher = self.kook_publisher();
+        let webhook_publisher = self.webhook_publisher(&self.sinks.report_discord_webhook_url);
+        if let Some(output) = &self.dry_run {
+            let name = format!("report-{}", report.start.format("%Y-%m-%d"));
+            output.write(
+                &format!("{}.{}", name, SINK_KOOK),
+                &kook_publisher.preview_report(&self.sinks.report_kook_target_id, report)
+            )?;
+            return output.write(&format!("{}.{}", name, SINK_DISCORD), &webhook_publisher.preview_report(report)?);
+        }
+
+        kook_publisher.publish_report(&self.sinks.report_kook_target_id, report).await
+            .map_err(|e| PollerError::sink(SINK_KOOK, e))?;
+        webhook_publisher.publish_report(report).await
+            .map_err(|e| PollerError::sink(SINK_DISCORD, e))?;
 
         Ok(())
     }
+
+    /// Send an operational `alert` to every configured admin destination. A destination failing to receive it is
+    /// logged rather than returned, so that one broken sink does not keep the alert from the others.
+    ///
+    /// # Arguments
+    ///
+    /// - `alert` - The alert about a component
+    pub async fn publish_alert(&self, alert: &Alert) {
+        if let Some(target_id) = &self.sinks.alert_kook_target_id {
+            if let Err(e) = self.kook_publisher().publish_alert(target_id, alert).await {
+                log::error!(sink = SINK_KOOK; "Failed to send alert \"{}\": {}", alert.text(), e);
+            }
+        }
+        if let Some(url) = &self.sinks.alert_discord_webhook_url {
+            if let Err(e) = self.webhook_publisher(url).publish_alert(alert).await {
+                log::error!(sink = SINK_DISCORD; "Failed to send alert \"{}\": {}", alert.text(), e);
+            }
+        }
+    }
+
+    /// Pass several `publish_records` to different publishers as a single compact summary, leaving out of each
+    /// destination the matches the delivery ledger says were already delivered to it
+    /// 
+    /// # Arguments
+    /// 
+    /// - `store` - The state store keeping the delivery ledger
+    /// - `publish_records` - The data POJOs to be summarized, oldest first
+    /// - `outcome` - The outcome of the run, recording the deliveries which failed
+    pub async fn publish_digest(
+        &self,
+        store: &dyn StateStore,
+        publish_records: &[&PublishRecord],
+        outcome: &mut RunOutcome
+    ) -> Result<(), Error> {
+        // Discord caps an embed at 25 fields, so long sessions are split over several digests
+        for chunk in publish_records.chunks(MAX_DIGEST_ENTRIES) {
+            for sink in SINKS {
+                let pending = undelivered(store, chunk, sink).await?;
+                if pending.is_empty() {
+                    continue;
+                }
+                match self.deliver_digest(sink, &pending).await {
+                    Ok(_) => {
+                        outcome.record_delivery(sink);
+                        mark_all_delivered(store, &pending, sink).await?
+                    },
+                    Err(e) => outcome.record_failure(sink, &match_ids(&pending), &e)
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Deliver the digest of `publish_records` to `sink`, or write what it would receive in a dry run
+    /// 
+    /// # Arguments
+    /// 
+    /// - `sink` - The name of the destination, `kook` or `discord`
+    /// - `publish_records` - The data POJOs to be summarized, oldest first
+    async fn deliver_digest(&self, sink: &str, publish_records: &[&PublishRecord]) -> Result<(), Error> {
+        let kook_publisher = self.kook_publisher();
+        let webhook_publisher = self.webhook_publisher(&self.sinks.discord_webhook_url);
+        if let Some(output) = &self.dry_run {
+            let payload = match sink {
+                SINK_KOOK => kook_publisher.preview_digest(&self.sinks.kook_target_id, publish_records),
+                _ => webhook_publisher.preview_digest(publish_records)?
+            };
+            let name = format!("digest-{}.{}", match_ids(publish_records).join("-"), sink);
+            return match payload {
+                Some(payload) => output.write(&name, &payload),
+                None => Ok(())
+            };
+        }
+
+        match sink {
+            SINK_KOOK => kook_publisher.publish_digest(&self.sinks.kook_target_id, publish_records).await,
+            SINK_DISCORD => webhook_publisher.publish_digest(publish_records).await,
+            _ => Err(format!("Invalid sink: {}", sink).into())
+        }
+    }
+
+    /// Get the PNG scoreboard of `publish_record` rendered with the configured hero portraits
+    /// Otherwise, log the failure and return `None`, so the message is still sent without the image
+    fn render_scoreboard(&self, publish_record: &PublishRecord) -> Option<Vec<u8>> {
+        let scoreboard_renderer = ScoreboardRenderer {
+            hero_assets_dir: self.templates.hero_assets_dir.clone()
+        };
+
+        match scoreboard_renderer.render(publish_record) {
+            Ok(scoreboard) => Some(scoreboard),
+            Err(e) => {
+                let match_id = publish_record.match_id.as_str();
+                log::error!(match_id = match_id; "Failed to render the scoreboard of match {}: {}", match_id, e);
+                None
+            }
+        }
+    }
+
+    /// Build the publisher of the Kook bot, sharing the HTTP client
+    fn kook_publisher(&self) -> KookPublisher {
+        KookPublisher {
+            client: self.client.clone(),
+            token: self.sinks.kook_token.clone()
+        }
+    }
+
+    /// Build the publisher of the Discord webhook at `url`, sharing the HTTP client
+    fn webhook_publisher(&self, url: &str) -> WebhookPublisher {
+        WebhookPublisher {
+            client: self.client.clone(),
+            url: url.to_string()
+        }
+    }
+}
+
+/// Get the records among `publish_records` not delivered to `sink` yet
+async fn undelivered<'a>(
+    store: &dyn StateStore,
+    publish_records: &[&'a PublishRecord],
+    sink: &str
+) -> Result<Vec<&'a PublishRecord>, Error> {
+    let mut pending = Vec::new();
+    for publish_record in publish_records.iter() {
+        if !store.is_delivered(&publish_record.match_id, sink).await? {
+            pending.push(*publish_record);
+        }
+    }
+
+    Ok(pending)
+}
+
+/// Record in the delivery ledger that every record of `publish_records` was delivered to `sink`
+async fn mark_all_delivered(store: &dyn StateStore, publish_records: &[&PublishRecord], sink: &str) -> Result<(), Error> {
+    for publish_record in publish_records.iter() {
+        store.mark_delivered(&publish_record.match_id, sink).await?;
+    }
+
+    Ok(())
+}
+
+/// Get the match ids of `publish_records`
+fn match_ids<'a>(publish_records: &[&'a PublishRecord]) -> Vec<&'a str> {
+    publish_records.iter().map(|publish_record| publish_record.match_id.as_str()).collect()
+}
+
+/// Build the data quality error of a match missing `what`
+fn missing(what: &str) -> PollerError {
+    PollerError::DataQuality(format!("Missing {}", what))
+}
+
+/// Check whether `player` is a guild member. When the guild members are unknown, every player is considered one.
+/// 
+/// # Arguments
+/// 
+/// * `player` - The player struct from Stratz API
+/// * `guild_members` - The steam account ids of guild members
+pub fn is_guild_member(player: &stratz::api::Player, guild_members: &HashSet<i64>) -> bool {
+    if guild_members.is_empty() {
+        return true;
+    }
+
+    player.steam_account.as_ref()
+        .and_then(|steam_account| steam_account.id)
+        .map(|steam_account_id| guild_members.contains(&steam_account_id))
+        .unwrap_or(false)
 }
 
-/// Transform Stratz MatchResult to Rust tuples
+/// Transform Stratz MatchResult to Rust tuples, from the point of view of guild members
 /// 
 /// # Arguments
 /// 
 /// * `players` - The vector of players in the match
-fn get_match_result(players: &Vec<Option<stratz::api::Player>>) -> Result<MatchResult, Error> {
+/// * `guild_members` - The steam account ids of guild members
+pub fn get_match_result(
+    players: &[Option<stratz::api::Player>],
+    guild_members: &HashSet<i64>
+) -> Result<MatchResult, Error> {
     let mut is_victory = false;
     let mut is_defeat = false;
-    for player in players.iter() {
-        let player = player.clone().unwrap();
-        let player_result = player.is_victory.unwrap();
+    for player in players.iter().flatten() {
+        if !is_guild_member(player, guild_members) {
+            continue;
+        }
+        let player_result = player.is_victory.ok_or_else(|| missing("result of a guild member"))?;
         match player_result {
             true => is_victory = true,
             false => is_defeat = true,
@@ -157,15 +646,14 @@ fn get_match_result(players: &Vec<Option<stratz::api::Player>>) -> Result<MatchR
 /// # Arguments
 /// 
 /// * `players` - The vector of players in the match
-fn get_players_by_team(players: &Vec<Option<stratz::api::Player>>) -> Result<HashMap<String, Vec<stratz::api::Player>>, Error> {
+fn get_players_by_team(players: &[Option<stratz::api::Player>]) -> Result<HashMap<String, Vec<stratz::api::Player>>, Error> {
     let mut radiant_players = Vec::new();
     let mut dire_players = Vec::new();
-    for player in players.iter() {
-        let player = player.clone().unwrap();
-        let player_team = player.is_radiant.unwrap();
+    for player in players.iter().flatten() {
+        let player_team = player.is_radiant.ok_or_else(|| missing("side of a player"))?;
         match player_team {
-            true => radiant_players.push(player),
-            false => dire_players.push(player)
+            true => radiant_players.push(player.clone()),
+            false => dire_players.push(player.clone())
         }
     }
 
@@ -177,20 +665,257 @@ fn get_players_by_team(players: &Vec<Option<stratz::api::Player>>) -> Result<Has
     ))
 }
 
-/// Extract data from Stratz API player struct and return as a PlayerStats struct.
+/// Number stacks of players who queued together, in the order they are listed, and list players of the same
+/// stack next to each other. Parties of a single listed player are not stacks.
+/// 
+/// # Arguments
+/// 
+/// * `radiant` - The stats of players on Radiant
+/// * `dire` - The stats of players on Dire
+fn assign_stacks(radiant: &mut [PlayerStats], dire: &mut [PlayerStats]) {
+    let mut party_sizes: HashMap<u8, usize> = HashMap::new();
+    for party_id in radiant.iter().chain(dire.iter()).filter_map(|player_stats| player_stats.party_id) {
+        *party_sizes.entry(party_id).or_default() += 1;
+    }
+
+    let mut stacks: HashMap<u8, u8> = HashMap::new();
+    for player_stats in radiant.iter_mut().chain(dire.iter_mut()) {
+        let party_id = match player_stats.party_id {
+            Some(party_id) if party_sizes.get(&party_id).copied().unwrap_or_default() > 1 => party_id,
+            _ => continue
+        };
+        let next_stack = stacks.len() as u8 + 1;
+        player_stats.stack = Some(*stacks.entry(party_id).or_insert(next_stack));
+    }
+
+    radiant.sort_by_key(|player_stats| player_stats.stack.unwrap_or(u8::MAX));
+    dire.sort_by_key(|player_stats| player_stats.stack.unwrap_or(u8::MAX));
+}
+
+/// Extract data from Stratz API player struct and return as a PlayerStats struct. Players hiding their profile
+/// are named Anonymous, unknown heroes are named Unknown Hero and missing K/D/A count as zero. A missing result is
+/// derived from the side of the player and the winning side, and counts as a defeat when neither is known: only the
+/// results of guild members are required, by `get_match_result`.
 /// 
 /// # Arguments
 /// 
 /// * `player` - The player struct from Stratz API
-fn get_player_stats(player: &stratz::api::Player) -> Result<PlayerStats, Error> {
-    let hero = player.hero.as_ref().unwrap();
+/// * `guild_members` - The steam account ids of guild members
+/// * `did_radiant_win` - Whether Radiant won the match, if known
+fn get_player_stats(
+    player: &stratz::api::Player,
+    guild_members: &HashSet<i64>,
+    did_radiant_win: Option<bool>
+) -> Result<PlayerStats, Error> {
+    let hero = player.hero.as_ref();
+    let is_victory = player.is_victory
+        .or_else(|| Some(player.is_radiant? == did_radiant_win?))
+        .unwrap_or_default();
+    let steam_account = player.steam_account.as_ref().filter(|steam_account| !steam_account.is_anonymous);
+    let name = steam_account
+        .and_then(|steam_account| steam_account.name.as_ref())
+        .map(|name| name.to_string())
+        .unwrap_or_else(|| ANONYMOUS.to_string());
 
     Ok(PlayerStats { 
-        hero_id: hero.id.unwrap(),
-        hero_display_name: hero.display_name.as_ref().unwrap().to_string(),
-        name: player.steam_account.as_ref().unwrap().name.as_ref().unwrap().to_string(),
-        kills: player.kills.unwrap(),
-        deaths: player.deaths.unwrap(),
-        assists: player.assists.unwrap()
+        steam_account_id: steam_account.and_then(|steam_account| steam_account.id),
+        is_guild_member: is_guild_member(player, guild_members),
+        party_id: player.party_id,
+        stack: None,
+        is_victory,
+        hero_id: hero.and_then(|hero| hero.id).unwrap_or_default(),
+        hero_display_name: hero.and_then(|hero| hero.display_name.clone()).unwrap_or_else(|| UNKNOWN_HERO.to_string()),
+        name,
+        kills: player.kills.unwrap_or_default(),
+        deaths: player.deaths.unwrap_or_default(),
+        assists: player.assists.unwrap_or_default(),
+        networth: player.networth.unwrap_or_default(),
+        kill_times: player.stats.as_ref()
+            .and_then(|stats| stats.kill_events.as_ref())
+            .map(|kill_events| kill_events.iter().flatten().map(|kill_event| kill_event.time).collect())
+            .unwrap_or_default()
     })
 }
+
+#[cfg(test)]
+mod tests {
+
+    use std::collections::HashSet;
+    use serde_json::json;
+    use crate::error::PollerError;
+    use crate::provider::stratz;
+    use crate::{deadline::Deadline, outcome::RunOutcome};
+    use crate::publisher::{dry_run::DryRunOutput, fixtures::{failing_server, guild_match, player_stats, publish_record, publisher}, streaks::Streak};
+    use crate::store::{memory::MemoryStore, state::StateStore};
+    use super::StreakEvent;
+    use super::{MatchResult, PlayerList, assign_stacks};
+
+    #[test]
+    fn test_extract_partial_record() {
+        let players = json!([
+            {
+                "isVictory": true, "isRadiant": true, "kills": null, "deaths": 2, "assists": 9, "hero": null,
+                "steamAccount": { "id": 86000, "name": "Alice", "isAnonymous": false }
+            },
+            null,
+            {
+                "isVictory": false, "isRadiant": false, "kills": 3, "deaths": 5, "assists": 1,
+                "hero": { "id": 14, "displayName": "Pudge" }, "steamAccount": null
+            }
+        ]);
+        let guild_members = HashSet::from([86000]);
+        let publisher = publisher(PlayerList::All);
+        let mut partial_match = guild_match(players);
+        partial_match.lobby_type = None;
+        let record = publisher.extract_record(&guild_members, 117311, "Guild", "logo", partial_match).unwrap();
+        assert_eq!(record.match_result, MatchResult::Victory);
+        assert_eq!(record.lobby_type, stratz::api::LobbyType::Other("UNKNOWN".to_string()));
+        assert_eq!(record.player_stats_radiant[0].hero_display_name, "Unknown Hero");
+        assert_eq!(record.player_stats_radiant[0].kills, 0);
+        assert_eq!(record.player_stats_dire[0].name, "Anonymous");
+    }
+
+    #[test]
+    fn test_extract_record_without_result_of_other_players() {
+        let players = json!([
+            {
+                "isVictory": true, "isRadiant": true, "hero": { "id": 86, "displayName": "Rubick" },
+                "steamAccount": { "id": 86000, "name": "Alice", "isAnonymous": false }
+            },
+            { "isVictory": null, "isRadiant": true, "hero": { "id": 11, "displayName": "Shadow Fiend" } },
+            { "isVictory": null, "isRadiant": false, "hero": { "id": 14, "displayName": "Pudge" } }
+        ]);
+        let guild_members = HashSet::from([86000]);
+        let publisher = publisher(PlayerList::All);
+        let record = publisher.extract_record(&guild_members, 117311, "Guild", "logo", guild_match(players.clone()))
+            .unwrap();
+        assert_eq!(record.match_result, MatchResult::Victory);
+        assert!(record.player_stats_radiant[1].is_victory);
+        assert!(!record.player_stats_dire[0].is_victory);
+
+        let mut unfinished_match = guild_match(players);
+        unfinished_match.did_radiant_win = None;
+        let record = publisher.extract_record(&guild_members, 117311, "Guild", "logo", unfinished_match).unwrap();
+        assert!(!record.player_stats_radiant[1].is_victory);
+
+        let players = json!([{
+            "isVictory": null, "isRadiant": true, "steamAccount": { "id": 86000, "name": "Alice", "isAnonymous": false }
+        }]);
+        let error = publisher.extract_record(&guild_members, 117311, "Guild", "logo", guild_match(players)).unwrap_err();
+        assert_eq!(error.to_string(), "Data quality error: Missing result of a guild member");
+    }
+
+    #[test]
+    fn test_extract_broken_record() {
+        let players = json!([{ "isVictory": true, "isRadiant": null, "hero": { "id": 14, "displayName": "Pudge" } }]);
+        let publisher = publisher(PlayerList::All);
+        let error = publisher.extract_record(&HashSet::new(), 117311, "Guild", "logo", guild_match(players)).unwrap_err();
+        assert!(PollerError::is_match_failure(&error));
+
+        let mut broken_match = guild_match(json!([]));
+        broken_match.end_date_time = None;
+        let error = publisher.extract_record(&HashSet::new(), 117311, "Guild", "logo", broken_match).unwrap_err();
+        assert_eq!(error.to_string(), "Data quality error: Missing end");
+    }
+
+    #[test]
+    fn test_assign_stacks() {
+        let mut radiant = vec![
+            player_stats(86, "Rubick", "Alice", (6, 5, 16), 11230),
+            player_stats(11, "Shadow Fiend", "Bob", (16, 4, 8), 24510),
+            player_stats(5, "Crystal Maiden", "Carol", (1, 7, 12), 7000)
+        ];
+        let mut dire = vec![
+            player_stats(14, "Pudge", "Dave", (3, 9, 7), 9870),
+            player_stats(13, "Puck", "Erin", (8, 3, 9), 15000)
+        ];
+        radiant[0].party_id = Some(2);
+        radiant[1].party_id = Some(0);
+        radiant[2].party_id = Some(2);
+        dire[0].party_id = Some(1);
+        dire[1].party_id = Some(3);
+
+        assign_stacks(&mut radiant, &mut dire);
+        let names: Vec<(&str, Option<u8>)> = radiant.iter()
+            .map(|player_stats| (player_stats.name.as_str(), player_stats.stack))
+            .collect();
+        assert_eq!(names, vec![("Alice", Some(1)), ("Carol", Some(1)), ("Bob", None)]);
+        assert!(dire.iter().all(|player_stats| player_stats.stack.is_none()));
+    }
+
+    #[tokio::test]
+    async fn test_dry_run_digest() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut publisher = publisher(PlayerList::All);
+        publisher.dry_run = Some(DryRunOutput::Directory(dir.path().to_path_buf()));
+
+        let mut second = publish_record(MatchResult::Defeat);
+        second.match_id = "6789012346".to_string();
+        let first = publish_record(MatchResult::Victory);
+        let store = MemoryStore::default();
+        let mut outcome = RunOutcome::default();
+        publisher.publish_digest(&store, &[&first, &second], &mut outcome).await.unwrap();
+
+        assert!(!outcome.has_failures());
+        for sink in ["kook", "discord"] {
+            assert!(dir.path().join(format!("digest-6789012345-6789012346.{}.json", sink)).exists());
+            assert!(store.is_delivered("6789012346", sink).await.unwrap());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_build_record_again_after_failed_delivery() {
+        let players = json!([
+            {
+                "isVictory": true, "isRadiant": true, "kills": 6, "deaths": 5, "assists": 16,
+                "hero": { "id": 86, "displayName": "Rubick" },
+                "steamAccount": { "id": 86000, "name": "Alice", "isAnonymous": false }
+            },
+            {
+                "isVictory": false, "isRadiant": false, "kills": 3, "deaths": 9, "assists": 7,
+                "hero": { "id": 14, "displayName": "Pudge" },
+                "steamAccount": { "id": 86001, "name": "Bob", "isAnonymous": false }
+            }
+        ]);
+        let guild_members = HashSet::from([86000, 86001]);
+        let mut publisher = publisher(PlayerList::All);
+        publisher.sinks.discord_webhook_url = failing_server().await;
+        let store = MemoryStore::default();
+        let streak = Streak { current: 2, best_win: 2, best_loss: 0, last_match_id: 6789012344, previous: 1 };
+        store.save_streak(86000, &streak).await.unwrap();
+        // Only Discord is left, and it fails
+        store.mark_delivered("6789012345", "kook").await.unwrap();
+
+        for _ in 0..2 {
+            let guild_match = guild_match(players.clone());
+            let record = publisher.build_record(&store, &guild_members, 117311, "Guild", "logo", guild_match)
+                .await
+                .unwrap();
+            let mut outcome = RunOutcome::default();
+            publisher.publish(&store, &record, &mut outcome).await.unwrap();
+            assert!(outcome.is_pending("6789012345"));
+
+            let head_to_head = &record.clash.unwrap().head_to_head[0];
+            assert_eq!((head_to_head.winner_wins, head_to_head.loser_wins), (1, 0));
+            assert_eq!(record.streaks, vec![StreakEvent::WinStreak { name: "Alice".to_string(), games: 3, best: 3 }]);
+        }
+        assert_eq!(store.get_streak(86000).await.unwrap().current, 3);
+        assert_eq!(store.get_streak(86001).await.unwrap().current, -1);
+        assert_eq!(store.increment_head_to_head(86000, 86001, 6789012345).await.unwrap(), (1, 0));
+    }
+
+    #[tokio::test]
+    async fn test_publish_all_past_deadline() {
+        let publisher = publisher(PlayerList::All);
+        let store = MemoryStore::default();
+        let mut outcome = RunOutcome::default();
+        let deadline = Deadline { stop_at: Some(chrono::Utc::now()) };
+        publisher.publish_all(&store, &[publish_record(MatchResult::Victory)], &deadline, &mut outcome).await.unwrap();
+
+        assert_eq!(outcome.deferred, 1);
+        assert!(outcome.is_pending("6789012345"));
+        assert!(!outcome.has_failures());
+        assert!(!store.is_delivered("6789012345", "kook").await.unwrap());
+    }
+
+}
@@ -0,0 +1,368 @@
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+
+use ab_glyph::{Font, FontRef, PxScale, ScaleFont, point};
+use image::{imageops, DynamicImage, ImageOutputFormat, Rgba, RgbaImage};
+use lambda_runtime::Error;
+use super::{publisher::{PublishRecord, MatchResult, PlayerStats},
+    utils::{transform_match_result, transform_lobby_type, transform_game_mode}
+};
+
+const FONT_REGULAR: &[u8] = include_bytes!("../../assets/fonts/DejaVuSansMono.ttf");
+const FONT_BOLD: &[u8] = include_bytes!("../../assets/fonts/DejaVuSansMono-Bold.ttf");
+
+const WIDTH: u32 = 720;
+const PADDING: u32 = 16;
+const BANNER_HEIGHT: u32 = 72;
+const TEAM_HEADER_HEIGHT: u32 = 32;
+const ROW_HEIGHT: u32 = 44;
+const FOOTER_HEIGHT: u32 = 28;
+const PORTRAIT_WIDTH: u32 = 64;
+const PORTRAIT_HEIGHT: u32 = 36;
+
+const COLUMN_NAME: u32 = PADDING + PORTRAIT_WIDTH + 12;
+const COLUMN_HERO: u32 = 300;
+const COLUMN_KDA: u32 = 500;
+const COLUMN_NETWORTH: u32 = 620;
+
+const NAME_MAX_CHARS: usize = 18;
+const HERO_MAX_CHARS: usize = 16;
+
+const BACKGROUND: Rgba<u8> = Rgba([24, 26, 31, 255]);
+const ROW_STRIPE: Rgba<u8> = Rgba([31, 34, 40, 255]);
+const TEXT_PRIMARY: Rgba<u8> = Rgba([236, 239, 244, 255]);
+const TEXT_SECONDARY: Rgba<u8> = Rgba([150, 156, 168, 255]);
+const NETWORTH_GOLD: Rgba<u8> = Rgba([230, 190, 80, 255]);
+const RADIANT_GREEN: Rgba<u8> = Rgba([102, 187, 106, 255]);
+const DIRE_RED: Rgba<u8> = Rgba([229, 57, 53, 255]);
+const STACK_COLORS: [Rgba<u8>; 4] = [
+    Rgba([66, 165, 245, 255]),
+    Rgba([171, 71, 188, 255]),
+    Rgba([255, 167, 38, 255]),
+    Rgba([38, 198, 218, 255])
+];
+
+/// Renderer turning a `PublishRecord` into a PNG scoreboard
+pub struct ScoreboardRenderer {
+    pub hero_assets_dir: PathBuf
+}
+
+impl ScoreboardRenderer {
+
+    /// Render `publish_record` as a scoreboard and return the encoded PNG bytes
+    ///
+    /// Hero portraits are read from `hero_assets_dir/<hero_id>.png`. A hero without a portrait in the asset pack is
+    /// drawn as a coloured tile with the initials of the hero instead.
+    ///
+    /// # Arguments
+    ///
+    /// * `publish_record` - The data POJO to be rendered
+    pub fn render(&self, publish_record: &PublishRecord) -> Result<Vec<u8>, Error> {
+        let canvas = self.draw(publish_record)?;
+
+        let mut bytes = Vec::new();
+        DynamicImage::ImageRgba8(canvas).write_to(&mut Cursor::new(&mut bytes), ImageOutputFormat::Png)?;
+
+        Ok(bytes)
+    }
+
+    /// Draw the scoreboard of `publish_record` on a new canvas
+    fn draw(&self, publish_record: &PublishRecord) -> Result<RgbaImage, Error> {
+        let regular = FontRef::try_from_slice(FONT_REGULAR)?;
+        let bold = FontRef::try_from_slice(FONT_BOLD)?;
+
+        let teams = [
+            ("Radiant", RADIANT_GREEN, &publish_record.player_stats_radiant),
+            ("Dire", DIRE_RED, &publish_record.player_stats_dire)
+        ];
+        let mut height = BANNER_HEIGHT + FOOTER_HEIGHT;
+        for (_, _, players_stats) in teams.iter().filter(|(_, _, players_stats)| !players_stats.is_empty()) {
+            height += TEAM_HEADER_HEIGHT + ROW_HEIGHT * players_stats.len() as u32;
+        }
+
+        let mut canvas = RgbaImage::from_pixel(WIDTH, height, BACKGROUND);
+
+        // Result banner
+        fill_rect(&mut canvas, 0, 0, WIDTH, BANNER_HEIGHT, banner_color(&publish_record.match_result));
+        let title = format!(
+            "{} - {} - {}",
+            transform_match_result(&publish_record.match_result),
+            transform_lobby_type(&publish_record.lobby_type),
+            transform_game_mode(&publish_record.game_mode)
+        );
+        draw_text(&mut canvas, &bold, 26.0, PADDING, 12, &title, TEXT_PRIMARY);
+        let subtitle = format!(
+            "{} · Match {} · {}",
+            publish_record.guild_name, publish_record.match_id, publish_record.duration_field
+        );
+        draw_text(&mut canvas, &regular, 16.0, PADDING, 44, &subtitle, TEXT_PRIMARY);
+
+        // Team sections
+        let mut y = BANNER_HEIGHT;
+        for (team_name, team_color, players_stats) in teams.iter() {
+            if players_stats.is_empty() {
+                continue;
+            }
+
+            fill_rect(&mut canvas, 0, y, 6, TEAM_HEADER_HEIGHT, *team_color);
+            draw_text(&mut canvas, &bold, 18.0, PADDING, y + 7, team_name, *team_color);
+            draw_text(&mut canvas, &regular, 14.0, COLUMN_KDA, y + 9, "K/D/A", TEXT_SECONDARY);
+            draw_text(&mut canvas, &regular, 14.0, COLUMN_NETWORTH, y + 9, "NET", TEXT_SECONDARY);
+            y += TEAM_HEADER_HEIGHT;
+
+            for (index, player_stats) in players_stats.iter().enumerate() {
+                if index % 2 == 0 {
+                    fill_rect(&mut canvas, 0, y, WIDTH, ROW_HEIGHT, ROW_STRIPE);
+                }
+                self.draw_player_row(&mut canvas, &regular, &bold, y, player_stats);
+                y += ROW_HEIGHT;
+            }
+        }
+
+        // Footer
+        draw_text(&mut canvas, &regular, 13.0, PADDING, y + 7, "Powered by STRATZ", TEXT_SECONDARY);
+
+        Ok(canvas)
+    }
+
+    /// Draw a single player row whose top edge is at `y`. Players outside the guild are dimmed and players who
+    /// queued together share a coloured marker.
+    fn draw_player_row(
+        &self,
+        canvas: &mut RgbaImage,
+        regular: &FontRef,
+        bold: &FontRef,
+        y: u32,
+        player_stats: &PlayerStats
+    ) {
+        let portrait_y = y + (ROW_HEIGHT - PORTRAIT_HEIGHT) / 2;
+        match self.load_portrait(player_stats.hero_id) {
+            Some(portrait) => imageops::overlay(canvas, &portrait, PADDING as i64, portrait_y as i64),
+            None => draw_placeholder_portrait(canvas, bold, PADDING, portrait_y, player_stats)
+        }
+
+        if let Some(stack) = player_stats.stack {
+            let stack_color = STACK_COLORS[(stack as usize - 1) % STACK_COLORS.len()];
+            fill_rect(canvas, PADDING - 8, portrait_y, 4, PORTRAIT_HEIGHT, stack_color);
+        }
+
+        let text_y = y + 13;
+        let (name_font, name_color) = match player_stats.is_guild_member {
+            true => (bold, TEXT_PRIMARY),
+            false => (regular, TEXT_SECONDARY)
+        };
+        let name = truncate(&player_stats.name, NAME_MAX_CHARS);
+        draw_text(canvas, name_font, 16.0, COLUMN_NAME, text_y, &name, name_color);
+        draw_text(
+            canvas, regular, 16.0, COLUMN_HERO, text_y,
+            &truncate(&player_stats.hero_display_name, HERO_MAX_CHARS), TEXT_SECONDARY
+        );
+        let kda = format!("{}/{}/{}", player_stats.kills, player_stats.deaths, player_stats.assists);
+        draw_text(canvas, bold, 16.0, COLUMN_KDA, text_y, &kda, TEXT_PRIMARY);
+        draw_text(canvas, regular, 16.0, COLUMN_NETWORTH, text_y, &format_networth(player_stats.networth), NETWORTH_GOLD);
+    }
+
+    /// Load the portrait of `hero_id` from the asset pack, scaled to the portrait size
+    fn load_portrait(&self, hero_id: i16) -> Option<RgbaImage> {
+        let path = self.portrait_path(hero_id);
+        let portrait = image::open(path).ok()?;
+
+        Some(portrait.resize_exact(PORTRAIT_WIDTH, PORTRAIT_HEIGHT, imageops::FilterType::Triangle).to_rgba8())
+    }
+
+    /// Get the path of the portrait of `hero_id` inside the asset pack
+    fn portrait_path(&self, hero_id: i16) -> PathBuf {
+        Path::new(&self.hero_assets_dir).join(format!("{}.png", hero_id))
+    }
+
+}
+
+/// Match MatchResult to the colour of the result banner
+///
+/// # Arguments
+///
+/// * `match_result` - The match result of a dota2 game
+fn banner_color(match_result: &MatchResult) -> Rgba<u8> {
+    match match_result {
+        MatchResult::Victory => Rgba([46, 125, 50, 255]),
+        MatchResult::Defeat => Rgba([183, 28, 28, 255]),
+        MatchResult::Both => Rgba([191, 120, 0, 255]),
+        MatchResult::None => Rgba([84, 90, 102, 255])
+    }
+}
+
+/// Draw a coloured tile with the initials of the hero when no portrait is available
+fn draw_placeholder_portrait(canvas: &mut RgbaImage, font: &FontRef, x: u32, y: u32, player_stats: &PlayerStats) {
+    let hue = (player_stats.hero_id as u32).wrapping_mul(47) % 360;
+    fill_rect(canvas, x, y, PORTRAIT_WIDTH, PORTRAIT_HEIGHT, hue_to_rgba(hue));
+
+    let initials: String = player_stats.hero_display_name
+        .split_whitespace()
+        .filter_map(|word| word.chars().next())
+        .take(2)
+        .collect();
+    draw_text(canvas, font, 18.0, x + 8, y + 8, &initials.to_uppercase(), TEXT_PRIMARY);
+}
+
+/// Convert a hue in degrees to a muted RGBA colour
+fn hue_to_rgba(hue: u32) -> Rgba<u8> {
+    let sector = hue / 60;
+    let fraction = ((hue % 60) * 255 / 60) as u8;
+    let (low, high) = (60u8, 160u8);
+    let rising = low + ((high - low) as u32 * fraction as u32 / 255) as u8;
+    let falling = high - ((high - low) as u32 * fraction as u32 / 255) as u8;
+    let (r, g, b) = match sector {
+        0 => (high, rising, low),
+        1 => (falling, high, low),
+        2 => (low, high, rising),
+        3 => (low, falling, high),
+        4 => (rising, low, high),
+        _ => (high, low, falling)
+    };
+
+    Rgba([r, g, b, 255])
+}
+
+/// Fill a rectangle, clipped to the canvas
+fn fill_rect(canvas: &mut RgbaImage, x: u32, y: u32, width: u32, height: u32, color: Rgba<u8>) {
+    let x_end = (x + width).min(canvas.width());
+    let y_end = (y + height).min(canvas.height());
+    for py in y..y_end {
+        for px in x..x_end {
+            canvas.put_pixel(px, py, color);
+        }
+    }
+}
+
+/// Draw `text` with its top-left corner at (`x`, `y`), blending glyph coverage onto the canvas
+fn draw_text(canvas: &mut RgbaImage, font: &FontRef, size: f32, x: u32, y: u32, text: &str, color: Rgba<u8>) {
+    let scaled_font = font.as_scaled(PxScale::from(size));
+    let mut caret = x as f32;
+    let baseline = y as f32 + scaled_font.ascent();
+
+    for character in text.chars() {
+        let mut glyph = scaled_font.scaled_glyph(character);
+        glyph.position = point(caret, baseline);
+        caret += scaled_font.h_advance(glyph.id);
+
+        if let Some(outlined) = font.outline_glyph(glyph) {
+            let bounds = outlined.px_bounds();
+            outlined.draw(|gx, gy, coverage| {
+                let px = bounds.min.x as i32 + gx as i32;
+                let py = bounds.min.y as i32 + gy as i32;
+                if px < 0 || py < 0 || px >= canvas.width() as i32 || py >= canvas.height() as i32 {
+                    return;
+                }
+                let pixel = canvas.get_pixel_mut(px as u32, py as u32);
+                for channel in 0..3 {
+                    let background = pixel[channel] as f32;
+                    let foreground = color[channel] as f32;
+                    pixel[channel] = (background + (foreground - background) * coverage).round() as u8;
+                }
+            });
+        }
+    }
+}
+
+/// Truncate `text` to at most `max_chars` characters, marking the cut with an ellipsis
+fn truncate(text: &str, max_chars: usize) -> String {
+    if text.chars().count() <= max_chars {
+        return text.to_string();
+    }
+
+    let mut truncated: String = text.chars().take(max_chars - 1).collect();
+    truncated.push('…');
+    truncated
+}
+
+/// Format net worth in thousands, e.g. `18.4k`
+fn format_networth(networth: i64) -> String {
+    if networth < 1000 {
+        return networth.to_string();
+    }
+
+    format!("{:.1}k", networth as f64 / 1000.0)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::path::PathBuf;
+    use crate::publisher::fixtures::publish_record;
+    use crate::publisher::publisher::MatchResult;
+    use super::{ScoreboardRenderer, format_networth, truncate};
+
+    const GOLDEN_DIR: &str = "src/publisher/assets/golden";
+
+    fn renderer() -> ScoreboardRenderer {
+        // An empty asset pack exercises the placeholder portraits and keeps the golden images self-contained
+        ScoreboardRenderer { hero_assets_dir: PathBuf::from("assets/heroes/does-not-exist") }
+    }
+
+    /// Compare the rendered scoreboard with the golden image `name`. Run the tests with `UPDATE_GOLDEN=1` to
+    /// regenerate the golden images after an intended change of the layout.
+    fn assert_golden(name: &str, png: &[u8]) {
+        let path = PathBuf::from(GOLDEN_DIR).join(name);
+        if std::env::var("UPDATE_GOLDEN").is_ok() {
+            std::fs::create_dir_all(GOLDEN_DIR).unwrap();
+            std::fs::write(&path, png).unwrap();
+        }
+
+        let expected = image::open(&path).unwrap().to_rgba8();
+        let actual = image::load_from_memory(png).unwrap().to_rgba8();
+        assert_eq!(expected.dimensions(), actual.dimensions());
+        assert!(expected.as_raw() == actual.as_raw(), "Scoreboard differs from golden image {}", name);
+    }
+
+    #[test]
+    fn test_render_victory() {
+        let png = renderer().render(&publish_record(MatchResult::Victory)).unwrap();
+        assert_golden("victory.png", &png);
+    }
+
+    #[test]
+    fn test_render_defeat() {
+        let png = renderer().render(&publish_record(MatchResult::Defeat)).unwrap();
+        assert_golden("defeat.png", &png);
+    }
+
+    #[test]
+    fn test_render_skips_empty_team() {
+        let mut record = publish_record(MatchResult::Victory);
+        record.player_stats_dire.clear();
+        let png = renderer().render(&record).unwrap();
+        assert_golden("radiant_only.png", &png);
+    }
+
+    #[test]
+    fn test_render_randoms_and_stacks() {
+        let mut record = publish_record(MatchResult::Victory);
+        record.player_stats_radiant[0].stack = Some(1);
+        record.player_stats_radiant[1].stack = Some(1);
+        record.player_stats_dire[0].is_guild_member = false;
+        record.player_stats_dire[0].name = "Anonymous".to_string();
+        let png = renderer().render(&record).unwrap();
+        assert_golden("randoms_and_stacks.png", &png);
+    }
+
+    #[test]
+    fn test_render_portraits() {
+        // The portrait pack of the golden images only has Rubick, the other heroes keep their placeholder tiles
+        let renderer = ScoreboardRenderer { hero_assets_dir: PathBuf::from(GOLDEN_DIR).join("heroes") };
+        let png = renderer.render(&publish_record(MatchResult::Victory)).unwrap();
+        assert_golden("portraits.png", &png);
+    }
+
+    #[test]
+    fn test_format_networth() {
+        assert_eq!(format_networth(850), "850");
+        assert_eq!(format_networth(18420), "18.4k");
+    }
+
+    #[test]
+    fn test_truncate() {
+        assert_eq!(truncate("Alice", 18), "Alice");
+        assert_eq!(truncate("Bob the Very Long Named Player", 10), "Bob the V…");
+    }
+
+}
@@ -0,0 +1,188 @@
+use lambda_runtime::Error;
+use serde::{Serialize, Deserialize};
+use crate::config::TemplatesConfig;
+use crate::store::state::StateStore;
+use super::publisher::PlayerStats;
+use super::clash::guild_members;
+
+const DEFAULT_THRESHOLD: i64 = 3;
+
+/// Struct containing the streaks of a guild member. The current streak counts wins as positive and losses as negative.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Streak {
+    pub current: i64,
+    pub best_win: i64,
+    pub best_loss: i64,
+    /// The latest match counted, so that a match built again after a failed delivery is not counted twice
+    #[serde(default)]
+    pub last_match_id: i64,
+    /// The current streak before the latest match counted
+    #[serde(default)]
+    pub previous: i64
+}
+
+/// Enum of the streak lines called out on the card
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum StreakEvent {
+    WinStreak { name: String, games: i64, best: i64 },
+    LossStreak { name: String, games: i64 },
+    WinStreakBroken { name: String, games: i64 },
+    LossStreakBroken { name: String, games: i64 }
+}
+
+impl Streak {
+
+    /// Extend the current streak with the result of a new game, or start a new one, and keep the best streaks
+    ///
+    /// # Arguments
+    ///
+    /// * `is_victory` - Whether the guild member won the new game
+    /// * `match_id` - The id of the new game
+    pub fn update(&self, is_victory: bool, match_id: i64) -> Streak {
+        let current = match (is_victory, self.current) {
+            (true, current) if current > 0 => current + 1,
+            (true, _) => 1,
+            (false, current) if current < 0 => current - 1,
+            (false, _) => -1
+        };
+
+        Streak {
+            current,
+            best_win: self.best_win.max(current),
+            best_loss: self.best_loss.max(-current),
+            last_match_id: match_id,
+            previous: self.current
+        }
+    }
+
+    /// Get the streaks before the latest match counted, as far as the streak lines are concerned
+    pub fn before_last_match(&self) -> Streak {
+        Streak { current: self.previous, ..*self }
+    }
+
+}
+
+/// Get the streak line of a guild member after a game, when the new streak reached `threshold` games or a streak of
+/// at least `threshold` games was broken
+///
+/// # Arguments
+///
+/// * `name` - The name of the guild member
+/// * `previous` - The streaks before the game
+/// * `next` - The streaks after the game
+/// * `threshold` - The number of games in a row worth calling out
+pub fn detect_streak(name: &str, previous: &Streak, next: &Streak, threshold: i64) -> Option<StreakEvent> {
+    let name = name.to_string();
+    match next.current {
+        games if games >= threshold => Some(StreakEvent::WinStreak { name, games, best: next.best_win }),
+        games if -games >= threshold => Some(StreakEvent::LossStreak { name, games: -games }),
+        games if games > 0 && -previous.current >= threshold => {
+            Some(StreakEvent::LossStreakBroken { name, games: -previous.current })
+        },
+        games if games < 0 && previous.current >= threshold => {
+            Some(StreakEvent::WinStreakBroken { name, games: previous.current })
+        },
+        _ => None
+    }
+}
+
+/// Get the number of games in a row worth calling out from the `streak_threshold` setting of the `[templates]` section
+/// Otherwise, the default of 3 applies
+pub fn threshold_from_config(templates: &TemplatesConfig) -> i64 {
+    templates.streak_threshold.value().copied().unwrap_or(DEFAULT_THRESHOLD)
+}
+
+/// Update the streaks of every guild member among `players_stats` with the result of the match and return the streak
+/// lines worth calling out. A match counted already, e.g. by a run which failed to deliver it, gets the same lines
+/// without being counted again, and a match older than the latest match counted is left out of the streaks.
+///
+/// # Arguments
+///
+/// * `store` - The state store keeping streaks
+/// * `players_stats` - The stats of players of the match
+/// * `match_id` - The id of the match
+/// * `threshold` - The number of games in a row worth calling out
+pub async fn record_streaks(
+    store: &dyn StateStore,
+    players_stats: &[PlayerStats],
+    match_id: i64,
+    threshold: i64
+) -> Result<Vec<StreakEvent>, Error> {
+    let mut streak_events = Vec::new();
+    for player_stats in guild_members(players_stats) {
+        // Anonymous players cannot be told apart between matches
+        let steam_account_id = match player_stats.steam_account_id {
+            Some(steam_account_id) => steam_account_id,
+            None => continue
+        };
+
+        let stored = store.get_streak(steam_account_id).await?;
+        let (previous, next) = match stored.last_match_id {
+            last_match_id if last_match_id < match_id => {
+                let next = stored.update(player_stats.is_victory, match_id);
+                store.save_streak(steam_account_id, &next).await?;
+                (stored, next)
+            },
+            last_match_id if last_match_id == match_id => (stored.before_last_match(), stored),
+            _ => continue
+        };
+
+        streak_events.extend(detect_streak(&player_stats.name, &previous, &next, threshold));
+    }
+
+    Ok(streak_events)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::{Streak, StreakEvent, detect_streak};
+
+    fn play(results: &[bool]) -> Streak {
+        results.iter()
+            .zip(1..)
+            .fold(Streak::default(), |streak, (is_victory, match_id)| streak.update(*is_victory, match_id))
+    }
+
+    #[test]
+    fn test_update() {
+        assert_eq!(
+            play(&[true, true, true]),
+            Streak { current: 3, best_win: 3, best_loss: 0, last_match_id: 3, previous: 2 }
+        );
+        assert_eq!(
+            play(&[true, true, false, false, false, true]),
+            Streak { current: 1, best_win: 2, best_loss: 3, last_match_id: 6, previous: -3 }
+        );
+        assert_eq!(play(&[true, true, false]).before_last_match().current, 2);
+    }
+
+    #[test]
+    fn test_detect_streak() {
+        let previous = play(&[true, true]);
+        let next = previous.update(true, 3);
+        assert_eq!(
+            detect_streak("Alice", &previous, &next, 3),
+            Some(StreakEvent::WinStreak { name: "Alice".to_string(), games: 3, best: 3 })
+        );
+        assert_eq!(detect_streak("Alice", &previous, &next, 4), None);
+
+        let previous = play(&[false, false, false, false]);
+        assert_eq!(
+            detect_streak("Bob", &previous, &previous.update(false, 5), 3),
+            Some(StreakEvent::LossStreak { name: "Bob".to_string(), games: 5 })
+        );
+        assert_eq!(
+            detect_streak("Bob", &previous, &previous.update(true, 5), 3),
+            Some(StreakEvent::LossStreakBroken { name: "Bob".to_string(), games: 4 })
+        );
+
+        let previous = play(&[true, true, true]);
+        assert_eq!(
+            detect_streak("Carol", &previous, &previous.update(false, 4), 3),
+            Some(StreakEvent::WinStreakBroken { name: "Carol".to_string(), games: 3 })
+        );
+        assert_eq!(detect_streak("Carol", &previous, &previous.update(false, 4), 4), None);
+    }
+
+}
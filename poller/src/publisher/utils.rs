@@ -1,6 +1,9 @@
 use crate::provider::stratz;
+use crate::report::summary::{GuildReport, MemberSummary, ReportPeriod};
 
-use super::publisher::MatchResult;
+use super::{
+    publisher::{MatchResult, PlayerStats, PublishRecord}, highlights::Highlight, clash::Clash, streaks::StreakEvent
+};
 
 /// Transform MatchResult struct to String literals
 /// 
@@ -79,3 +82,173 @@ pub fn transform_game_mode(game_mode: &stratz::api::GameMode) -> String {
 
     result.to_string()
 }
+
+/// Transform Highlight struct to a callout line
+/// 
+/// # Arguments
+/// 
+/// * `highlight` - The notable performance of a dota2 game
+pub fn transform_highlight(highlight: &Highlight) -> String {
+    match highlight {
+        Highlight::Rampage { name, hero } => format!("Rampage! {} on {}", name, hero),
+        Highlight::KillCount { name, hero, kills } => format!("{} kills by {} on {}", kills, name, hero),
+        Highlight::Deathless { name, hero, kills, assists } => {
+            format!("Deathless {} on {} with {}/0/{}", name, hero, kills, assists)
+        },
+        Highlight::ExtremeKda { name, hero, kda } => format!("KDA {:.1} by {} on {}", kda, name, hero),
+        Highlight::Comeback { deficit } => format!("Comeback from a {:.1}k net worth deficit", *deficit as f64 / 1000.0),
+        Highlight::LongGame { duration } => format!("Marathon game of {}", duration),
+        Highlight::ShortGame { duration } => format!("Stomp in {}", duration)
+    }
+}
+
+/// Transform Clash struct to lines naming the winners, the losers and their head-to-head records
+/// 
+/// # Arguments
+/// 
+/// * `clash` - The per-side result of a dota2 game between guild members
+pub fn transform_clash(clash: &Clash) -> Vec<String> {
+    let mut lines = vec![
+        format!("Won: {}", clash.winners.join(", ")),
+        format!("Lost: {}", clash.losers.join(", "))
+    ];
+    for head_to_head in clash.head_to_head.iter() {
+        lines.push(format!(
+            "Head-to-head: {} {} - {} {}",
+            head_to_head.winner, head_to_head.winner_wins, head_to_head.loser_wins, head_to_head.loser
+        ));
+    }
+
+    lines
+}
+
+/// Transform the name of a player to markdown, guild members in bold and followed by the stack they queued in
+/// 
+/// # Arguments
+/// 
+/// * `player_stats` - The stats of a player in a dota2 game
+pub fn transform_player_name(player_stats: &PlayerStats) -> String {
+    let mut name = match player_stats.is_guild_member {
+        true => format!("**{}**", player_stats.name),
+        false => player_stats.name.to_string()
+    };
+    if let Some(stack) = player_stats.stack {
+        name.push_str(&format!(" (Stack {})", stack));
+    }
+
+    name
+}
+
+/// Transform PublishRecord struct to the one-line title of a match in a digest
+/// 
+/// # Arguments
+/// 
+/// * `publish_record` - The data POJO summarized in a digest
+/// 
+/// # Examples
+/// 
+/// Victory - Ranked - All Draft (25:51)
+pub fn transform_digest_title(publish_record: &PublishRecord) -> String {
+    format!(
+        "{} - {} - {} ({})",
+        transform_match_result(&publish_record.match_result),
+        transform_lobby_type(&publish_record.lobby_type),
+        transform_game_mode(&publish_record.game_mode),
+        publish_record.duration_field
+    )
+}
+
+/// Transform PublishRecord struct to one line per guild member of a match in a digest
+/// 
+/// # Arguments
+/// 
+/// * `publish_record` - The data POJO summarized in a digest
+/// 
+/// # Examples
+/// 
+/// Alice - Rubick - 6/5/16
+pub fn transform_digest_players(publish_record: &PublishRecord) -> Vec<String> {
+    publish_record.player_stats_radiant.iter()
+        .chain(publish_record.player_stats_dire.iter())
+        .filter(|player_stats| player_stats.is_guild_member)
+        .map(|player_stats| format!(
+            "{} - {} - {}/{}/{}",
+            player_stats.name, player_stats.hero_display_name, player_stats.kills, player_stats.deaths, player_stats.assists
+        ))
+        .collect()
+}
+
+/// Transform GuildReport struct to the title of a summary report, with the dates in the timezone of the report
+/// 
+/// # Arguments
+/// 
+/// * `report` - The guild summary report
+/// 
+/// # Examples
+/// 
+/// Weekly report 2024-01-08 - 2024-01-14
+pub fn transform_report_title(report: &GuildReport) -> String {
+    let period = match report.period {
+        ReportPeriod::Daily => "Daily",
+        ReportPeriod::Weekly => "Weekly"
+    };
+    let first_day = report.start.with_timezone(&report.timezone).date_naive();
+    let last_day = (report.end - chrono::Duration::seconds(1)).with_timezone(&report.timezone).date_naive();
+
+    match first_day == last_day {
+        true => format!("{} report {}", period, first_day),
+        false => format!("{} report {} - {}", period, first_day, last_day)
+    }
+}
+
+/// Transform MemberSummary struct to markdown lines of a summary report
+/// 
+/// # Arguments
+/// 
+/// * `summary` - The aggregated stats of a guild member
+/// 
+/// # Examples
+/// 
+/// **Alice** 12 games, 7 wins (58%), KDA 6.3/4.1/12.0
+/// Heroes: Rubick 4, Pudge 3
+/// Best game: Rubick 12/1/20 [6789012345](https://stratz.com/matches/6789012345)
+pub fn transform_member_summary(summary: &MemberSummary) -> Vec<String> {
+    let (kills, deaths, assists) = summary.average_kda();
+    let mut lines = vec![format!(
+        "**{}** {} games, {} wins ({:.0}%), KDA {:.1}/{:.1}/{:.1}",
+        summary.name, summary.games, summary.wins, summary.win_rate() * 100.0, kills, deaths, assists
+    )];
+    let heroes = summary.most_played_heroes().iter()
+        .map(|(hero, games)| format!("{} {}", hero, games))
+        .collect::<Vec<String>>();
+    if !heroes.is_empty() {
+        lines.push(format!("Heroes: {}", heroes.join(", ")));
+    }
+    if let Some(best_game) = summary.best_game.as_ref() {
+        lines.push(format!(
+            "Best game: {} {}/{}/{} [{}](https://stratz.com/matches/{})",
+            best_game.hero, best_game.kills, best_game.deaths, best_game.assists, best_game.match_id, best_game.match_id
+        ));
+    }
+
+    lines
+}
+
+/// Transform StreakEvent struct to a callout line
+/// 
+/// # Arguments
+/// 
+/// * `streak_event` - The win or loss streak of a guild member worth calling out
+pub fn transform_streak(streak_event: &StreakEvent) -> String {
+    match streak_event {
+        StreakEvent::WinStreak { name, games, best } if games >= best => {
+            format!("{} is on a {}-game win streak, a personal best", name, games)
+        },
+        StreakEvent::WinStreak { name, games, best } => {
+            format!("{} is on a {}-game win streak (best {})", name, games, best)
+        },
+        StreakEvent::LossStreak { name, games } => format!("{} has lost {} in a row", name, games),
+        StreakEvent::WinStreakBroken { name, games } => format!("{}'s {}-game win streak is over", name, games),
+        StreakEvent::LossStreakBroken { name, games } => format!("{} ended a {}-game losing streak", name, games)
+    }
+}
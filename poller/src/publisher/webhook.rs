@@ -1,10 +1,20 @@
 use lambda_runtime::Error;
+use reqwest::multipart::{Form, Part};
+use serde_json::Value;
+use webhook::models::Message;
 use super::publisher::PublishRecord;
-use super::utils::{transform_match_result, transform_lobby_type, transform_game_mode};
+use super::utils::{transform_match_result, transform_lobby_type, transform_game_mode, transform_highlight, transform_clash,
+    transform_player_name, transform_digest_title, transform_digest_players, transform_report_title, transform_member_summary,
+    transform_streak};
+use crate::{alert::Alert, report::summary::GuildReport};
+
+const SCOREBOARD_FILE_NAME: &str = "scoreboard.png";
+const MAX_EMBED_FIELDS: usize = 25;
 
 /// Webhook Publisher
 pub struct WebhookPublisher {
-    pub client: webhook::client::WebhookClient
+    pub client: reqwest::Client,
+    pub url: String
 }
 
 impl WebhookPublisher {
@@ -14,67 +24,286 @@ impl WebhookPublisher {
     /// # Arguments
     /// 
     /// * `publish_record` - The data POJO to be published
-    pub async fn publish(&self, publish_record: &PublishRecord) -> Result<(), Error> {
-        self.client.send(|mut message| {
-            message = message.content(&format!("https://stratz.com/matches/{}", publish_record.match_id));
-            message = message.embed(|mut embed| {
-                embed = embed.author(
-                    &publish_record.guild_name,
-                    Some(format!("https://stratz.com/guilds/{}", publish_record.guild_id)),
-                    Some(format!("https://steamusercontent-a.akamaihd.net/ugc/{}/", publish_record.guild_logo))
-                );
-                embed = embed.title(&format!(
-                    "{} - {} - {}",
-                    transform_match_result(&publish_record.match_result),
-                    transform_lobby_type(&publish_record.lobby_type),
-                    transform_game_mode(&publish_record.game_mode)
-                ));
-
-                let mut radiant_field = String::new();
-                for player_stats in publish_record.player_stats_radiant.iter() {
-                    let line = format!("{} {} [{}/{}/{}]\n",
-                        match_hero_emoji(player_stats.hero_id),
-                        player_stats.name,
-                        player_stats.kills,
-                        player_stats.deaths,
-                        player_stats.assists
-                    );
-                    radiant_field.push_str(&line);
-                }
+    /// * `scoreboard` - The PNG scoreboard attached to the message as a file, if any
+    pub async fn publish(&self, publish_record: &PublishRecord, scoreboard: Option<&[u8]>) -> Result<(), Error> {
+        let mut message = Message::new();
+        self.build_message(&mut message, publish_record, scoreboard.is_some());
+        let payload = serde_json::to_string(&message)?;
 
-                let mut dire_field = String::new();
-                for player_stats in publish_record.player_stats_dire.iter() {
-                    let line = format!("{} {} [{}/{}/{}]\n",
-                        match_hero_emoji(player_stats.hero_id),
-                        player_stats.name,
-                        player_stats.kills,
-                        player_stats.deaths,
-                        player_stats.assists
-                    );
-                    dire_field.push_str(&line);
-                }
+        let request = match scoreboard {
+            Some(scoreboard) => {
+                let file = Part::bytes(scoreboard.to_vec())
+                    .file_name(SCOREBOARD_FILE_NAME)
+                    .mime_str("image/png")?;
+                let form = Form::new()
+                    .text("payload_json", payload)
+                    .part("files[0]", file);
+                self.client.post(&self.url).multipart(form)
+            },
+            None => {
+                self.client.post(&self.url)
+                    .header(reqwest::header::CONTENT_TYPE, "application/json")
+                    .body(payload)
+            }
+        };
 
-                if radiant_field.len() > 0 {
-                    embed = embed.field("<:radiant:958274781919207505> Radiant", &radiant_field, true);
-                }
+        request.send().await?.error_for_status()?;
+
+        Ok(())
+    }
+
+    /// Get the message `publish` would send to Discord for `publish_record`, without sending it
+    ///
+    /// # Arguments
+    ///
+    /// * `publish_record` - The data POJO to be published
+    /// * `has_scoreboard` - Whether the scoreboard would be attached and shown inside the embed
+    pub fn preview(&self, publish_record: &PublishRecord, has_scoreboard: bool) -> Result<Value, Error> {
+        let mut message = Message::new();
+        self.build_message(&mut message, publish_record, has_scoreboard);
+
+        Ok(serde_json::to_value(&message)?)
+    }
+
+    /// Get the message `publish_digest` would send to Discord for `publish_records`, without sending it
+    /// Otherwise, None when there is no record
+    ///
+    /// # Arguments
+    ///
+    /// * `publish_records` - The data POJOs to be summarized, oldest first
+    pub fn preview_digest(&self, publish_records: &[&PublishRecord]) -> Result<Option<Value>, Error> {
+        if publish_records.is_empty() {
+            return Ok(None);
+        }
+        let mut message = Message::new();
+        self.build_digest_message(&mut message, publish_records);
 
-                if dire_field.len() > 0 {
-                    embed = embed.field("<:dire:958274694203719740> Dire", &dire_field, true);
+        Ok(Some(serde_json::to_value(&message)?))
+    }
+
+    /// Get the message `publish_report` would send to Discord for `report`, without sending it
+    ///
+    /// # Arguments
+    ///
+    /// * `report` - The guild summary report
+    pub fn preview_report(&self, report: &GuildReport) -> Result<Value, Error> {
+        let mut message = Message::new();
+        self.build_report_message(&mut message, report);
+
+        Ok(serde_json::to_value(&message)?)
+    }
+
+    /// Format several `publish_records` into a single compact message and publish it to Discord
+    ///
+    /// # Arguments
+    ///
+    /// * `publish_records` - The data POJOs to be summarized, oldest first
+    pub async fn publish_digest(&self, publish_records: &[&PublishRecord]) -> Result<(), Error> {
+        if publish_records.is_empty() {
+            return Ok(());
+        }
+
+        let mut message = Message::new();
+        self.build_digest_message(&mut message, publish_records);
+        self.client.post(&self.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(serde_json::to_string(&message)?)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(())
+    }
+
+    /// Build the Discord message summarizing `publish_records`, one embed field per match
+    ///
+    /// # Arguments
+    ///
+    /// * `message` - The Discord message to be filled
+    /// * `publish_records` - The data POJOs to be summarized, oldest first
+    fn build_digest_message<'a>(&self, message: &'a mut Message, publish_records: &[&PublishRecord]) -> &'a mut Message {
+        let first_record = publish_records[0];
+        let last_record = publish_records[publish_records.len() - 1];
+        message.embed(|mut embed| {
+            embed = embed.author(
+                &first_record.guild_name,
+                Some(format!("https://stratz.com/guilds/{}", first_record.guild_id)),
+                Some(format!("https://steamusercontent-a.akamaihd.net/ugc/{}/", first_record.guild_logo))
+            );
+            embed = embed.title(&format!("{} new matches", publish_records.len()));
+
+            for publish_record in publish_records.iter() {
+                let mut field = format!("[Match {0}](https://stratz.com/matches/{0})\n", publish_record.match_id);
+                for line in transform_digest_players(publish_record) {
+                    field.push_str(&format!("{}\n", line));
                 }
+                embed = embed.field(&transform_digest_title(publish_record), &field, false);
+            }
 
-                embed = embed.field(":clock3: Duration", &publish_record.duration_field, false);
-                embed = embed.footer("Powered by STRATZ", Some(String::from("https://cdn.discordapp.com/icons/268890221943324677/12b63c55a83a715ec569e91e40641db0.webp?size=96")));
-                embed = embed.timestamp(&publish_record.end.to_rfc3339());
+            embed = embed.footer("Powered by STRATZ", Some(String::from("https://cdn.discordapp.com/icons/268890221943324677/12b63c55a83a715ec569e91e40641db0.webp?size=96")));
+            embed = embed.timestamp(&last_record.end.to_rfc3339());
 
-                return embed;
-            });
+            embed
+        })
+    }
+
+    /// Format `report` into a message listing the stats of every guild member and publish it to Discord
+    ///
+    /// # Arguments
+    ///
+    /// * `report` - The guild summary report
+    pub async fn publish_report(&self, report: &GuildReport) -> Result<(), Error> {
+        let mut message = Message::new();
+        self.build_report_message(&mut message, report);
+        self.client.post(&self.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(serde_json::to_string(&message)?)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(())
+    }
 
-            return message;
-        }).await?;
+    /// Publish an operational `alert` to the admins on Discord, as a plain text message
+    ///
+    /// # Arguments
+    ///
+    /// * `alert` - The alert about a component
+    pub async fn publish_alert(&self, alert: &Alert) -> Result<(), Error> {
+        let mut message = Message::new();
+        message.content(&alert.text());
+        self.client.post(&self.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(serde_json::to_string(&message)?)
+            .send()
+            .await?
+            .error_for_status()?;
 
         Ok(())
     }
 
+    /// Build the Discord message listing the stats of every guild member in `report`
+    ///
+    /// # Arguments
+    ///
+    /// * `message` - The Discord message to be filled
+    /// * `report` - The guild summary report
+    fn build_report_message<'a>(&self, message: &'a mut Message, report: &GuildReport) -> &'a mut Message {
+        message.embed(|mut embed| {
+            embed = embed.author(
+                &report.guild_name,
+                Some(format!("https://stratz.com/guilds/{}", report.guild_id)),
+                Some(format!("https://steamusercontent-a.akamaihd.net/ugc/{}/", report.guild_logo))
+            );
+            embed = embed.title(&transform_report_title(report));
+            if report.members.is_empty() {
+                embed = embed.description("No games played");
+            }
+            // Discord caps an embed at 25 fields
+            for summary in report.members.iter().take(MAX_EMBED_FIELDS) {
+                let mut lines = transform_member_summary(summary);
+                let name = lines.remove(0);
+                embed = embed.field(&name, &lines.join("\n"), false);
+            }
+            embed = embed.footer("Powered by STRATZ", Some(String::from("https://cdn.discordapp.com/icons/268890221943324677/12b63c55a83a715ec569e91e40641db0.webp?size=96")));
+            embed = embed.timestamp(&report.end.to_rfc3339());
+
+            embed
+        })
+    }
+
+    /// Build the Discord message of `publish_record`
+    ///
+    /// # Arguments
+    ///
+    /// * `message` - The Discord message to be filled
+    /// * `publish_record` - The data POJO to be published
+    /// * `has_scoreboard` - Whether the scoreboard is attached and should be shown inside the embed
+    fn build_message<'a>(
+        &self,
+        message: &'a mut Message,
+        publish_record: &PublishRecord,
+        has_scoreboard: bool
+    ) -> &'a mut Message {
+        message.content(&format!("https://stratz.com/matches/{}", publish_record.match_id));
+        message.embed(|mut embed| {
+            embed = embed.author(
+                &publish_record.guild_name,
+                Some(format!("https://stratz.com/guilds/{}", publish_record.guild_id)),
+                Some(format!("https://steamusercontent-a.akamaihd.net/ugc/{}/", publish_record.guild_logo))
+            );
+            embed = embed.title(&format!(
+                "{} - {} - {}",
+                transform_match_result(&publish_record.match_result),
+                transform_lobby_type(&publish_record.lobby_type),
+                transform_game_mode(&publish_record.game_mode)
+            ));
+
+            let mut radiant_field = String::new();
+            for player_stats in publish_record.player_stats_radiant.iter() {
+                let line = format!("{} {} [{}/{}/{}]\n",
+                    match_hero_emoji(player_stats.hero_id),
+                    transform_player_name(player_stats),
+                    player_stats.kills,
+                    player_stats.deaths,
+                    player_stats.assists
+                );
+                radiant_field.push_str(&line);
+            }
+
+            let mut dire_field = String::new();
+            for player_stats in publish_record.player_stats_dire.iter() {
+                let line = format!("{} {} [{}/{}/{}]\n",
+                    match_hero_emoji(player_stats.hero_id),
+                    transform_player_name(player_stats),
+                    player_stats.kills,
+                    player_stats.deaths,
+                    player_stats.assists
+                );
+                dire_field.push_str(&line);
+            }
+
+            if !radiant_field.is_empty() {
+                embed = embed.field("<:radiant:958274781919207505> Radiant", &radiant_field, true);
+            }
+
+            if !dire_field.is_empty() {
+                embed = embed.field("<:dire:958274694203719740> Dire", &dire_field, true);
+            }
+
+            if let Some(clash) = publish_record.clash.as_ref() {
+                let clash_field = transform_clash(clash).iter()
+                    .map(|line| format!("{}\n", line))
+                    .collect::<String>();
+                embed = embed.field(&format!(":crossed_swords: {}", clash.headline()), &clash_field, false);
+            }
+
+            if !publish_record.highlights.is_empty() {
+                let highlights_field = publish_record.highlights.iter()
+                    .map(|highlight| format!("{}\n", transform_highlight(highlight)))
+                    .collect::<String>();
+                embed = embed.field(":star2: Highlights", &highlights_field, false);
+            }
+
+            if !publish_record.streaks.is_empty() {
+                let streaks_field = publish_record.streaks.iter()
+                    .map(|streak_event| format!("{}\n", transform_streak(streak_event)))
+                    .collect::<String>();
+                embed = embed.field(":fire: Streaks", &streaks_field, false);
+            }
+
+            embed = embed.field(":clock3: Duration", &publish_record.duration_field, false);
+            if has_scoreboard {
+                embed = embed.image(&format!("attachment://{}", SCOREBOARD_FILE_NAME));
+            }
+            embed = embed.footer("Powered by STRATZ", Some(String::from("https://cdn.discordapp.com/icons/268890221943324677/12b63c55a83a715ec569e91e40641db0.webp?size=96")));
+            embed = embed.timestamp(&publish_record.end.to_rfc3339());
+
+            embed
+        })
+    }
+
 }
 
 /// Match hero id to discord dota2 emoji and return a String literal representing a discord dota2 emoji
@@ -0,0 +1,330 @@
+use std::collections::HashMap;
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use lambda_runtime::Error;
+use crate::config::TemplatesConfig;
+use crate::publisher::publisher::{PublishRecord, PlayerStats};
+
+const MOST_PLAYED_HEROES: usize = 3;
+
+/// Enum of the periods a guild summary report covers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportPeriod {
+    Daily,
+    Weekly
+}
+
+impl ReportPeriod {
+
+    /// Parse the period of a report, e.g. `daily` or `weekly`
+    pub fn parse(value: &str) -> Result<ReportPeriod, Error> {
+        match value.trim().to_lowercase().as_str() {
+            "daily" => Ok(ReportPeriod::Daily),
+            "weekly" => Ok(ReportPeriod::Weekly),
+            _ => Err(format!("Invalid report period: {}", value).into())
+        }
+    }
+
+    /// Get the name of the period, as written in events and settings
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReportPeriod::Daily => "daily",
+            ReportPeriod::Weekly => "weekly"
+        }
+    }
+
+    /// Get the start and the end of the last complete period before `now`, with days starting at midnight and weeks
+    /// starting on Monday in `timezone`
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - The time the report is run at
+    /// * `timezone` - The timezone the days of the report are counted in
+    pub fn bounds(&self, now: DateTime<Utc>, timezone: Tz) -> Result<(DateTime<Utc>, DateTime<Utc>), Error> {
+        let end_date = self.current_start_date(now, timezone);
+        let start_date = end_date - Duration::days(self.days());
+
+        Ok((midnight(start_date, timezone)?, midnight(end_date, timezone)?))
+    }
+
+    /// Get the start of the period after the one `now` falls in, when the report of the current period is due
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - The current time
+    /// * `timezone` - The timezone the days of the report are counted in
+    pub fn next_start(&self, now: DateTime<Utc>, timezone: Tz) -> Result<DateTime<Utc>, Error> {
+        midnight(self.current_start_date(now, timezone) + Duration::days(self.days()), timezone)
+    }
+
+    /// Get the first day of the period `now` falls in, in `timezone`
+    fn current_start_date(&self, now: DateTime<Utc>, timezone: Tz) -> NaiveDate {
+        let today = now.with_timezone(&timezone).date_naive();
+        match self {
+            ReportPeriod::Daily => today,
+            ReportPeriod::Weekly => today - Duration::days(today.weekday().num_days_from_monday() as i64)
+        }
+    }
+
+    /// Get the number of days of the period
+    fn days(&self) -> i64 {
+        match self {
+            ReportPeriod::Daily => 1,
+            ReportPeriod::Weekly => 7
+        }
+    }
+
+}
+
+/// Get the start of `date` in `timezone`
+/// Otherwise, an error when the day starts later than midnight, skipped by a daylight saving time change
+fn midnight(date: NaiveDate, timezone: Tz) -> Result<DateTime<Utc>, Error> {
+    timezone.from_local_datetime(&date.and_time(NaiveTime::MIN))
+        .earliest()
+        .map(|start| start.with_timezone(&Utc))
+        .ok_or_else(|| format!("No midnight on {} in {}", date, timezone).into())
+}
+
+/// Get the timezone of the reports from the `report_timezone` setting of the `[templates]` section, UTC by default
+pub fn timezone_from_config(templates: &TemplatesConfig) -> Result<Tz, Error> {
+    match templates.report_timezone.value() {
+        Some(value) => value.trim().parse::<Tz>().map_err(|_| format!("Invalid REPORT_TIMEZONE: {}", value).into()),
+        None => Ok(Tz::UTC)
+    }
+}
+
+/// Struct containing the best game of a guild member in a report
+#[derive(Debug, PartialEq)]
+pub struct BestGame {
+    pub match_id: String,
+    pub hero: String,
+    pub kills: u8,
+    pub deaths: u8,
+    pub assists: u8
+}
+
+/// Struct containing the aggregated stats of a guild member over the period of a report
+#[derive(Debug, PartialEq)]
+pub struct MemberSummary {
+    pub steam_account_id: i64,
+    pub name: String,
+    pub games: usize,
+    pub wins: usize,
+    pub heroes: Vec<(String, usize)>,
+    pub kills: u32,
+    pub deaths: u32,
+    pub assists: u32,
+    pub best_game: Option<BestGame>
+}
+
+impl MemberSummary {
+
+    /// Get the share of games won, between 0 and 1
+    pub fn win_rate(&self) -> f64 {
+        match self.games {
+            0 => 0.0,
+            games => self.wins as f64 / games as f64
+        }
+    }
+
+    /// Get the average kills, deaths and assists per game
+    pub fn average_kda(&self) -> (f64, f64, f64) {
+        let games = self.games.max(1) as f64;
+        (self.kills as f64 / games, self.deaths as f64 / games, self.assists as f64 / games)
+    }
+
+    /// Get the heroes played the most, most played first
+    pub fn most_played_heroes(&self) -> &[(String, usize)] {
+        &self.heroes[..self.heroes.len().min(MOST_PLAYED_HEROES)]
+    }
+
+}
+
+/// Struct containing a guild summary report, ready to be published
+#[derive(Debug)]
+pub struct GuildReport {
+    pub guild_id: String,
+    pub guild_name: String,
+    pub guild_logo: String,
+    pub period: ReportPeriod,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    pub timezone: Tz,
+    pub members: Vec<MemberSummary>
+}
+
+/// Aggregate the stats of every guild member over `publish_records`. Members are sorted by games played, then by win
+/// rate. Anonymous players cannot be told apart between matches and are left out.
+///
+/// # Arguments
+///
+/// * `publish_records` - The matches of the period of the report
+pub fn summarize_members(publish_records: &[PublishRecord]) -> Vec<MemberSummary> {
+    let mut summaries: HashMap<i64, MemberSummary> = HashMap::new();
+    let mut heroes: HashMap<i64, HashMap<String, usize>> = HashMap::new();
+    for publish_record in publish_records.iter() {
+        let players_stats = publish_record.player_stats_radiant.iter().chain(publish_record.player_stats_dire.iter());
+        for player_stats in players_stats.filter(|player_stats| player_stats.is_guild_member) {
+            let steam_account_id = match player_stats.steam_account_id {
+                Some(steam_account_id) => steam_account_id,
+                None => continue
+            };
+
+            let summary = summaries.entry(steam_account_id).or_insert_with(|| MemberSummary {
+                steam_account_id,
+                name: player_stats.name.to_string(),
+                games: 0,
+                wins: 0,
+                heroes: Vec::new(),
+                kills: 0,
+                deaths: 0,
+                assists: 0,
+                best_game: None
+            });
+            summary.name = player_stats.name.to_string();
+            summary.games += 1;
+            summary.wins += player_stats.is_victory as usize;
+            summary.kills += player_stats.kills as u32;
+            summary.deaths += player_stats.deaths as u32;
+            summary.assists += player_stats.assists as u32;
+            let is_best_game = summary.best_game.as_ref()
+                .is_none_or(|best_game| game_score(player_stats) > best_game_score(best_game));
+            if is_best_game {
+                summary.best_game = Some(BestGame {
+                    match_id: publish_record.match_id.to_string(),
+                    hero: player_stats.hero_display_name.to_string(),
+                    kills: player_stats.kills,
+                    deaths: player_stats.deaths,
+                    assists: player_stats.assists
+                });
+            }
+
+            *heroes.entry(steam_account_id).or_default()
+                .entry(player_stats.hero_display_name.to_string())
+                .or_default() += 1;
+        }
+    }
+
+    let mut summaries: Vec<MemberSummary> = summaries.into_values()
+        .map(|mut summary| {
+            let mut member_heroes: Vec<(String, usize)> = heroes.remove(&summary.steam_account_id)
+                .unwrap_or_default()
+                .into_iter()
+                .collect();
+            member_heroes.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+            summary.heroes = member_heroes;
+            summary
+        })
+        .collect();
+    summaries.sort_by(|a, b| {
+        b.games.cmp(&a.games)
+            .then_with(|| b.win_rate().total_cmp(&a.win_rate()))
+            .then_with(|| a.name.cmp(&b.name))
+    });
+
+    summaries
+}
+
+/// Score a game by its (kills + assists) / deaths ratio, with deathless games counting as one death
+fn game_score(player_stats: &PlayerStats) -> (f64, u8) {
+    kda_score(player_stats.kills, player_stats.deaths, player_stats.assists)
+}
+
+/// Score the best game so far the same way as `game_score`
+fn best_game_score(best_game: &BestGame) -> (f64, u8) {
+    kda_score(best_game.kills, best_game.deaths, best_game.assists)
+}
+
+/// Get the KDA ratio, with kills breaking ties
+fn kda_score(kills: u8, deaths: u8, assists: u8) -> (f64, u8) {
+    ((kills as f64 + assists as f64) / deaths.max(1) as f64, kills)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use chrono::TimeZone;
+    use chrono_tz::Tz;
+    use crate::publisher::fixtures::{player_stats, publish_record};
+    use crate::publisher::publisher::MatchResult;
+    use super::{ReportPeriod, summarize_members};
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(ReportPeriod::parse("daily").unwrap(), ReportPeriod::Daily);
+        assert_eq!(ReportPeriod::parse(" Weekly ").unwrap(), ReportPeriod::Weekly);
+        assert!(ReportPeriod::parse("monthly").is_err());
+    }
+
+    #[test]
+    fn test_bounds() {
+        // Wednesday 2024-01-17 01:30 in Shanghai, still Tuesday in UTC
+        let now = chrono::Utc.with_ymd_and_hms(2024, 1, 16, 17, 30, 0).unwrap();
+        let shanghai: Tz = "Asia/Shanghai".parse().unwrap();
+
+        let (start, end) = ReportPeriod::Daily.bounds(now, shanghai).unwrap();
+        assert_eq!(start, chrono::Utc.with_ymd_and_hms(2024, 1, 15, 16, 0, 0).unwrap());
+        assert_eq!(end, chrono::Utc.with_ymd_and_hms(2024, 1, 16, 16, 0, 0).unwrap());
+
+        let (start, end) = ReportPeriod::Weekly.bounds(now, shanghai).unwrap();
+        assert_eq!(start, chrono::Utc.with_ymd_and_hms(2024, 1, 7, 16, 0, 0).unwrap());
+        assert_eq!(end, chrono::Utc.with_ymd_and_hms(2024, 1, 14, 16, 0, 0).unwrap());
+
+        let (start, end) = ReportPeriod::Daily.bounds(now, Tz::UTC).unwrap();
+        assert_eq!(start, chrono::Utc.with_ymd_and_hms(2024, 1, 15, 0, 0, 0).unwrap());
+        assert_eq!(end, chrono::Utc.with_ymd_and_hms(2024, 1, 16, 0, 0, 0).unwrap());
+    }
+
+    #[test]
+    fn test_next_start() {
+        // Wednesday 2024-01-17 01:30 in Shanghai, still Tuesday in UTC
+        let now = chrono::Utc.with_ymd_and_hms(2024, 1, 16, 17, 30, 0).unwrap();
+        let shanghai: Tz = "Asia/Shanghai".parse().unwrap();
+        assert_eq!(
+            ReportPeriod::Daily.next_start(now, shanghai).unwrap(),
+            chrono::Utc.with_ymd_and_hms(2024, 1, 17, 16, 0, 0).unwrap()
+        );
+        assert_eq!(
+            ReportPeriod::Weekly.next_start(now, shanghai).unwrap(),
+            chrono::Utc.with_ymd_and_hms(2024, 1, 21, 16, 0, 0).unwrap()
+        );
+        assert_eq!(
+            ReportPeriod::Daily.next_start(now, Tz::UTC).unwrap(),
+            chrono::Utc.with_ymd_and_hms(2024, 1, 17, 0, 0, 0).unwrap()
+        );
+
+        // Once due, the report covers the period which just ended
+        let due_at = ReportPeriod::Weekly.next_start(now, shanghai).unwrap();
+        let (start, end) = ReportPeriod::Weekly.bounds(due_at, shanghai).unwrap();
+        assert_eq!((start, end), (chrono::Utc.with_ymd_and_hms(2024, 1, 14, 16, 0, 0).unwrap(), due_at));
+    }
+
+    #[test]
+    fn test_summarize_members() {
+        let first = publish_record(MatchResult::Victory);
+        let mut second = publish_record(MatchResult::Defeat);
+        second.match_id = "6789012346".to_string();
+        let mut alice = player_stats(86, "Rubick", "Alice", (12, 1, 20), 15000);
+        alice.is_victory = false;
+        let mut random = player_stats(14, "Pudge", "Random", (3, 9, 7), 9870);
+        random.is_guild_member = false;
+        second.player_stats_radiant = vec![alice];
+        second.player_stats_dire = vec![random];
+
+        let summaries = summarize_members(&[first, second]);
+        assert_eq!(summaries.len(), 3);
+
+        let alice = &summaries[0];
+        assert_eq!(alice.name, "Alice");
+        assert_eq!((alice.games, alice.wins), (2, 1));
+        assert_eq!(alice.win_rate(), 0.5);
+        assert_eq!(alice.average_kda(), (9.0, 3.0, 18.0));
+        assert_eq!(alice.most_played_heroes(), &[("Rubick".to_string(), 2)]);
+        assert_eq!(alice.best_game.as_ref().unwrap().match_id, "6789012346");
+
+        assert_eq!(summaries[1].name, "Bob the Very Long Named Player");
+        assert_eq!(summaries[2].name, "Carol");
+        assert!(summaries.iter().all(|summary| summary.name != "Random"));
+    }
+
+}
@@ -0,0 +1,388 @@
+use std::path::{Path, PathBuf};
+use chrono_tz::Tz;
+use lambda_runtime::Error;
+use crate::{daemon::Schedule, error::PollerError, outcome::ErrorMode};
+use crate::config::{self, Config, RunConfig, Setting};
+use crate::filter::{bootstrap::Bootstrap, dedup::Dedup, rules::MatchFilter};
+use crate::publisher::{digest::DigestOptions, dry_run::DryRunOutput, highlights::HighlightThresholds, publisher::PlayerList, streaks};
+use crate::report::summary::timezone_from_config;
+use crate::store::{dynamo::TableNames, state::StoreKind};
+
+const DEFAULT_GUILD_ID: i64 = 117311;
+const DEFAULT_TAKE: i64 = 5;
+const DEFAULT_KOOK_TARGET_ID: &str = "3193188266865676";
+const DEFAULT_LEASE_SECONDS: i64 = 90;
+const DEFAULT_DEADLINE_MARGIN_SECONDS: i64 = 5;
+const DEFAULT_ALERT_THRESHOLD: i64 = 3;
+const DEFAULT_HERO_ASSETS_DIR: &str = "assets/heroes";
+const DEFAULT_METRICS_NAMESPACE: &str = "NanTuanTracker";
+
+/// Struct containing the guild polled
+#[derive(Debug, PartialEq, Eq)]
+pub struct GuildSettings {
+    pub id: i64,
+    /// The number of latest matches of the guild fetched by a run
+    pub take: i64
+}
+
+/// Struct containing the credentials of the Dota2 data provider
+pub struct ProviderSettings {
+    pub stratz_jwt: String
+}
+
+/// Struct containing where matches and reports are published
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SinkSettings {
+    pub kook_token: String,
+    pub kook_target_id: String,
+    pub report_kook_target_id: String,
+    pub discord_webhook_url: String,
+    pub report_discord_webhook_url: String,
+    /// The Kook channel operational alerts are sent to, if any
+    pub alert_kook_target_id: Option<String>,
+    /// The Discord webhook operational alerts are sent to, if any
+    pub alert_discord_webhook_url: Option<String>
+}
+
+/// Struct containing how matches and reports are rendered
+#[derive(Debug)]
+pub struct TemplateSettings {
+    pub hero_assets_dir: PathBuf,
+    pub highlights: HighlightThresholds,
+    pub streak_threshold: i64,
+    pub digest: DigestOptions,
+    pub report_timezone: Tz
+}
+
+/// Struct containing where and how long the state is kept
+#[derive(Debug)]
+pub struct StorageSettings {
+    pub kind: StoreKind,
+    pub dynamodb_endpoint: Option<String>,
+    pub tables: TableNames,
+    pub dedup: Dedup,
+    pub bootstrap: Bootstrap,
+    pub lease_seconds: i64
+}
+
+/// Struct containing how the poller runs
+#[derive(Debug)]
+pub struct RunSettings {
+    pub daemon: bool,
+    pub schedule: Schedule,
+    pub error_mode: ErrorMode,
+    pub deadline_margin: chrono::Duration,
+    pub metrics_port: Option<u16>,
+    pub metrics_namespace: String,
+    /// The number of failed cycles in a row of a component before alerting the admins
+    pub alert_threshold: i64,
+    pub dry_run: Option<DryRunOutput>
+}
+
+/// The configuration of the poller, loaded and validated once at startup
+pub struct Settings {
+    pub guild: GuildSettings,
+    pub provider: ProviderSettings,
+    pub sinks: SinkSettings,
+    pub players: PlayerList,
+    pub filters: MatchFilter,
+    pub templates: TemplateSettings,
+    pub storage: StorageSettings,
+    pub run: RunSettings
+}
+
+impl Settings {
+
+    /// Load the configuration from the TOML file at `path`, if any, overridden by the environmental variables
+    /// Otherwise, a configuration error listing every missing or invalid field
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the configuration file, usually from `CONFIG_FILE` environmental variable
+    pub fn load(path: Option<&Path>) -> Result<Settings, Error> {
+        Settings::load_with(path, &config::env_var)
+    }
+
+    /// Load the configuration from the TOML file at `path`, if any, overridden by the environmental variables found
+    /// by `lookup`
+    /// Otherwise, a configuration error listing every missing or invalid field
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the configuration file
+    /// * `lookup` - The lookup of an environmental variable by name
+    pub fn load_with(path: Option<&Path>, lookup: &dyn Fn(&str) -> Option<String>) -> Result<Settings, Error> {
+        let mut errors = Vec::new();
+        let mut config = match path.map(Config::read) {
+            Some(Ok(config)) => config,
+            Some(Err(e)) => {
+                errors.push(e);
+                Config::default()
+            },
+            None => Config::default()
+        };
+        errors.extend(config.apply_env(lookup));
+        Settings::resolve(&config, errors)
+    }
+
+    /// Validate the typed sections of `config` into the settings of the poller
+    /// Otherwise, a configuration error listing `errors` along with every missing or invalid field
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The configuration file with the environmental overrides applied
+    /// * `errors` - The errors of the unknown or invalid fields found so far
+    fn resolve(config: &Config, errors: Vec<String>) -> Result<Settings, Error> {
+        let mut check = Check { errors };
+        let guild_id = check.result(parse_or(&config.guild.id, "GUILD_ID", DEFAULT_GUILD_ID));
+        let take = check.result(parse_or(&config.guild.take, "POLL_TAKE", DEFAULT_TAKE));
+        let stratz_jwt = check.required(&config.provider.stratz_jwt, "STRATZ_JWT");
+        let kook = &config.sinks.kook;
+        let discord = &config.sinks.discord;
+        let kook_token = check.required(&kook.token, "KOOK_TOKEN");
+        let discord_webhook_url = check.required(&discord.webhook_url, "DISCORD_WEBHOOK_URL");
+        let players = check.result(PlayerList::from_config(&config.players));
+        let filters = check.result(MatchFilter::from_config(&config.filters));
+        let templates = &config.templates;
+        let report_timezone = check.result(timezone_from_config(templates));
+        let storage = &config.storage;
+        let kind = check.result(StoreKind::from_config(storage));
+        let dedup = check.result(Dedup::from_config(storage));
+        let bootstrap = check.result(Bootstrap::from_config(storage));
+        let lease_seconds = check.result(parse_or(&storage.lease_seconds, "LEASE_SECONDS", DEFAULT_LEASE_SECONDS));
+        let run = &config.run;
+        let daemon = check.result(is_daemon(run));
+        let schedule = check.result(Schedule::from_config(run));
+        let error_mode = check.result(ErrorMode::from_config(run));
+        let deadline_margin = check.result(parse_or(
+            &run.deadline_margin_seconds, "DEADLINE_MARGIN_SECONDS", DEFAULT_DEADLINE_MARGIN_SECONDS
+        ));
+        let metrics_port = check.result(metrics_port(run));
+        let alert_threshold = check.result(parse_or(&run.alert_threshold, "ALERT_THRESHOLD", DEFAULT_ALERT_THRESHOLD));
+
+        let settings = (|| {
+            let kook_target_id = text_or(&kook.target_id, DEFAULT_KOOK_TARGET_ID);
+            let discord_webhook_url = discord_webhook_url?;
+
+            Some(Settings {
+                guild: GuildSettings { id: guild_id?, take: take? },
+                provider: ProviderSettings { stratz_jwt: stratz_jwt? },
+                sinks: SinkSettings {
+                    kook_token: kook_token?,
+                    report_kook_target_id: text_or(&kook.report_target_id, &kook_target_id),
+                    kook_target_id,
+                    report_discord_webhook_url: text_or(&discord.report_webhook_url, &discord_webhook_url),
+                    discord_webhook_url,
+                    alert_kook_target_id: kook.alert_target_id.value().cloned(),
+                    alert_discord_webhook_url: discord.alert_webhook_url.value().cloned()
+                },
+                players: players?,
+                filters: filters?,
+                templates: TemplateSettings {
+                    hero_assets_dir: templates.hero_assets_dir.value()
+                        .cloned()
+                        .unwrap_or_else(|| PathBuf::from(DEFAULT_HERO_ASSETS_DIR)),
+                    highlights: HighlightThresholds::from_config(&templates.highlights),
+                    streak_threshold: streaks::threshold_from_config(templates),
+                    digest: DigestOptions::from_config(&templates.digest),
+                    report_timezone: report_timezone?
+                },
+                storage: StorageSettings {
+                    kind: kind?,
+                    dynamodb_endpoint: storage.dynamodb_endpoint.value().cloned(),
+                    tables: TableNames::from_config(&storage.tables),
+                    dedup: dedup?,
+                    bootstrap: bootstrap?,
+                    lease_seconds: lease_seconds?
+                },
+                run: RunSettings {
+                    daemon: daemon?,
+                    schedule: schedule?,
+                    error_mode: error_mode?,
+                    deadline_margin: chrono::Duration::seconds(deadline_margin?),
+                    metrics_port: metrics_port?,
+                    metrics_namespace: text_or(&run.metrics_namespace, DEFAULT_METRICS_NAMESPACE),
+                    alert_threshold: alert_threshold?,
+                    dry_run: DryRunOutput::from_config(run)
+                }
+            })
+        })();
+
+        match settings {
+            Some(settings) if check.errors.is_empty() => Ok(settings),
+            _ => Err(PollerError::Config(check.errors).into())
+        }
+    }
+
+}
+
+/// Collector of the errors of every field, so that all of them are reported at once
+struct Check {
+    errors: Vec<String>
+}
+
+impl Check {
+
+    /// Get the value of a parsed field, keeping its error if any
+    fn result<T>(&mut self, result: Result<T, Error>) -> Option<T> {
+        result.map_err(|e| self.errors.push(e.to_string())).ok()
+    }
+
+    /// Get the value of a field without default, keeping an error when it is missing. An invalid field is already
+    /// reported.
+    fn required(&mut self, setting: &Setting<String>, name: &str) -> Option<String> {
+        match setting {
+            Setting::Value(value) if !value.trim().is_empty() => Some(value.to_string()),
+            Setting::Invalid(_) => None,
+            _ => {
+                self.errors.push(format!("Missing {}", name));
+                None
+            }
+        }
+    }
+
+}
+
+/// Get the text of a setting, falling back to `default` when the setting is missing
+pub fn text_or(setting: &Setting<String>, default: &str) -> String {
+    setting.value().cloned().unwrap_or_else(|| default.to_string())
+}
+
+/// Get a positive number of a setting, falling back to `default` when the setting is missing
+///
+/// # Arguments
+///
+/// * `setting` - The setting
+/// * `name` - The name of the environmental variable of the setting, used in error messages
+/// * `default` - The value applying when the setting is missing
+pub fn parse_or(setting: &Setting<i64>, name: &str, default: i64) -> Result<i64, Error> {
+    match setting.value() {
+        Some(number) if *number > 0 => Ok(*number),
+        Some(number) => Err(format!("Invalid {}: {}", name, number).into()),
+        None => Ok(default)
+    }
+}
+
+/// Get the port the daemon serves metrics on from the `run.metrics_port` setting
+/// Otherwise, None and metrics are not served
+fn metrics_port(run: &RunConfig) -> Result<Option<u16>, Error> {
+    match run.metrics_port.value() {
+        Some(0) => Err("Invalid METRICS_PORT: 0".into()),
+        port => Ok(port.copied())
+    }
+}
+
+/// Check whether the poller runs as a daemon from the `run.mode` setting, `lambda` or `daemon`
+fn is_daemon(run: &RunConfig) -> Result<bool, Error> {
+    match run.mode.value() {
+        None => Ok(false),
+        Some(value) => match value.trim().to_lowercase().as_str() {
+            "lambda" => Ok(false),
+            "daemon" => Ok(true),
+            _ => Err(format!("Invalid RUN_MODE: {}", value).into())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::collections::HashMap;
+    use crate::error::PollerError;
+    use super::Settings;
+
+    fn lookup(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let vars: HashMap<String, String> = vars.iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        move |name| vars.get(name).cloned()
+    }
+
+    #[test]
+    fn test_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("poller.toml");
+        std::fs::write(&path, r#"
+            [guild]
+            id = 117311
+            take = 10
+
+            [provider]
+            stratz_jwt = "TestingJWT"
+
+            [sinks.kook]
+            token = "TestingToken"
+
+            [sinks.discord]
+            webhook_url = "http://localhost/webhook"
+
+            [storage]
+            backend = "sqlite"
+            database = "/tmp/poller.db"
+        "#).unwrap();
+
+        let settings = Settings::load_with(Some(&path), &lookup(&[("POLL_TAKE", "20")])).unwrap();
+        assert_eq!(settings.guild.id, 117311);
+        assert_eq!(settings.guild.take, 20);
+        assert_eq!(settings.provider.stratz_jwt, "TestingJWT");
+        assert_eq!(settings.sinks.kook_target_id, "3193188266865676");
+        assert_eq!(settings.sinks.report_discord_webhook_url, "http://localhost/webhook");
+        assert_eq!(settings.storage.tables.guilds, "Guilds");
+        assert_eq!(settings.run.metrics_namespace, "NanTuanTracker");
+    }
+
+    #[test]
+    fn test_load_reports_every_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("poller.toml");
+        std::fs::write(&path, r#"
+            [guild]
+            id = "NanTuan"
+            take = 0
+
+            [sinks.kook]
+            token = "TestingToken"
+
+            [filters]
+            lobby_types = ["RANKED", "CASUAL"]
+            exclude_abandoned = "yes"
+
+            [templates]
+            report_timezone = "Mars/Olympus"
+
+            [storage]
+            backend = "postgres"
+            lease_time = 90
+
+            [run]
+            mode = "cron"
+        "#).unwrap();
+
+        let error = Settings::load_with(Some(&path), &lookup(&[("METRICS_PORT", "http")])).err().unwrap();
+        let Some(PollerError::Config(errors)) = error.downcast_ref::<PollerError>() else {
+            panic!("Expected a configuration error, got {}", error);
+        };
+        assert_eq!(errors, &vec![
+            "Invalid field guild.id: invalid type: string \"NanTuan\", expected i64",
+            "Invalid field filters.exclude_abandoned: invalid type: string \"yes\", expected a boolean",
+            "Unknown field storage.lease_time",
+            "Invalid METRICS_PORT: http",
+            "Invalid POLL_TAKE: 0",
+            "Missing STRATZ_JWT",
+            "Missing DISCORD_WEBHOOK_URL",
+            "Invalid FILTER_LOBBY_TYPES: CASUAL",
+            "Invalid REPORT_TIMEZONE: Mars/Olympus",
+            "Invalid STATE_STORE: postgres",
+            "Invalid RUN_MODE: cron"
+        ]);
+    }
+
+    #[test]
+    fn test_load_missing_file() {
+        let error = Settings::load_with(Some(std::path::Path::new("/nonexistent/poller.toml")), &lookup(&[]))
+            .err()
+            .unwrap();
+        assert!(error.to_string().contains("Cannot read configuration file /nonexistent/poller.toml"));
+    }
+
+}
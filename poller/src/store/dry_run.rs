@@ -0,0 +1,157 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use lambda_runtime::Error;
+use crate::alert::ComponentHealth;
+use crate::publisher::{publisher::PublishRecord, streaks::Streak};
+use super::{memory::MemoryStore, state::{QuarantinedMatch, StateStore}};
+
+/// State store of a dry run, reading through to the real store and keeping every write in memory, so that a dry run
+/// behaves like a real one without advancing the checkpoint or touching the ledgers. Head-to-head records cannot be
+/// read without being incremented, so they start from zero.
+pub struct DryRunStore<'a> {
+    pub inner: &'a dyn StateStore,
+    pub overlay: MemoryStore
+}
+
+impl<'a> DryRunStore<'a> {
+
+    /// Build the store of a dry run on top of `inner`, which is only read
+    pub fn new(inner: &'a dyn StateStore) -> DryRunStore<'a> {
+        DryRunStore { inner, overlay: MemoryStore::default() }
+    }
+
+}
+
+#[async_trait]
+impl StateStore for DryRunStore<'_> {
+
+    async fn get_checkpoint(&self, guild_id: i64) -> Result<Option<i64>, Error> {
+        match self.overlay.get_checkpoint(guild_id).await? {
+            Some(match_id) => Ok(Some(match_id)),
+            None => self.inner.get_checkpoint(guild_id).await
+        }
+    }
+
+    async fn save_checkpoint(&self, guild_id: i64, _expected: Option<i64>, match_id: i64) -> Result<bool, Error> {
+        self.overlay.with_state(|state| {
+            state.checkpoints.insert(guild_id, match_id);
+            true
+        })
+    }
+
+    async fn acquire_lease(&self, name: &str, owner: &str, expires_at: DateTime<Utc>) -> Result<bool, Error> {
+        self.overlay.acquire_lease(name, owner, expires_at).await
+    }
+
+    async fn release_lease(&self, name: &str, owner: &str) -> Result<(), Error> {
+        self.overlay.release_lease(name, owner).await
+    }
+
+    async fn quarantine_match(&self, match_id: i64, reason: &str, quarantined_at: DateTime<Utc>) -> Result<(), Error> {
+        self.overlay.quarantine_match(match_id, reason, quarantined_at).await
+    }
+
+    async fn quarantined_matches(&self) -> Result<Vec<QuarantinedMatch>, Error> {
+        self.inner.quarantined_matches().await
+    }
+
+    async fn is_seen(&self, match_id: i64) -> Result<bool, Error> {
+        Ok(self.overlay.is_seen(match_id).await? || self.inner.is_seen(match_id).await?)
+    }
+
+    async fn mark_seen(&self, match_id: i64, expires_at: DateTime<Utc>) -> Result<(), Error> {
+        self.overlay.mark_seen(match_id, expires_at).await
+    }
+
+    async fn is_delivered(&self, match_id: &str, sink: &str) -> Result<bool, Error> {
+        Ok(self.overlay.is_delivered(match_id, sink).await? || self.inner.is_delivered(match_id, sink).await?)
+    }
+
+    async fn mark_delivered(&self, match_id: &str, sink: &str) -> Result<(), Error> {
+        self.overlay.mark_delivered(match_id, sink).await
+    }
+
+    async fn record_match(&self, publish_record: &PublishRecord) -> Result<(), Error> {
+        self.overlay.record_match(publish_record).await
+    }
+
+    async fn get_match(&self, match_id: &str) -> Result<Option<PublishRecord>, Error> {
+        match self.overlay.get_match(match_id).await? {
+            Some(publish_record) => Ok(Some(publish_record)),
+            None => self.inner.get_match(match_id).await
+        }
+    }
+
+    async fn matches_of_player(
+        &self,
+        steam_account_id: i64,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>
+    ) -> Result<Vec<PublishRecord>, Error> {
+        self.inner.matches_of_player(steam_account_id, start, end).await
+    }
+
+    async fn matches_of_guild(
+        &self,
+        guild_id: &str,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>
+    ) -> Result<Vec<PublishRecord>, Error> {
+        self.inner.matches_of_guild(guild_id, start, end).await
+    }
+
+    async fn increment_head_to_head(&self, winner_id: i64, loser_id: i64, match_id: i64) -> Result<(i64, i64), Error> {
+        self.overlay.increment_head_to_head(winner_id, loser_id, match_id).await
+    }
+
+    async fn get_streak(&self, steam_account_id: i64) -> Result<Streak, Error> {
+        match self.overlay.with_state(|state| state.streaks.get(&steam_account_id).copied())? {
+            Some(streak) => Ok(streak),
+            None => self.inner.get_streak(steam_account_id).await
+        }
+    }
+
+    async fn save_streak(&self, steam_account_id: i64, streak: &Streak) -> Result<(), Error> {
+        self.overlay.save_streak(steam_account_id, streak).await
+    }
+
+    async fn get_health(&self, component: &str) -> Result<ComponentHealth, Error> {
+        match self.overlay.with_state(|state| state.health.get(component).copied())? {
+            Some(health) => Ok(health),
+            None => self.inner.get_health(component).await
+        }
+    }
+
+    async fn save_health(&self, component: &str, health: &ComponentHealth) -> Result<(), Error> {
+        self.overlay.save_health(component, health).await
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::store::{memory::MemoryStore, state::StateStore};
+    use super::DryRunStore;
+
+    #[tokio::test]
+    async fn test_writes_stay_in_overlay() {
+        let inner = MemoryStore::default();
+        inner.save_checkpoint(117311, None, 6789012345).await.unwrap();
+        inner.mark_delivered("6789012345", "kook").await.unwrap();
+
+        let store = DryRunStore::new(&inner);
+        assert_eq!(store.get_checkpoint(117311).await.unwrap(), Some(6789012345));
+        assert!(store.is_delivered("6789012345", "kook").await.unwrap());
+
+        // A stale expectation does not matter, as nothing else writes to the overlay
+        assert!(store.save_checkpoint(117311, None, 6789012346).await.unwrap());
+        store.mark_delivered("6789012346", "kook").await.unwrap();
+        assert_eq!(store.get_checkpoint(117311).await.unwrap(), Some(6789012346));
+        assert!(store.is_delivered("6789012346", "kook").await.unwrap());
+
+        assert_eq!(inner.get_checkpoint(117311).await.unwrap(), Some(6789012345));
+        assert!(!inner.is_delivered("6789012346", "kook").await.unwrap());
+    }
+
+}
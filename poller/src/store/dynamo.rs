@@ -0,0 +1,471 @@
+use async_trait::async_trait;
+use aws_sdk_dynamodb::model::AttributeValue;
+use chrono::{DateTime, Utc};
+use lambda_runtime::Error;
+use crate::{alert::ComponentHealth, config::TablesConfig, error::PollerError};
+use crate::settings::text_or;
+use crate::publisher::{clash::pair_id, publisher::{PublishRecord, PlayerStats}, streaks::Streak};
+use crate::utils::dynamo::{DynamoClient, Item};
+use super::{memory::delivery_key, state::{QuarantinedMatch, StateStore}};
+
+/// Sort keys of the history are `<end timestamp>#<match id>`, so this sorts after every sort key of the same second
+const SORT_KEY_UPPER_SUFFIX: &str = "#~";
+const SORT_KEY_MIN: &str = "0";
+const SORT_KEY_MAX: &str = "~";
+
+/// Names of the AWS DynamoDB tables of the state store
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TableNames {
+    pub guilds: String,
+    pub leases: String,
+    pub seen_matches: String,
+    pub quarantine: String,
+    pub deliveries: String,
+    pub head_to_head: String,
+    pub streaks: String,
+    pub health: String,
+    pub match_history: String
+}
+
+impl TableNames {
+
+    /// Get the table names from the `storage.tables` settings, the names of the stack by default
+    pub fn from_config(tables: &TablesConfig) -> TableNames {
+        TableNames {
+            guilds: text_or(&tables.guilds, "Guilds"),
+            leases: text_or(&tables.leases, "Leases"),
+            seen_matches: text_or(&tables.seen_matches, "SeenMatches"),
+            quarantine: text_or(&tables.quarantine, "Quarantine"),
+            deliveries: text_or(&tables.deliveries, "Deliveries"),
+            head_to_head: text_or(&tables.head_to_head, "HeadToHead"),
+            streaks: text_or(&tables.streaks, "Streaks"),
+            health: text_or(&tables.health, "Health"),
+            match_history: text_or(&tables.match_history, "MatchHistory")
+        }
+    }
+
+}
+
+/// State store keeping everything in AWS DynamoDB tables, named after `tables`
+/// - `Guilds` keeps the checkpoint of each guild
+/// - `Leases` keeps the holder of each guild lease, expiring through the TTL attribute `expires_at`
+/// - `SeenMatches` keeps the seen match ids until the TTL attribute `expires_at`
+/// - `Quarantine` keeps the matches which could not be published, with the reason
+/// - `Deliveries` keeps the delivery ledger under `<match_id>#<sink>`
+/// - `HeadToHead` keeps the wins of each pair of guild members under `<low id>#<high id>`, and the latest match
+///   counted
+/// - `Streaks` keeps the streaks of each guild member
+/// - `Health` keeps the failed cycles in a row of each component, and whether the admins were alerted
+/// - `MatchHistory` keeps every published match three ways, so that it can be read by match under
+///   `match#<match_id>`, by guild under `guild#<guild_id>` and by guild member under `player#<steam_account_id>`
+pub struct DynamoStore {
+    pub client: DynamoClient,
+    pub tables: TableNames
+}
+
+impl DynamoStore {
+
+    /// Get the records stored in the history under `pk` which ended between `start` and `end`, both inclusive
+    async fn query_between(&self, pk: &str, start: DateTime<Utc>, end: DateTime<Utc>) -> Result<Vec<PublishRecord>, Error> {
+        let from = end_prefix(start);
+        let to = format!("{}{}", end_prefix(end), SORT_KEY_UPPER_SUFFIX);
+        let items = self.client.query_range(&self.tables.match_history, pk, &from, &to).await?;
+
+        items.iter().map(record_from_item).collect()
+    }
+
+}
+
+#[async_trait]
+impl StateStore for DynamoStore {
+
+    async fn get_checkpoint(&self, guild_id: i64) -> Result<Option<i64>, Error> {
+        let output = match self.client.get_item(&self.tables.guilds, guild_id).await {
+            Ok(output) => output,
+            Err(aws_sdk_dynamodb::Error::ResourceNotFoundException(_)) => {
+                let message = format!("Table {} not found, deploy the stack before running the poller", self.tables.guilds);
+                return Err(PollerError::State(message).into());
+            },
+            Err(e) => return Err(e.into())
+        };
+        let match_id = match output.item().and_then(|item| item.get("match_id")) {
+            Some(match_id) => match_id,
+            None => return Ok(None)
+        };
+        let match_id = match_id.as_n()
+            .ok()
+            .and_then(|match_id| match_id.parse::<i64>().ok())
+            .ok_or_else(|| PollerError::State(format!("Invalid checkpoint of guild {}", guild_id)))?;
+
+        Ok(Some(match_id))
+    }
+
+    async fn save_checkpoint(&self, guild_id: i64, expected: Option<i64>, match_id: i64) -> Result<bool, Error> {
+        Ok(self.client.put_item(&self.tables.guilds, guild_id, expected, match_id).await?)
+    }
+
+    async fn acquire_lease(&self, name: &str, owner: &str, expires_at: DateTime<Utc>) -> Result<bool, Error> {
+        let item = Item::from([
+            ("id".to_string(), AttributeValue::S(name.to_string())),
+            ("holder".to_string(), AttributeValue::S(owner.to_string())),
+            ("expires_at".to_string(), AttributeValue::N(expires_at.timestamp().to_string()))
+        ]);
+        let values = Item::from([
+            (":owner".to_string(), AttributeValue::S(owner.to_string())),
+            (":now".to_string(), AttributeValue::N(Utc::now().timestamp().to_string()))
+        ]);
+        let acquired = self.client.put_attributes_if(
+            &self.tables.leases, item, "attribute_not_exists(id) OR holder = :owner OR expires_at <= :now", values
+        ).await;
+
+        Ok(acquired?)
+    }
+
+    async fn release_lease(&self, name: &str, owner: &str) -> Result<(), Error> {
+        let values = Item::from([(":owner".to_string(), AttributeValue::S(owner.to_string()))]);
+        self.client.delete_item_if(&self.tables.leases, name, "holder = :owner", values).await?;
+
+        Ok(())
+    }
+
+    async fn quarantine_match(&self, match_id: i64, reason: &str, quarantined_at: DateTime<Utc>) -> Result<(), Error> {
+        let item = Item::from([
+            ("id".to_string(), AttributeValue::S(match_id.to_string())),
+            ("reason".to_string(), AttributeValue::S(reason.to_string())),
+            ("quarantined_at".to_string(), AttributeValue::N(quarantined_at.timestamp().to_string()))
+        ]);
+        self.client.put_attributes(&self.tables.quarantine, item).await?;
+
+        Ok(())
+    }
+
+    async fn quarantined_matches(&self) -> Result<Vec<QuarantinedMatch>, Error> {
+        let items = self.client.scan_items(&self.tables.quarantine).await?;
+        let mut quarantined_matches: Vec<QuarantinedMatch> = items.iter()
+            .map(quarantined_match_from_item)
+            .collect::<Result<_, Error>>()?;
+        quarantined_matches.sort_by_key(|quarantined_match| (quarantined_match.quarantined_at, quarantined_match.match_id));
+
+        Ok(quarantined_matches)
+    }
+
+    async fn is_seen(&self, match_id: i64) -> Result<bool, Error> {
+        let item = self.client.get_attributes(&self.tables.seen_matches, &match_id.to_string()).await?;
+
+        // DynamoDB deletes expired items lazily, so the expiry is checked here too
+        let expires_at = item.as_ref()
+            .and_then(|item| item.get("expires_at"))
+            .and_then(|expires_at| expires_at.as_n().ok())
+            .and_then(|expires_at| expires_at.parse::<i64>().ok());
+        Ok(expires_at.is_some_and(|expires_at| expires_at > Utc::now().timestamp()))
+    }
+
+    async fn mark_seen(&self, match_id: i64, expires_at: DateTime<Utc>) -> Result<(), Error> {
+        let item = Item::from([
+            ("id".to_string(), AttributeValue::S(match_id.to_string())),
+            ("expires_at".to_string(), AttributeValue::N(expires_at.timestamp().to_string()))
+        ]);
+        self.client.put_attributes(&self.tables.seen_matches, item).await?;
+
+        Ok(())
+    }
+
+    async fn is_delivered(&self, match_id: &str, sink: &str) -> Result<bool, Error> {
+        let item = self.client.get_attributes(&self.tables.deliveries, &delivery_key(match_id, sink)).await?;
+        Ok(item.is_some())
+    }
+
+    async fn mark_delivered(&self, match_id: &str, sink: &str) -> Result<(), Error> {
+        let item = Item::from([
+            ("id".to_string(), AttributeValue::S(delivery_key(match_id, sink))),
+            ("delivered_at".to_string(), AttributeValue::N(Utc::now().timestamp().to_string()))
+        ]);
+        self.client.put_attributes(&self.tables.deliveries, item).await?;
+
+        Ok(())
+    }
+
+    async fn record_match(&self, publish_record: &PublishRecord) -> Result<(), Error> {
+        for item in history_items(publish_record)? {
+            self.client.put_attributes(&self.tables.match_history, item).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn get_match(&self, match_id: &str) -> Result<Option<PublishRecord>, Error> {
+        let items = self.client.query_range(
+            &self.tables.match_history, &match_key(match_id), SORT_KEY_MIN, SORT_KEY_MAX
+        ).await?;
+
+        items.first().map(record_from_item).transpose()
+    }
+
+    async fn matches_of_player(
+        &self,
+        steam_account_id: i64,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>
+    ) -> Result<Vec<PublishRecord>, Error> {
+        self.query_between(&player_key(steam_account_id), start, end).await
+    }
+
+    async fn matches_of_guild(
+        &self,
+        guild_id: &str,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>
+    ) -> Result<Vec<PublishRecord>, Error> {
+        self.query_between(&guild_key(guild_id), start, end).await
+    }
+
+    async fn increment_head_to_head(&self, winner_id: i64, loser_id: i64, match_id: i64) -> Result<(i64, i64), Error> {
+        let pair_id = pair_id(winner_id, loser_id);
+        let incremented = self.client.increment_counter(
+            &self.tables.head_to_head,
+            &pair_id,
+            &wins_attribute(winner_id),
+            match_id
+        ).await?;
+        // A match counted already, e.g. by a run which failed to deliver it, leaves the record as is
+        let attributes = match incremented {
+            Some(attributes) => Some(attributes),
+            None => self.client.get_attributes(&self.tables.head_to_head, &pair_id).await?
+        };
+        let wins = |steam_account_id: i64| -> i64 {
+            attributes.as_ref()
+                .and_then(|attributes| attributes.get(&wins_attribute(steam_account_id)))
+                .and_then(|value| value.as_n().ok())
+                .and_then(|value| value.parse::<i64>().ok())
+                .unwrap_or_default()
+        };
+
+        Ok((wins(winner_id), wins(loser_id)))
+    }
+
+    async fn get_streak(&self, steam_account_id: i64) -> Result<Streak, Error> {
+        let item = self.client.get_attributes(&self.tables.streaks, &steam_account_id.to_string()).await?;
+        Ok(item.map(|item| streak_from_item(&item)).unwrap_or_default())
+    }
+
+    async fn save_streak(&self, steam_account_id: i64, streak: &Streak) -> Result<(), Error> {
+        self.client.put_attributes(&self.tables.streaks, streak_item(&steam_account_id.to_string(), streak)).await?;
+        Ok(())
+    }
+
+    async fn get_health(&self, component: &str) -> Result<ComponentHealth, Error> {
+        let item = self.client.get_attributes(&self.tables.health, component).await?;
+        Ok(item.map(|item| health_from_item(&item)).unwrap_or_default())
+    }
+
+    async fn save_health(&self, component: &str, health: &ComponentHealth) -> Result<(), Error> {
+        let item = Item::from([
+            ("id".to_string(), AttributeValue::S(component.to_string())),
+            ("failures".to_string(), AttributeValue::N(health.failures.to_string())),
+            ("alerted".to_string(), AttributeValue::Bool(health.alerted))
+        ]);
+        self.client.put_attributes(&self.tables.health, item).await?;
+        Ok(())
+    }
+
+}
+
+/// Build the items storing `publish_record` under its match, its guild and each of its guild members. Anonymous
+/// players cannot be told apart between matches and are not stored under a player.
+///
+/// # Arguments
+///
+/// * `publish_record` - The data POJO of a published match
+pub fn history_items(publish_record: &PublishRecord) -> Result<Vec<Item>, Error> {
+    let record = serde_json::to_string(publish_record)?;
+    let sort_key = format!("{}#{}", end_prefix(publish_record.end), publish_record.match_id);
+    let item = |pk: String| -> Item {
+        Item::from([
+            ("pk".to_string(), AttributeValue::S(pk)),
+            ("sk".to_string(), AttributeValue::S(sort_key.to_string())),
+            ("match_id".to_string(), AttributeValue::N(publish_record.match_id.to_string())),
+            ("end".to_string(), AttributeValue::N(publish_record.end.timestamp().to_string())),
+            ("record".to_string(), AttributeValue::S(record.to_string()))
+        ])
+    };
+
+    let mut items = vec![
+        item(match_key(&publish_record.match_id)),
+        item(guild_key(&publish_record.guild_id))
+    ];
+    let players_stats = publish_record.player_stats_radiant.iter().chain(publish_record.player_stats_dire.iter());
+    for player_stats in players_stats.filter(|player_stats| player_stats.is_guild_member) {
+        if let Some(steam_account_id) = player_stats.steam_account_id {
+            let mut player_item = item(player_key(steam_account_id));
+            player_item.extend(player_attributes(player_stats));
+            items.push(player_item);
+        }
+    }
+
+    Ok(items)
+}
+
+/// Get the stats of a guild member kept as attributes next to the record, for queries that do not need the record
+fn player_attributes(player_stats: &PlayerStats) -> Item {
+    Item::from([
+        ("hero_id".to_string(), AttributeValue::N(player_stats.hero_id.to_string())),
+        ("hero".to_string(), AttributeValue::S(player_stats.hero_display_name.to_string())),
+        ("kills".to_string(), AttributeValue::N(player_stats.kills.to_string())),
+        ("deaths".to_string(), AttributeValue::N(player_stats.deaths.to_string())),
+        ("assists".to_string(), AttributeValue::N(player_stats.assists.to_string())),
+        ("networth".to_string(), AttributeValue::N(player_stats.networth.to_string())),
+        ("is_victory".to_string(), AttributeValue::Bool(player_stats.is_victory))
+    ])
+}
+
+/// Parse the record stored in a history item
+fn record_from_item(item: &Item) -> Result<PublishRecord, Error> {
+    let record = item.get("record")
+        .and_then(|record| record.as_s().ok())
+        .ok_or("History item without record")?;
+
+    Ok(serde_json::from_str(record)?)
+}
+
+/// Get the zero-padded end timestamp which sorts history items chronologically
+fn end_prefix(end: DateTime<Utc>) -> String {
+    format!("{:012}", end.timestamp())
+}
+
+fn match_key(match_id: &str) -> String {
+    format!("match#{}", match_id)
+}
+
+fn guild_key(guild_id: &str) -> String {
+    format!("guild#{}", guild_id)
+}
+
+fn player_key(steam_account_id: i64) -> String {
+    format!("player#{}", steam_account_id)
+}
+
+/// Get the name of the attribute counting the wins of a player in a head-to-head item
+fn wins_attribute(steam_account_id: i64) -> String {
+    format!("wins_{}", steam_account_id)
+}
+
+/// Build the item storing the streaks of a guild member
+fn streak_item(id: &str, streak: &Streak) -> Item {
+    Item::from([
+        ("id".to_string(), AttributeValue::S(id.to_string())),
+        ("current".to_string(), AttributeValue::N(streak.current.to_string())),
+        ("best_win".to_string(), AttributeValue::N(streak.best_win.to_string())),
+        ("best_loss".to_string(), AttributeValue::N(streak.best_loss.to_string())),
+        ("last_match_id".to_string(), AttributeValue::N(streak.last_match_id.to_string())),
+        ("previous".to_string(), AttributeValue::N(streak.previous.to_string()))
+    ])
+}
+
+/// Parse the streaks stored in an item, missing attributes counting as zero
+fn streak_from_item(item: &Item) -> Streak {
+    let number = |attribute: &str| -> i64 {
+        item.get(attribute)
+            .and_then(|value| value.as_n().ok())
+            .and_then(|value| value.parse::<i64>().ok())
+            .unwrap_or_default()
+    };
+
+    Streak {
+        current: number("current"),
+        best_win: number("best_win"),
+        best_loss: number("best_loss"),
+        last_match_id: number("last_match_id"),
+        previous: number("previous")
+    }
+}
+
+/// Parse the health of a component stored in an item, missing attributes counting as healthy
+fn health_from_item(item: &Item) -> ComponentHealth {
+    ComponentHealth {
+        failures: item.get("failures")
+            .and_then(|value| value.as_n().ok())
+            .and_then(|value| value.parse::<i64>().ok())
+            .unwrap_or_default(),
+        alerted: item.get("alerted")
+            .and_then(|value| value.as_bool().ok())
+            .copied()
+            .unwrap_or_default()
+    }
+}
+
+/// Parse a quarantined match stored in an item
+fn quarantined_match_from_item(item: &Item) -> Result<QuarantinedMatch, Error> {
+    let match_id = item.get("id")
+        .and_then(|value| value.as_s().ok())
+        .and_then(|value| value.parse::<i64>().ok())
+        .ok_or("Invalid quarantined match id")?;
+    let reason = item.get("reason")
+        .and_then(|value| value.as_s().ok())
+        .cloned()
+        .unwrap_or_default();
+    let quarantined_at = item.get("quarantined_at")
+        .and_then(|value| value.as_n().ok())
+        .and_then(|value| value.parse::<i64>().ok())
+        .and_then(|value| DateTime::<Utc>::from_timestamp(value, 0))
+        .ok_or_else(|| format!("Invalid quarantine time of match {}", match_id))?;
+
+    Ok(QuarantinedMatch { match_id, reason, quarantined_at })
+}
+
+#[cfg(test)]
+mod tests {
+
+    use aws_sdk_dynamodb::model::AttributeValue;
+    use crate::publisher::fixtures::publish_record;
+    use crate::publisher::publisher::MatchResult;
+    use crate::publisher::streaks::Streak;
+    use super::{history_items, quarantined_match_from_item, record_from_item, streak_from_item, streak_item, wins_attribute};
+
+    #[test]
+    fn test_history_items() {
+        let mut record = publish_record(MatchResult::Victory);
+        record.player_stats_dire[0].is_guild_member = false;
+        record.player_stats_radiant[1].steam_account_id = None;
+
+        let items = history_items(&record).unwrap();
+        let keys: Vec<&str> = items.iter().map(|item| item["pk"].as_s().unwrap().as_str()).collect();
+        assert_eq!(keys, vec!["match#6789012345", "guild#117311", "player#86000"]);
+        assert!(items.iter().all(|item| item["sk"].as_s().unwrap() == "001650000000#6789012345"));
+        assert_eq!(items[2]["kills"], AttributeValue::N("6".to_string()));
+        assert_eq!(items[2]["is_victory"], AttributeValue::Bool(true));
+    }
+
+    #[test]
+    fn test_record_from_item() {
+        let record = publish_record(MatchResult::Defeat);
+        let items = history_items(&record).unwrap();
+        assert_eq!(record_from_item(&items[0]).unwrap(), record);
+    }
+
+    #[test]
+    fn test_streak_item() {
+        let streak = Streak { current: -4, best_win: 6, best_loss: 4, last_match_id: 6789012345, previous: -3 };
+        assert_eq!(streak_from_item(&streak_item("86000", &streak)), streak);
+        assert_eq!(streak_from_item(&Default::default()), Streak::default());
+    }
+
+    #[test]
+    fn test_quarantined_match_from_item() {
+        let item = super::Item::from([
+            ("id".to_string(), AttributeValue::S("6789012345".to_string())),
+            ("reason".to_string(), AttributeValue::S("Missing end".to_string())),
+            ("quarantined_at".to_string(), AttributeValue::N("1650000000".to_string()))
+        ]);
+        let quarantined_match = quarantined_match_from_item(&item).unwrap();
+        assert_eq!(quarantined_match.match_id, 6789012345);
+        assert_eq!(quarantined_match.reason, "Missing end");
+        assert_eq!(quarantined_match.quarantined_at.timestamp(), 1650000000);
+        assert!(quarantined_match_from_item(&super::Item::new()).is_err());
+    }
+
+    #[test]
+    fn test_wins_attribute() {
+        assert_eq!(wins_attribute(100), "wins_100");
+    }
+
+}
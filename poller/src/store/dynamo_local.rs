@@ -0,0 +1,123 @@
+//! Integration tests of the `dynamodb` store against DynamoDB Local, ignored by default. Start DynamoDB Local, e.g.
+//! `docker run -p 8000:8000 amazon/dynamodb-local`, then run `cargo test -- --ignored`, with `DYNAMODB_ENDPOINT`
+//! pointing to it when it does not listen on `http://localhost:8000`.
+
+use aws_sdk_dynamodb::{
+    model::{AttributeDefinition, BillingMode, KeySchemaElement, KeyType, ScalarAttributeType},
+    Client, Config, Credentials, Endpoint, Region
+};
+use crate::utils::dynamo::DynamoClient;
+use super::{dynamo::{DynamoStore, TableNames}, state::StateStore};
+
+const DEFAULT_ENDPOINT: &str = "http://localhost:8000";
+
+fn local_client() -> Client {
+    let endpoint = std::env::var("DYNAMODB_ENDPOINT").unwrap_or_else(|_| DEFAULT_ENDPOINT.to_string());
+    let config = Config::builder()
+        .region(Region::new("us-east-1"))
+        .credentials_provider(Credentials::new("local", "local", None, None, "dynamodb-local"))
+        .endpoint_resolver(Endpoint::immutable(endpoint.parse().unwrap()))
+        .build();
+
+    Client::from_conf(config)
+}
+
+/// Table names unique to a test, so that tests do not share state
+fn table_names(test: &str) -> TableNames {
+    let suffix = format!("{}-{}", test, chrono::Utc::now().timestamp_nanos_opt().unwrap_or_default());
+    TableNames {
+        guilds: format!("Guilds-{}", suffix),
+        leases: format!("Leases-{}", suffix),
+        seen_matches: format!("SeenMatches-{}", suffix),
+        quarantine: format!("Quarantine-{}", suffix),
+        deliveries: format!("Deliveries-{}", suffix),
+        head_to_head: format!("HeadToHead-{}", suffix),
+        streaks: format!("Streaks-{}", suffix),
+        health: format!("Health-{}", suffix),
+        match_history: format!("MatchHistory-{}", suffix)
+    }
+}
+
+async fn create_table(client: &Client, table_name: &str, id_type: ScalarAttributeType) {
+    client.create_table()
+        .table_name(table_name)
+        .attribute_definitions(AttributeDefinition::builder().attribute_name("id").attribute_type(id_type).build())
+        .key_schema(KeySchemaElement::builder().attribute_name("id").key_type(KeyType::Hash).build())
+        .billing_mode(BillingMode::PayPerRequest)
+        .send()
+        .await
+        .unwrap();
+}
+
+async fn local_store(test: &str) -> DynamoStore {
+    let client = local_client();
+    let tables = table_names(test);
+    create_table(&client, &tables.guilds, ScalarAttributeType::N).await;
+    create_table(&client, &tables.leases, ScalarAttributeType::S).await;
+    create_table(&client, &tables.head_to_head, ScalarAttributeType::S).await;
+
+    DynamoStore { client: DynamoClient { client }, tables }
+}
+
+#[tokio::test]
+#[ignore = "needs DynamoDB Local"]
+async fn test_checkpoint() {
+    let store = local_store("checkpoint").await;
+    assert_eq!(store.get_checkpoint(117311).await.unwrap(), None);
+    assert!(store.save_checkpoint(117311, None, 6789012345).await.unwrap());
+    assert_eq!(store.get_checkpoint(117311).await.unwrap(), Some(6789012345));
+    assert_eq!(store.get_checkpoint(1).await.unwrap(), None);
+}
+
+#[tokio::test]
+#[ignore = "needs DynamoDB Local"]
+async fn test_checkpoint_conditional_update() {
+    let store = local_store("conditional").await;
+    assert!(store.save_checkpoint(117311, None, 6789012345).await.unwrap());
+
+    // A run which read no checkpoint, or an older one, loses the compare-and-set
+    assert!(!store.save_checkpoint(117311, None, 6789012346).await.unwrap());
+    assert!(!store.save_checkpoint(117311, Some(6789012344), 6789012346).await.unwrap());
+    assert_eq!(store.get_checkpoint(117311).await.unwrap(), Some(6789012345));
+
+    assert!(store.save_checkpoint(117311, Some(6789012345), 6789012346).await.unwrap());
+    assert_eq!(store.get_checkpoint(117311).await.unwrap(), Some(6789012346));
+}
+
+#[tokio::test]
+#[ignore = "needs DynamoDB Local"]
+async fn test_lease() {
+    let store = local_store("lease").await;
+    let now = chrono::Utc::now();
+    assert!(store.acquire_lease("guild#117311", "first", now + chrono::Duration::seconds(90)).await.unwrap());
+    assert!(!store.acquire_lease("guild#117311", "second", now + chrono::Duration::seconds(90)).await.unwrap());
+    assert!(store.acquire_lease("guild#117311", "first", now + chrono::Duration::seconds(90)).await.unwrap());
+
+    store.release_lease("guild#117311", "second").await.unwrap();
+    assert!(!store.acquire_lease("guild#117311", "second", now + chrono::Duration::seconds(90)).await.unwrap());
+    store.release_lease("guild#117311", "first").await.unwrap();
+    assert!(store.acquire_lease("guild#117311", "second", now + chrono::Duration::seconds(90)).await.unwrap());
+
+    assert!(store.acquire_lease("guild#1", "crashed", now - chrono::Duration::seconds(1)).await.unwrap());
+    assert!(store.acquire_lease("guild#1", "third", now + chrono::Duration::seconds(90)).await.unwrap());
+}
+
+#[tokio::test]
+#[ignore = "needs DynamoDB Local"]
+async fn test_head_to_head() {
+    let store = local_store("head-to-head").await;
+    assert_eq!(store.increment_head_to_head(100, 200, 6789012345).await.unwrap(), (1, 0));
+    assert_eq!(store.increment_head_to_head(200, 100, 6789012346).await.unwrap(), (1, 1));
+
+    // A match counted already, or older than the latest match counted, leaves the record as is
+    assert_eq!(store.increment_head_to_head(200, 100, 6789012346).await.unwrap(), (1, 1));
+    assert_eq!(store.increment_head_to_head(100, 200, 6789012345).await.unwrap(), (1, 1));
+}
+
+#[tokio::test]
+#[ignore = "needs DynamoDB Local"]
+async fn test_missing_table() {
+    let store = DynamoStore { client: DynamoClient { client: local_client() }, tables: table_names("missing") };
+    let error = store.get_checkpoint(117311).await.unwrap_err();
+    assert!(error.to_string().starts_with("State error: Table Guilds-missing-"));
+}
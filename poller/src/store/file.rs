@@ -0,0 +1,192 @@
+use std::fs;
+use std::path::PathBuf;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use lambda_runtime::Error;
+use crate::alert::ComponentHealth;
+use crate::publisher::{publisher::PublishRecord, streaks::Streak};
+use super::{memory::{MemoryStore, State}, state::{QuarantinedMatch, StateStore}};
+
+/// State store keeping everything in a local JSON file, rewritten after every change. Useful to run the poller on a
+/// laptop without AWS.
+pub struct FileStore {
+    pub path: PathBuf,
+    memory: MemoryStore
+}
+
+impl FileStore {
+
+    /// Open the state kept in the file at `path`, starting from an empty state when the file does not exist yet
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the JSON file
+    pub fn open(path: PathBuf) -> Result<FileStore, Error> {
+        let state = match path.exists() {
+            true => serde_json::from_str::<State>(&fs::read_to_string(&path)?)
+                .map_err(|e| format!("Invalid state file {}: {}", path.display(), e))?,
+            false => State::default()
+        };
+
+        Ok(FileStore {
+            path,
+            memory: MemoryStore { state: state.into() }
+        })
+    }
+
+    /// Write the whole state to the file, through a temporary file so that a crash never leaves it half written
+    fn persist(&self) -> Result<(), Error> {
+        let content = self.memory.with_state(|state| serde_json::to_string_pretty(state))??;
+        let temporary_path = self.path.with_extension("json.tmp");
+        fs::write(&temporary_path, content)?;
+        fs::rename(&temporary_path, &self.path)?;
+
+        Ok(())
+    }
+
+}
+
+#[async_trait]
+impl StateStore for FileStore {
+
+    async fn get_checkpoint(&self, guild_id: i64) -> Result<Option<i64>, Error> {
+        self.memory.get_checkpoint(guild_id).await
+    }
+
+    async fn save_checkpoint(&self, guild_id: i64, expected: Option<i64>, match_id: i64) -> Result<bool, Error> {
+        let saved = self.memory.save_checkpoint(guild_id, expected, match_id).await?;
+        self.persist()?;
+
+        Ok(saved)
+    }
+
+    async fn acquire_lease(&self, name: &str, owner: &str, expires_at: DateTime<Utc>) -> Result<bool, Error> {
+        let acquired = self.memory.acquire_lease(name, owner, expires_at).await?;
+        self.persist()?;
+
+        Ok(acquired)
+    }
+
+    async fn release_lease(&self, name: &str, owner: &str) -> Result<(), Error> {
+        self.memory.release_lease(name, owner).await?;
+        self.persist()
+    }
+
+    async fn quarantine_match(&self, match_id: i64, reason: &str, quarantined_at: DateTime<Utc>) -> Result<(), Error> {
+        self.memory.quarantine_match(match_id, reason, quarantined_at).await?;
+        self.persist()
+    }
+
+    async fn quarantined_matches(&self) -> Result<Vec<QuarantinedMatch>, Error> {
+        self.memory.quarantined_matches().await
+    }
+
+    async fn is_seen(&self, match_id: i64) -> Result<bool, Error> {
+        self.memory.is_seen(match_id).await
+    }
+
+    async fn mark_seen(&self, match_id: i64, expires_at: DateTime<Utc>) -> Result<(), Error> {
+        self.memory.mark_seen(match_id, expires_at).await?;
+        self.persist()
+    }
+
+    async fn is_delivered(&self, match_id: &str, sink: &str) -> Result<bool, Error> {
+        self.memory.is_delivered(match_id, sink).await
+    }
+
+    async fn mark_delivered(&self, match_id: &str, sink: &str) -> Result<(), Error> {
+        self.memory.mark_delivered(match_id, sink).await?;
+        self.persist()
+    }
+
+    async fn record_match(&self, publish_record: &PublishRecord) -> Result<(), Error> {
+        self.memory.record_match(publish_record).await?;
+        self.persist()
+    }
+
+    async fn get_match(&self, match_id: &str) -> Result<Option<PublishRecord>, Error> {
+        self.memory.get_match(match_id).await
+    }
+
+    async fn matches_of_player(
+        &self,
+        steam_account_id: i64,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>
+    ) -> Result<Vec<PublishRecord>, Error> {
+        self.memory.matches_of_player(steam_account_id, start, end).await
+    }
+
+    async fn matches_of_guild(
+        &self,
+        guild_id: &str,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>
+    ) -> Result<Vec<PublishRecord>, Error> {
+        self.memory.matches_of_guild(guild_id, start, end).await
+    }
+
+    async fn increment_head_to_head(&self, winner_id: i64, loser_id: i64, match_id: i64) -> Result<(i64, i64), Error> {
+        let wins = self.memory.increment_head_to_head(winner_id, loser_id, match_id).await?;
+        self.persist()?;
+
+        Ok(wins)
+    }
+
+    async fn get_streak(&self, steam_account_id: i64) -> Result<Streak, Error> {
+        self.memory.get_streak(steam_account_id).await
+    }
+
+    async fn save_streak(&self, steam_account_id: i64, streak: &Streak) -> Result<(), Error> {
+        self.memory.save_streak(steam_account_id, streak).await?;
+        self.persist()
+    }
+
+    async fn get_health(&self, component: &str) -> Result<ComponentHealth, Error> {
+        self.memory.get_health(component).await
+    }
+
+    async fn save_health(&self, component: &str, health: &ComponentHealth) -> Result<(), Error> {
+        self.memory.save_health(component, health).await?;
+        self.persist()
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::publisher::fixtures::publish_record;
+    use crate::publisher::publisher::MatchResult;
+    use crate::store::state::StateStore;
+    use super::FileStore;
+
+    #[tokio::test]
+    async fn test_state_survives_reopening() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("state.json");
+        let record = publish_record(MatchResult::Victory);
+
+        let store = FileStore::open(path.clone()).unwrap();
+        store.save_checkpoint(117311, None, 6789012345).await.unwrap();
+        store.mark_delivered("6789012345", "discord").await.unwrap();
+        store.record_match(&record).await.unwrap();
+        store.increment_head_to_head(100, 200, 6789012345).await.unwrap();
+
+        let store = FileStore::open(path).unwrap();
+        assert_eq!(store.get_checkpoint(117311).await.unwrap(), Some(6789012345));
+        assert!(store.is_delivered("6789012345", "discord").await.unwrap());
+        assert_eq!(store.get_match("6789012345").await.unwrap(), Some(record));
+        assert_eq!(store.increment_head_to_head(100, 200, 6789012345).await.unwrap(), (1, 0));
+        assert_eq!(store.increment_head_to_head(100, 200, 6789012346).await.unwrap(), (2, 0));
+    }
+
+    #[test]
+    fn test_open_invalid_file() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("state.json");
+        std::fs::write(&path, "not json").unwrap();
+        assert!(FileStore::open(path).is_err());
+    }
+
+}
@@ -0,0 +1,334 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::Mutex;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use lambda_runtime::Error;
+use serde::{Serialize, Deserialize};
+use crate::alert::ComponentHealth;
+use crate::publisher::{clash::pair_id, publisher::PublishRecord, streaks::Streak};
+use super::state::{QuarantinedMatch, StateStore};
+
+/// A lease held by a run until it expires
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Lease {
+    pub owner: String,
+    pub expires_at: i64
+}
+
+/// The whole state kept by the in-memory and file stores
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct State {
+    #[serde(default)]
+    pub checkpoints: BTreeMap<i64, i64>,
+    #[serde(default)]
+    pub leases: BTreeMap<String, Lease>,
+    #[serde(default)]
+    pub seen: BTreeMap<i64, i64>,
+    #[serde(default)]
+    pub quarantine: BTreeMap<i64, QuarantinedMatch>,
+    #[serde(default)]
+    pub deliveries: BTreeSet<String>,
+    #[serde(default)]
+    pub matches: BTreeMap<String, PublishRecord>,
+    #[serde(default)]
+    pub head_to_head: BTreeMap<String, HashMap<i64, i64>>,
+    /// The latest match counted in the head-to-head record of each pair
+    #[serde(default)]
+    pub head_to_head_matches: BTreeMap<String, i64>,
+    #[serde(default)]
+    pub streaks: BTreeMap<i64, Streak>,
+    #[serde(default)]
+    pub health: BTreeMap<String, ComponentHealth>
+}
+
+impl State {
+
+    /// Get the matches satisfying `predicate` which ended between `start` and `end`, both inclusive, oldest first
+    fn matches_between<P>(&self, start: DateTime<Utc>, end: DateTime<Utc>, predicate: P) -> Vec<PublishRecord>
+    where
+        P: Fn(&PublishRecord) -> bool
+    {
+        let mut matches: Vec<PublishRecord> = self.matches.values()
+            .filter(|publish_record| publish_record.end >= start && publish_record.end <= end)
+            .filter(|publish_record| predicate(publish_record))
+            .cloned()
+            .collect();
+        matches.sort_by(|a, b| a.end.cmp(&b.end).then_with(|| a.match_id.cmp(&b.match_id)));
+
+        matches
+    }
+
+}
+
+/// State store keeping everything in memory, lost when the process exits. Useful for tests and local runs.
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    pub state: Mutex<State>
+}
+
+impl MemoryStore {
+
+    /// Run `operation` on the state while holding the lock
+    pub fn with_state<T, F>(&self, operation: F) -> Result<T, Error>
+    where
+        F: FnOnce(&mut State) -> T
+    {
+        let mut state = self.state.lock().map_err(|_| "State store lock poisoned")?;
+        Ok(operation(&mut state))
+    }
+
+}
+
+#[async_trait]
+impl StateStore for MemoryStore {
+
+    async fn get_checkpoint(&self, guild_id: i64) -> Result<Option<i64>, Error> {
+        self.with_state(|state| state.checkpoints.get(&guild_id).copied())
+    }
+
+    async fn save_checkpoint(&self, guild_id: i64, expected: Option<i64>, match_id: i64) -> Result<bool, Error> {
+        self.with_state(|state| {
+            if state.checkpoints.get(&guild_id).copied() != expected {
+                return false;
+            }
+            state.checkpoints.insert(guild_id, match_id);
+            true
+        })
+    }
+
+    async fn acquire_lease(&self, name: &str, owner: &str, expires_at: DateTime<Utc>) -> Result<bool, Error> {
+        let now = Utc::now().timestamp();
+        self.with_state(|state| {
+            let is_taken = state.leases.get(name)
+                .is_some_and(|lease| lease.owner != owner && lease.expires_at > now);
+            if is_taken {
+                return false;
+            }
+            state.leases.insert(name.to_string(), Lease { owner: owner.to_string(), expires_at: expires_at.timestamp() });
+            true
+        })
+    }
+
+    async fn release_lease(&self, name: &str, owner: &str) -> Result<(), Error> {
+        self.with_state(|state| {
+            if state.leases.get(name).is_some_and(|lease| lease.owner == owner) {
+                state.leases.remove(name);
+            }
+        })
+    }
+
+    async fn quarantine_match(&self, match_id: i64, reason: &str, quarantined_at: DateTime<Utc>) -> Result<(), Error> {
+        self.with_state(|state| {
+            let quarantined_match = QuarantinedMatch { match_id, reason: reason.to_string(), quarantined_at };
+            state.quarantine.insert(match_id, quarantined_match);
+        })
+    }
+
+    async fn quarantined_matches(&self) -> Result<Vec<QuarantinedMatch>, Error> {
+        self.with_state(|state| {
+            let mut quarantined_matches: Vec<QuarantinedMatch> = state.quarantine.values().cloned().collect();
+            quarantined_matches.sort_by_key(|quarantined_match| (quarantined_match.quarantined_at, quarantined_match.match_id));
+            quarantined_matches
+        })
+    }
+
+    async fn is_seen(&self, match_id: i64) -> Result<bool, Error> {
+        let now = Utc::now().timestamp();
+        self.with_state(|state| state.seen.get(&match_id).is_some_and(|expires_at| *expires_at > now))
+    }
+
+    async fn mark_seen(&self, match_id: i64, expires_at: DateTime<Utc>) -> Result<(), Error> {
+        let now = Utc::now().timestamp();
+        self.with_state(|state| {
+            state.seen.retain(|_, expires_at| *expires_at > now);
+            state.seen.insert(match_id, expires_at.timestamp());
+        })
+    }
+
+    async fn is_delivered(&self, match_id: &str, sink: &str) -> Result<bool, Error> {
+        self.with_state(|state| state.deliveries.contains(&delivery_key(match_id, sink)))
+    }
+
+    async fn mark_delivered(&self, match_id: &str, sink: &str) -> Result<(), Error> {
+        self.with_state(|state| {
+            state.deliveries.insert(delivery_key(match_id, sink));
+        })
+    }
+
+    async fn record_match(&self, publish_record: &PublishRecord) -> Result<(), Error> {
+        self.with_state(|state| {
+            state.matches.insert(publish_record.match_id.to_string(), publish_record.clone());
+        })
+    }
+
+    async fn get_match(&self, match_id: &str) -> Result<Option<PublishRecord>, Error> {
+        self.with_state(|state| state.matches.get(match_id).cloned())
+    }
+
+    async fn matches_of_player(
+        &self,
+        steam_account_id: i64,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>
+    ) -> Result<Vec<PublishRecord>, Error> {
+        self.with_state(|state| state.matches_between(start, end, |publish_record| {
+            publish_record.player_stats_radiant.iter()
+                .chain(publish_record.player_stats_dire.iter())
+                .any(|player_stats| player_stats.is_guild_member && player_stats.steam_account_id == Some(steam_account_id))
+        }))
+    }
+
+    async fn matches_of_guild(
+        &self,
+        guild_id: &str,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>
+    ) -> Result<Vec<PublishRecord>, Error> {
+        self.with_state(|state| state.matches_between(start, end, |publish_record| publish_record.guild_id == guild_id))
+    }
+
+    async fn increment_head_to_head(&self, winner_id: i64, loser_id: i64, match_id: i64) -> Result<(i64, i64), Error> {
+        self.with_state(|state| {
+            let pair_id = pair_id(winner_id, loser_id);
+            let is_counted = state.head_to_head_matches.get(&pair_id)
+                .is_some_and(|last_match_id| *last_match_id >= match_id);
+            let wins = state.head_to_head.entry(pair_id.clone()).or_default();
+            if !is_counted {
+                *wins.entry(winner_id).or_default() += 1;
+                state.head_to_head_matches.insert(pair_id, match_id);
+            }
+            (wins.get(&winner_id).copied().unwrap_or_default(), wins.get(&loser_id).copied().unwrap_or_default())
+        })
+    }
+
+    async fn get_streak(&self, steam_account_id: i64) -> Result<Streak, Error> {
+        self.with_state(|state| state.streaks.get(&steam_account_id).copied().unwrap_or_default())
+    }
+
+    async fn save_streak(&self, steam_account_id: i64, streak: &Streak) -> Result<(), Error> {
+        self.with_state(|state| {
+            state.streaks.insert(steam_account_id, *streak);
+        })
+    }
+
+    async fn get_health(&self, component: &str) -> Result<ComponentHealth, Error> {
+        self.with_state(|state| state.health.get(component).copied().unwrap_or_default())
+    }
+
+    async fn save_health(&self, component: &str, health: &ComponentHealth) -> Result<(), Error> {
+        self.with_state(|state| {
+            state.health.insert(component.to_string(), *health);
+        })
+    }
+
+}
+
+/// Get the key of a delivery in the ledger
+pub fn delivery_key(match_id: &str, sink: &str) -> String {
+    format!("{}#{}", match_id, sink)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::publisher::fixtures::publish_record;
+    use crate::publisher::publisher::MatchResult;
+    use crate::publisher::streaks::Streak;
+    use crate::store::state::StateStore;
+    use super::MemoryStore;
+
+    #[tokio::test]
+    async fn test_checkpoint_and_deliveries() {
+        let store = MemoryStore::default();
+        assert_eq!(store.get_checkpoint(117311).await.unwrap(), None);
+        assert!(store.save_checkpoint(117311, None, 6789012345).await.unwrap());
+        assert_eq!(store.get_checkpoint(117311).await.unwrap(), Some(6789012345));
+
+        // A run which read an older checkpoint loses the compare-and-set
+        assert!(!store.save_checkpoint(117311, None, 6789012346).await.unwrap());
+        assert!(!store.save_checkpoint(117311, Some(6789012344), 6789012346).await.unwrap());
+        assert!(store.save_checkpoint(117311, Some(6789012345), 6789012346).await.unwrap());
+        assert_eq!(store.get_checkpoint(117311).await.unwrap(), Some(6789012346));
+
+        assert!(!store.is_delivered("6789012345", "kook").await.unwrap());
+        store.mark_delivered("6789012345", "kook").await.unwrap();
+        assert!(store.is_delivered("6789012345", "kook").await.unwrap());
+        assert!(!store.is_delivered("6789012345", "discord").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_lease() {
+        let store = MemoryStore::default();
+        let now = chrono::Utc::now();
+        assert!(store.acquire_lease("guild#117311", "first", now + chrono::Duration::seconds(90)).await.unwrap());
+        assert!(!store.acquire_lease("guild#117311", "second", now + chrono::Duration::seconds(90)).await.unwrap());
+        assert!(store.acquire_lease("guild#117311", "first", now + chrono::Duration::seconds(90)).await.unwrap());
+
+        // Releasing a lease held by another owner does nothing
+        store.release_lease("guild#117311", "second").await.unwrap();
+        assert!(!store.acquire_lease("guild#117311", "second", now + chrono::Duration::seconds(90)).await.unwrap());
+        store.release_lease("guild#117311", "first").await.unwrap();
+        assert!(store.acquire_lease("guild#117311", "second", now + chrono::Duration::seconds(90)).await.unwrap());
+
+        // An expired lease is taken over
+        assert!(store.acquire_lease("guild#1", "crashed", now - chrono::Duration::seconds(1)).await.unwrap());
+        assert!(store.acquire_lease("guild#1", "third", now + chrono::Duration::seconds(90)).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_quarantine() {
+        let store = MemoryStore::default();
+        let now = chrono::Utc::now();
+        store.quarantine_match(6789012346, "Missing end", now).await.unwrap();
+        store.quarantine_match(6789012345, "Missing duration", now - chrono::Duration::minutes(5)).await.unwrap();
+
+        let quarantined_matches = store.quarantined_matches().await.unwrap();
+        let match_ids: Vec<i64> = quarantined_matches.iter().map(|quarantined_match| quarantined_match.match_id).collect();
+        assert_eq!(match_ids, vec![6789012345, 6789012346]);
+        assert_eq!(quarantined_matches[1].reason, "Missing end");
+    }
+
+    #[tokio::test]
+    async fn test_history() {
+        let store = MemoryStore::default();
+        let first = publish_record(MatchResult::Victory);
+        let mut second = publish_record(MatchResult::Defeat);
+        second.match_id = "6789012346".to_string();
+        second.end += chrono::Duration::hours(1);
+        second.player_stats_dire[0].is_guild_member = false;
+        store.record_match(&second).await.unwrap();
+        store.record_match(&first).await.unwrap();
+
+        assert_eq!(store.get_match("6789012345").await.unwrap(), Some(first.clone()));
+        assert_eq!(store.get_match("1").await.unwrap(), None);
+
+        let guild_matches = store.matches_of_guild("117311", first.end, second.end).await.unwrap();
+        assert_eq!(guild_matches, vec![first.clone(), second.clone()]);
+        let guild_matches = store.matches_of_guild("117311", first.end, second.end - chrono::Duration::seconds(1))
+            .await
+            .unwrap();
+        assert_eq!(guild_matches, vec![first.clone()]);
+
+        let carol_id = first.player_stats_dire[0].steam_account_id.unwrap();
+        let carol_matches = store.matches_of_player(carol_id, first.end, second.end).await.unwrap();
+        assert_eq!(carol_matches, vec![first]);
+    }
+
+    #[tokio::test]
+    async fn test_head_to_head_and_streaks() {
+        let store = MemoryStore::default();
+        assert_eq!(store.increment_head_to_head(100, 200, 6789012345).await.unwrap(), (1, 0));
+        assert_eq!(store.increment_head_to_head(200, 100, 6789012346).await.unwrap(), (1, 1));
+        assert_eq!(store.increment_head_to_head(100, 200, 6789012347).await.unwrap(), (2, 1));
+
+        // A match counted already, or older than the latest match counted, leaves the record as is
+        assert_eq!(store.increment_head_to_head(100, 200, 6789012347).await.unwrap(), (2, 1));
+        assert_eq!(store.increment_head_to_head(200, 100, 6789012346).await.unwrap(), (1, 2));
+
+        assert_eq!(store.get_streak(100).await.unwrap(), Streak::default());
+        let streak = Streak { current: 2, best_win: 2, best_loss: 0, last_match_id: 6789012345, previous: 1 };
+        store.save_streak(100, &streak).await.unwrap();
+        assert_eq!(store.get_streak(100).await.unwrap(), streak);
+    }
+
+}
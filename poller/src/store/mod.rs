@@ -0,0 +1,8 @@
+pub mod state;
+pub mod dynamo;
+pub mod memory;
+pub mod file;
+pub mod sqlite;
+pub mod dry_run;
+#[cfg(test)]
+mod dynamo_local;
@@ -0,0 +1,576 @@
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use lambda_runtime::Error;
+use rusqlite::{Connection, OptionalExtension, params};
+use crate::{alert::ComponentHealth, error::PollerError};
+use crate::publisher::{clash::pair_id, publisher::PublishRecord, streaks::Streak};
+use super::state::{QuarantinedMatch, StateStore};
+
+/// Schema migrations, applied in order. The number of migrations applied is kept in `PRAGMA user_version`, so a
+/// migration must never change once released: add a new one instead.
+const MIGRATIONS: [&str; 8] = [
+    "CREATE TABLE checkpoints (
+        guild_id INTEGER PRIMARY KEY,
+        match_id INTEGER NOT NULL
+    );
+    CREATE TABLE deliveries (
+        match_id TEXT NOT NULL,
+        sink TEXT NOT NULL,
+        delivered_at INTEGER NOT NULL,
+        PRIMARY KEY (match_id, sink)
+    );
+    CREATE TABLE matches (
+        match_id TEXT PRIMARY KEY,
+        guild_id TEXT NOT NULL,
+        end_time INTEGER NOT NULL,
+        record TEXT NOT NULL
+    );
+    CREATE INDEX matches_guild_end_time ON matches (guild_id, end_time);
+    CREATE TABLE match_players (
+        match_id TEXT NOT NULL REFERENCES matches (match_id) ON DELETE CASCADE,
+        steam_account_id INTEGER NOT NULL,
+        hero_id INTEGER NOT NULL,
+        kills INTEGER NOT NULL,
+        deaths INTEGER NOT NULL,
+        assists INTEGER NOT NULL,
+        networth INTEGER NOT NULL,
+        is_victory INTEGER NOT NULL,
+        PRIMARY KEY (match_id, steam_account_id)
+    );
+    CREATE INDEX match_players_steam_account_id ON match_players (steam_account_id);",
+    "CREATE TABLE head_to_head (
+        pair_id TEXT NOT NULL,
+        steam_account_id INTEGER NOT NULL,
+        wins INTEGER NOT NULL,
+        PRIMARY KEY (pair_id, steam_account_id)
+    );
+    CREATE TABLE streaks (
+        steam_account_id INTEGER PRIMARY KEY,
+        current INTEGER NOT NULL,
+        best_win INTEGER NOT NULL,
+        best_loss INTEGER NOT NULL
+    );",
+    "CREATE TABLE seen_matches (
+        match_id INTEGER PRIMARY KEY,
+        expires_at INTEGER NOT NULL
+    );",
+    "CREATE TABLE leases (
+        name TEXT PRIMARY KEY,
+        owner TEXT NOT NULL,
+        expires_at INTEGER NOT NULL
+    );",
+    "CREATE TABLE quarantine (
+        match_id INTEGER PRIMARY KEY,
+        reason TEXT NOT NULL,
+        quarantined_at INTEGER NOT NULL
+    );",
+    "CREATE TABLE health (
+        component TEXT PRIMARY KEY,
+        failures INTEGER NOT NULL,
+        alerted INTEGER NOT NULL
+    );",
+    "CREATE TABLE head_to_head_matches (
+        pair_id TEXT PRIMARY KEY,
+        last_match_id INTEGER NOT NULL
+    );",
+    "ALTER TABLE streaks ADD COLUMN last_match_id INTEGER NOT NULL DEFAULT 0;
+    ALTER TABLE streaks ADD COLUMN previous INTEGER NOT NULL DEFAULT 0;"
+];
+
+/// State store keeping everything in a SQLite database, for self-hosted deployments without AWS
+pub struct SqliteStore {
+    connection: Mutex<Connection>
+}
+
+impl SqliteStore {
+
+    /// Open the database at `path`, creating it when missing, and bring its schema up to date
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the SQLite database file
+    pub fn open(path: &Path) -> Result<SqliteStore, Error> {
+        SqliteStore::from_connection(Connection::open(path)?)
+    }
+
+    /// Open a database living in memory only, with an up to date schema
+    #[cfg(test)]
+    pub fn open_in_memory() -> Result<SqliteStore, Error> {
+        SqliteStore::from_connection(Connection::open_in_memory()?)
+    }
+
+    fn from_connection(mut connection: Connection) -> Result<SqliteStore, Error> {
+        connection.pragma_update(None, "foreign_keys", true)?;
+        migrate(&mut connection)?;
+
+        Ok(SqliteStore {
+            connection: Mutex::new(connection)
+        })
+    }
+
+    fn connection(&self) -> Result<MutexGuard<'_, Connection>, Error> {
+        Ok(self.connection.lock().map_err(|_| "State store lock poisoned")?)
+    }
+
+    /// Get the records matching `condition` which ended between `start` and `end`, both inclusive, oldest first
+    fn select_records(
+        &self,
+        condition: &str,
+        value: &dyn rusqlite::ToSql,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>
+    ) -> Result<Vec<PublishRecord>, Error> {
+        let connection = self.connection()?;
+        let mut statement = connection.prepare(&format!(
+            "SELECT record FROM matches WHERE {} AND end_time BETWEEN ?2 AND ?3 ORDER BY end_time, match_id",
+            condition
+        ))?;
+        let records = statement.query_map(params![value, start.timestamp(), end.timestamp()], |row| row.get::<_, String>(0))?
+            .map(|record| Ok(serde_json::from_str(&record?)?))
+            .collect::<Result<Vec<PublishRecord>, Error>>()?;
+
+        Ok(records)
+    }
+
+}
+
+/// Apply the migrations not applied to the database yet, all in a single transaction
+fn migrate(connection: &mut Connection) -> Result<(), Error> {
+    let version: usize = connection.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    if version > MIGRATIONS.len() {
+        return Err(PollerError::State(format!(
+            "State database schema version {} is newer than the supported version {}", version, MIGRATIONS.len()
+        )).into());
+    }
+
+    let transaction = connection.transaction()?;
+    for migration in MIGRATIONS.iter().skip(version) {
+        transaction.execute_batch(migration)?;
+    }
+    transaction.pragma_update(None, "user_version", MIGRATIONS.len())?;
+    transaction.commit()?;
+
+    Ok(())
+}
+
+#[async_trait]
+impl StateStore for SqliteStore {
+
+    async fn get_checkpoint(&self, guild_id: i64) -> Result<Option<i64>, Error> {
+        let match_id = self.connection()?
+            .query_row("SELECT match_id FROM checkpoints WHERE guild_id = ?1", params![guild_id], |row| row.get(0))
+            .optional()?;
+
+        Ok(match_id)
+    }
+
+    async fn save_checkpoint(&self, guild_id: i64, expected: Option<i64>, match_id: i64) -> Result<bool, Error> {
+        let connection = self.connection()?;
+        let changes = match expected {
+            Some(expected) => connection.execute(
+                "UPDATE checkpoints SET match_id = ?3 WHERE guild_id = ?1 AND match_id = ?2",
+                params![guild_id, expected, match_id]
+            )?,
+            None => connection.execute(
+                "INSERT INTO checkpoints (guild_id, match_id) VALUES (?1, ?2) ON CONFLICT (guild_id) DO NOTHING",
+                params![guild_id, match_id]
+            )?
+        };
+
+        Ok(changes == 1)
+    }
+
+    async fn acquire_lease(&self, name: &str, owner: &str, expires_at: DateTime<Utc>) -> Result<bool, Error> {
+        let changes = self.connection()?.execute(
+            "INSERT INTO leases (name, owner, expires_at) VALUES (?1, ?2, ?3)
+            ON CONFLICT (name) DO UPDATE SET owner = excluded.owner, expires_at = excluded.expires_at
+            WHERE leases.owner = excluded.owner OR leases.expires_at <= ?4",
+            params![name, owner, expires_at.timestamp(), Utc::now().timestamp()]
+        )?;
+
+        Ok(changes == 1)
+    }
+
+    async fn release_lease(&self, name: &str, owner: &str) -> Result<(), Error> {
+        self.connection()?.execute("DELETE FROM leases WHERE name = ?1 AND owner = ?2", params![name, owner])?;
+        Ok(())
+    }
+
+    async fn quarantine_match(&self, match_id: i64, reason: &str, quarantined_at: DateTime<Utc>) -> Result<(), Error> {
+        self.connection()?.execute(
+            "INSERT OR REPLACE INTO quarantine (match_id, reason, quarantined_at) VALUES (?1, ?2, ?3)",
+            params![match_id, reason, quarantined_at.timestamp()]
+        )?;
+
+        Ok(())
+    }
+
+    async fn quarantined_matches(&self) -> Result<Vec<QuarantinedMatch>, Error> {
+        let connection = self.connection()?;
+        let mut statement = connection.prepare(
+            "SELECT match_id, reason, quarantined_at FROM quarantine ORDER BY quarantined_at, match_id"
+        )?;
+        let rows = statement.query_map([], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?, row.get::<_, i64>(2)?)))?;
+
+        let mut quarantined_matches = Vec::new();
+        for row in rows {
+            let (match_id, reason, quarantined_at) = row?;
+            let quarantined_at = DateTime::<Utc>::from_timestamp(quarantined_at, 0)
+                .ok_or_else(|| format!("Invalid quarantine time of match {}", match_id))?;
+            quarantined_matches.push(QuarantinedMatch { match_id, reason, quarantined_at });
+        }
+
+        Ok(quarantined_matches)
+    }
+
+    async fn is_seen(&self, match_id: i64) -> Result<bool, Error> {
+        let seen = self.connection()?
+            .query_row(
+                "SELECT 1 FROM seen_matches WHERE match_id = ?1 AND expires_at > ?2",
+                params![match_id, Utc::now().timestamp()],
+                |_| Ok(())
+            )
+            .optional()?
+            .is_some();
+
+        Ok(seen)
+    }
+
+    async fn mark_seen(&self, match_id: i64, expires_at: DateTime<Utc>) -> Result<(), Error> {
+        let connection = self.connection()?;
+        connection.execute("DELETE FROM seen_matches WHERE expires_at <= ?1", params![Utc::now().timestamp()])?;
+        connection.execute(
+            "INSERT OR REPLACE INTO seen_matches (match_id, expires_at) VALUES (?1, ?2)",
+            params![match_id, expires_at.timestamp()]
+        )?;
+
+        Ok(())
+    }
+
+    async fn is_delivered(&self, match_id: &str, sink: &str) -> Result<bool, Error> {
+        let delivered = self.connection()?
+            .query_row(
+                "SELECT 1 FROM deliveries WHERE match_id = ?1 AND sink = ?2",
+                params![match_id, sink],
+                |_| Ok(())
+            )
+            .optional()?
+            .is_some();
+
+        Ok(delivered)
+    }
+
+    async fn mark_delivered(&self, match_id: &str, sink: &str) -> Result<(), Error> {
+        self.connection()?.execute(
+            "INSERT OR REPLACE INTO deliveries (match_id, sink, delivered_at) VALUES (?1, ?2, ?3)",
+            params![match_id, sink, Utc::now().timestamp()]
+        )?;
+
+        Ok(())
+    }
+
+    async fn record_match(&self, publish_record: &PublishRecord) -> Result<(), Error> {
+        let record = serde_json::to_string(publish_record)?;
+        let mut connection = self.connection()?;
+        let transaction = connection.transaction()?;
+        transaction.execute(
+            "INSERT OR REPLACE INTO matches (match_id, guild_id, end_time, record) VALUES (?1, ?2, ?3, ?4)",
+            params![publish_record.match_id, publish_record.guild_id, publish_record.end.timestamp(), record]
+        )?;
+
+        // Anonymous players cannot be told apart between matches and are not stored under a player
+        let players_stats = publish_record.player_stats_radiant.iter().chain(publish_record.player_stats_dire.iter());
+        for player_stats in players_stats.filter(|player_stats| player_stats.is_guild_member) {
+            if let Some(steam_account_id) = player_stats.steam_account_id {
+                transaction.execute(
+                    "INSERT OR REPLACE INTO match_players
+                    (match_id, steam_account_id, hero_id, kills, deaths, assists, networth, is_victory)
+                    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                    params![
+                        publish_record.match_id, steam_account_id, player_stats.hero_id, player_stats.kills,
+                        player_stats.deaths, player_stats.assists, player_stats.networth, player_stats.is_victory
+                    ]
+                )?;
+            }
+        }
+        transaction.commit()?;
+
+        Ok(())
+    }
+
+    async fn get_match(&self, match_id: &str) -> Result<Option<PublishRecord>, Error> {
+        let record: Option<String> = self.connection()?
+            .query_row("SELECT record FROM matches WHERE match_id = ?1", params![match_id], |row| row.get(0))
+            .optional()?;
+
+        Ok(record.map(|record| serde_json::from_str(&record)).transpose()?)
+    }
+
+    async fn matches_of_player(
+        &self,
+        steam_account_id: i64,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>
+    ) -> Result<Vec<PublishRecord>, Error> {
+        self.select_records(
+            "match_id IN (SELECT match_id FROM match_players WHERE steam_account_id = ?1)",
+            &steam_account_id,
+            start,
+            end
+        )
+    }
+
+    async fn matches_of_guild(
+        &self,
+        guild_id: &str,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>
+    ) -> Result<Vec<PublishRecord>, Error> {
+        self.select_records("guild_id = ?1", &guild_id, start, end)
+    }
+
+    async fn increment_head_to_head(&self, winner_id: i64, loser_id: i64, match_id: i64) -> Result<(i64, i64), Error> {
+        let pair_id = pair_id(winner_id, loser_id);
+        let mut connection = self.connection()?;
+        let transaction = connection.transaction()?;
+        let is_counted = transaction.execute(
+            "INSERT INTO head_to_head_matches (pair_id, last_match_id) VALUES (?1, ?2)
+            ON CONFLICT (pair_id) DO UPDATE SET last_match_id = excluded.last_match_id
+            WHERE last_match_id < excluded.last_match_id",
+            params![pair_id, match_id]
+        )? == 0;
+        if !is_counted {
+            transaction.execute(
+                "INSERT INTO head_to_head (pair_id, steam_account_id, wins) VALUES (?1, ?2, 1)
+                ON CONFLICT (pair_id, steam_account_id) DO UPDATE SET wins = wins + 1",
+                params![pair_id, winner_id]
+            )?;
+        }
+        let wins = |steam_account_id: i64| -> Result<i64, rusqlite::Error> {
+            transaction.query_row(
+                "SELECT wins FROM head_to_head WHERE pair_id = ?1 AND steam_account_id = ?2",
+                params![pair_id, steam_account_id],
+                |row| row.get(0)
+            ).optional().map(Option::unwrap_or_default)
+        };
+        let (winner_wins, loser_wins) = (wins(winner_id)?, wins(loser_id)?);
+        transaction.commit()?;
+
+        Ok((winner_wins, loser_wins))
+    }
+
+    async fn get_streak(&self, steam_account_id: i64) -> Result<Streak, Error> {
+        let streak = self.connection()?
+            .query_row(
+                "SELECT current, best_win, best_loss, last_match_id, previous FROM streaks WHERE steam_account_id = ?1",
+                params![steam_account_id],
+                |row| Ok(Streak {
+                    current: row.get(0)?,
+                    best_win: row.get(1)?,
+                    best_loss: row.get(2)?,
+                    last_match_id: row.get(3)?,
+                    previous: row.get(4)?
+                })
+            )
+            .optional()?;
+
+        Ok(streak.unwrap_or_default())
+    }
+
+    async fn save_streak(&self, steam_account_id: i64, streak: &Streak) -> Result<(), Error> {
+        self.connection()?.execute(
+            "INSERT OR REPLACE INTO streaks (steam_account_id, current, best_win, best_loss, last_match_id, previous)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                steam_account_id, streak.current, streak.best_win, streak.best_loss, streak.last_match_id, streak.previous
+            ]
+        )?;
+
+        Ok(())
+    }
+
+    async fn get_health(&self, component: &str) -> Result<ComponentHealth, Error> {
+        let health = self.connection()?
+            .query_row(
+                "SELECT failures, alerted FROM health WHERE component = ?1",
+                params![component],
+                |row| Ok(ComponentHealth { failures: row.get(0)?, alerted: row.get(1)? })
+            )
+            .optional()?;
+
+        Ok(health.unwrap_or_default())
+    }
+
+    async fn save_health(&self, component: &str, health: &ComponentHealth) -> Result<(), Error> {
+        self.connection()?.execute(
+            "INSERT OR REPLACE INTO health (component, failures, alerted) VALUES (?1, ?2, ?3)",
+            params![component, health.failures, health.alerted]
+        )?;
+
+        Ok(())
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use rusqlite::Connection;
+    use crate::publisher::fixtures::publish_record;
+    use crate::publisher::publisher::MatchResult;
+    use crate::alert::ComponentHealth;
+    use crate::publisher::streaks::Streak;
+    use crate::store::state::StateStore;
+    use super::{MIGRATIONS, SqliteStore, migrate};
+
+    fn user_version(connection: &Connection) -> usize {
+        connection.pragma_query_value(None, "user_version", |row| row.get(0)).unwrap()
+    }
+
+    #[test]
+    fn test_migrate() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        connection.execute_batch(MIGRATIONS[0]).unwrap();
+        connection.pragma_update(None, "user_version", 1).unwrap();
+
+        migrate(&mut connection).unwrap();
+        assert_eq!(user_version(&connection), MIGRATIONS.len());
+        connection.execute("INSERT INTO streaks (steam_account_id, current, best_win, best_loss) VALUES (1, 2, 2, 0)", [])
+            .unwrap();
+
+        // Applying the migrations again is a no-op
+        migrate(&mut connection).unwrap();
+        assert_eq!(user_version(&connection), MIGRATIONS.len());
+
+        connection.pragma_update(None, "user_version", MIGRATIONS.len() + 1).unwrap();
+        assert!(migrate(&mut connection).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_checkpoint_and_deliveries() {
+        let store = SqliteStore::open_in_memory().unwrap();
+        assert_eq!(store.get_checkpoint(117311).await.unwrap(), None);
+        assert!(store.save_checkpoint(117311, None, 6789012345).await.unwrap());
+        assert!(!store.save_checkpoint(117311, None, 6789012346).await.unwrap());
+        assert!(!store.save_checkpoint(117311, Some(6789012344), 6789012346).await.unwrap());
+        assert!(store.save_checkpoint(117311, Some(6789012345), 6789012346).await.unwrap());
+        assert_eq!(store.get_checkpoint(117311).await.unwrap(), Some(6789012346));
+
+        assert!(!store.is_delivered("6789012345", "kook").await.unwrap());
+        store.mark_delivered("6789012345", "kook").await.unwrap();
+        assert!(store.is_delivered("6789012345", "kook").await.unwrap());
+        assert!(!store.is_delivered("6789012345", "discord").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_lease() {
+        let store = SqliteStore::open_in_memory().unwrap();
+        let now = chrono::Utc::now();
+        assert!(store.acquire_lease("guild#117311", "first", now + chrono::Duration::seconds(90)).await.unwrap());
+        assert!(!store.acquire_lease("guild#117311", "second", now + chrono::Duration::seconds(90)).await.unwrap());
+        assert!(store.acquire_lease("guild#117311", "first", now + chrono::Duration::seconds(90)).await.unwrap());
+
+        store.release_lease("guild#117311", "second").await.unwrap();
+        assert!(!store.acquire_lease("guild#117311", "second", now + chrono::Duration::seconds(90)).await.unwrap());
+        store.release_lease("guild#117311", "first").await.unwrap();
+        assert!(store.acquire_lease("guild#117311", "second", now + chrono::Duration::seconds(90)).await.unwrap());
+
+        assert!(store.acquire_lease("guild#1", "crashed", now - chrono::Duration::seconds(1)).await.unwrap());
+        assert!(store.acquire_lease("guild#1", "third", now + chrono::Duration::seconds(90)).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_seen() {
+        let store = SqliteStore::open_in_memory().unwrap();
+        let now = chrono::Utc::now();
+        assert!(!store.is_seen(6789012345).await.unwrap());
+        store.mark_seen(6789012345, now + chrono::Duration::days(7)).await.unwrap();
+        assert!(store.is_seen(6789012345).await.unwrap());
+        store.mark_seen(6789012346, now - chrono::Duration::seconds(1)).await.unwrap();
+        assert!(!store.is_seen(6789012346).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_quarantine() {
+        let store = SqliteStore::open_in_memory().unwrap();
+        let now = chrono::DateTime::<chrono::Utc>::from_timestamp(1650000000, 0).unwrap();
+        store.quarantine_match(6789012346, "Missing end", now).await.unwrap();
+        store.quarantine_match(6789012345, "Missing duration", now - chrono::Duration::minutes(5)).await.unwrap();
+
+        let quarantined_matches = store.quarantined_matches().await.unwrap();
+        let match_ids: Vec<i64> = quarantined_matches.iter().map(|quarantined_match| quarantined_match.match_id).collect();
+        assert_eq!(match_ids, vec![6789012345, 6789012346]);
+        assert_eq!(quarantined_matches[1].reason, "Missing end");
+        assert_eq!(quarantined_matches[1].quarantined_at, now);
+    }
+
+    #[tokio::test]
+    async fn test_history() {
+        let store = SqliteStore::open_in_memory().unwrap();
+        let first = publish_record(MatchResult::Victory);
+        let mut second = publish_record(MatchResult::Defeat);
+        second.match_id = "6789012346".to_string();
+        second.end += chrono::Duration::hours(1);
+        second.player_stats_dire[0].is_guild_member = false;
+        store.record_match(&second).await.unwrap();
+        store.record_match(&first).await.unwrap();
+        store.record_match(&first).await.unwrap();
+
+        assert_eq!(store.get_match("6789012345").await.unwrap(), Some(first.clone()));
+        assert_eq!(store.get_match("1").await.unwrap(), None);
+
+        let guild_matches = store.matches_of_guild("117311", first.end, second.end).await.unwrap();
+        assert_eq!(guild_matches, vec![first.clone(), second.clone()]);
+        let guild_matches = store.matches_of_guild("117311", first.end, second.end - chrono::Duration::seconds(1))
+            .await
+            .unwrap();
+        assert_eq!(guild_matches, vec![first.clone()]);
+
+        let carol_id = first.player_stats_dire[0].steam_account_id.unwrap();
+        let carol_matches = store.matches_of_player(carol_id, first.end, second.end).await.unwrap();
+        assert_eq!(carol_matches, vec![first]);
+    }
+
+    #[tokio::test]
+    async fn test_head_to_head_and_streaks() {
+        let store = SqliteStore::open_in_memory().unwrap();
+        assert_eq!(store.increment_head_to_head(100, 200, 6789012345).await.unwrap(), (1, 0));
+        assert_eq!(store.increment_head_to_head(200, 100, 6789012346).await.unwrap(), (1, 1));
+        assert_eq!(store.increment_head_to_head(100, 200, 6789012347).await.unwrap(), (2, 1));
+
+        // A match counted already, or older than the latest match counted, leaves the record as is
+        assert_eq!(store.increment_head_to_head(100, 200, 6789012347).await.unwrap(), (2, 1));
+        assert_eq!(store.increment_head_to_head(200, 100, 6789012346).await.unwrap(), (1, 2));
+
+        assert_eq!(store.get_streak(100).await.unwrap(), Streak::default());
+        let streak = Streak { current: -3, best_win: 2, best_loss: 3, last_match_id: 6789012345, previous: -2 };
+        store.save_streak(100, &streak).await.unwrap();
+        assert_eq!(store.get_streak(100).await.unwrap(), streak);
+    }
+
+    #[tokio::test]
+    async fn test_health() {
+        let store = SqliteStore::open_in_memory().unwrap();
+        assert_eq!(store.get_health("kook").await.unwrap(), ComponentHealth::default());
+        let health = ComponentHealth { failures: 3, alerted: true };
+        store.save_health("kook", &health).await.unwrap();
+        assert_eq!(store.get_health("kook").await.unwrap(), health);
+        assert_eq!(store.get_health("discord").await.unwrap(), ComponentHealth::default());
+    }
+
+    #[tokio::test]
+    async fn test_state_survives_reopening() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("state.db");
+
+        let store = SqliteStore::open(&path).unwrap();
+        store.save_checkpoint(117311, None, 6789012345).await.unwrap();
+        drop(store);
+
+        let store = SqliteStore::open(&path).unwrap();
+        assert_eq!(store.get_checkpoint(117311).await.unwrap(), Some(6789012345));
+    }
+
+}
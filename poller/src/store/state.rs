@@ -0,0 +1,159 @@
+use std::path::PathBuf;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use lambda_runtime::Error;
+use serde::{Serialize, Deserialize};
+use crate::{alert::ComponentHealth, settings::StorageSettings};
+use crate::config::{Setting, StorageConfig};
+use crate::publisher::{publisher::PublishRecord, streaks::Streak};
+use crate::utils::dynamo::DynamoClient;
+use super::{dynamo::DynamoStore, memory::MemoryStore, file::FileStore, sqlite::SqliteStore};
+
+const DEFAULT_STATE_FILE: &str = "state.json";
+const DEFAULT_STATE_DATABASE: &str = "state.db";
+
+/// The state kept between runs of the poller
+/// - the checkpoint of each guild, a.k.a the latest match id already processed
+/// - the leases making sure a single run processes a guild at a time
+/// - the match ids already seen, kept until they expire
+/// - the delivery ledger, recording which destinations each match was delivered to
+/// - the quarantine of matches which could not be published
+/// - the history of published matches
+/// - the head-to-head records and streaks of guild members
+/// - the health of the components, counting their failed cycles in a row
+#[async_trait]
+pub trait StateStore: Send + Sync {
+
+    /// Get the latest match id already processed for a guild
+    /// Otherwise, return None when the guild was never processed
+    async fn get_checkpoint(&self, guild_id: i64) -> Result<Option<i64>, Error>;
+
+    /// Save the latest match id processed for a guild, only when the checkpoint is still `expected`, `None` meaning
+    /// the guild has no checkpoint yet. Returns false when another run changed the checkpoint in the meantime.
+    async fn save_checkpoint(&self, guild_id: i64, expected: Option<i64>, match_id: i64) -> Result<bool, Error>;
+
+    /// Take the lease `name` for `owner` until `expires_at`. Returns false when another owner holds a lease which has
+    /// not expired yet.
+    async fn acquire_lease(&self, name: &str, owner: &str, expires_at: DateTime<Utc>) -> Result<bool, Error>;
+
+    /// Give the lease `name` back, when `owner` still holds it
+    async fn release_lease(&self, name: &str, owner: &str) -> Result<(), Error>;
+
+    /// Put a match which could not be published aside, with the reason
+    async fn quarantine_match(&self, match_id: i64, reason: &str, quarantined_at: DateTime<Utc>) -> Result<(), Error>;
+
+    /// Get the matches put aside, oldest first
+    async fn quarantined_matches(&self) -> Result<Vec<QuarantinedMatch>, Error>;
+
+    /// Check whether a match id was already seen and has not expired yet
+    async fn is_seen(&self, match_id: i64) -> Result<bool, Error>;
+
+    /// Mark a match id as seen until `expires_at`
+    async fn mark_seen(&self, match_id: i64, expires_at: DateTime<Utc>) -> Result<(), Error>;
+
+    /// Check whether a match was already delivered to a destination, e.g. `kook`
+    async fn is_delivered(&self, match_id: &str, sink: &str) -> Result<bool, Error>;
+
+    /// Record that a match was delivered to a destination
+    async fn mark_delivered(&self, match_id: &str, sink: &str) -> Result<(), Error>;
+
+    /// Store a published match in the history
+    async fn record_match(&self, publish_record: &PublishRecord) -> Result<(), Error>;
+
+    /// Get a match from the history
+    /// Otherwise, return None when the match was never published
+    async fn get_match(&self, match_id: &str) -> Result<Option<PublishRecord>, Error>;
+
+    /// Get the matches of a guild member which ended between `start` and `end`, both inclusive, oldest first
+    async fn matches_of_player(
+        &self,
+        steam_account_id: i64,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>
+    ) -> Result<Vec<PublishRecord>, Error>;
+
+    /// Get the matches of a guild which ended between `start` and `end`, both inclusive, oldest first
+    async fn matches_of_guild(
+        &self,
+        guild_id: &str,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>
+    ) -> Result<Vec<PublishRecord>, Error>;
+
+    /// Add a win of `winner_id` over `loser_id` in `match_id` and return the wins of the winner and of the loser
+    /// against each other. A match already counted for the pair, or older than the latest match counted, is not
+    /// counted again, so that a match published again after a failed delivery keeps the record as is.
+    async fn increment_head_to_head(&self, winner_id: i64, loser_id: i64, match_id: i64) -> Result<(i64, i64), Error>;
+
+    /// Get the streaks of a guild member, all zero when the member never played
+    async fn get_streak(&self, steam_account_id: i64) -> Result<Streak, Error>;
+
+    /// Save the streaks of a guild member
+    async fn save_streak(&self, steam_account_id: i64, streak: &Streak) -> Result<(), Error>;
+
+    /// Get the health of a component, such as `provider` or `kook`, healthy when it never failed
+    async fn get_health(&self, component: &str) -> Result<ComponentHealth, Error>;
+
+    /// Save the health of a component
+    async fn save_health(&self, component: &str, health: &ComponentHealth) -> Result<(), Error>;
+
+}
+
+/// A match which could not be published, kept aside with the reason
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct QuarantinedMatch {
+    pub match_id: i64,
+    pub reason: String,
+    pub quarantined_at: DateTime<Utc>
+}
+
+/// Enum of the backends keeping the state
+#[derive(Debug, PartialEq, Eq)]
+pub enum StoreKind {
+    DynamoDb,
+    Memory,
+    File(PathBuf),
+    Sqlite(PathBuf)
+}
+
+impl StoreKind {
+
+    /// Get the StoreKind from the `backend` setting of the `[storage]` section, `dynamodb`, `memory`, `file` or
+    /// `sqlite`, with the `file` and `database` paths, `state.json` and `state.db` by default
+    /// Otherwise, the state is kept in AWS DynamoDB
+    pub fn from_config(storage: &StorageConfig) -> Result<StoreKind, Error> {
+        let path = |setting: &Setting<PathBuf>, default: &str| {
+            setting.value().cloned().unwrap_or_else(|| PathBuf::from(default))
+        };
+        match storage.backend.value() {
+            None => Ok(StoreKind::DynamoDb),
+            Some(value) => match value.trim().to_lowercase().as_str() {
+                "dynamodb" => Ok(StoreKind::DynamoDb),
+                "memory" => Ok(StoreKind::Memory),
+                "file" => Ok(StoreKind::File(path(&storage.file, DEFAULT_STATE_FILE))),
+                "sqlite" => Ok(StoreKind::Sqlite(path(&storage.database, DEFAULT_STATE_DATABASE))),
+                _ => Err(format!("Invalid STATE_STORE: {}", value).into())
+            }
+        }
+    }
+
+}
+
+/// Open the state store chosen by the storage settings
+///
+/// # Arguments
+///
+/// * `storage` - The storage settings, with the backend of the state
+pub async fn open(storage: &StorageSettings) -> Result<Box<dyn StateStore>, Error> {
+    let store: Box<dyn StateStore> = match &storage.kind {
+        StoreKind::DynamoDb => Box::new(DynamoStore {
+            client: DynamoClient::connect(storage.dynamodb_endpoint.as_deref()).await?,
+            tables: storage.tables.clone()
+        }),
+        StoreKind::Memory => Box::new(MemoryStore::default()),
+        StoreKind::File(path) => Box::new(FileStore::open(path.clone())?),
+        StoreKind::Sqlite(path) => Box::new(SqliteStore::open(path)?)
+    };
+
+    Ok(store)
+}
@@ -0,0 +1,121 @@
+use chrono::{DateTime, SecondsFormat, Utc};
+use lambda_runtime::Error;
+use log::{LevelFilter, Log, Metadata, Record, kv::{self, Key, VisitSource}};
+use serde_json::{Map, Value, json};
+
+/// Logger writing every record to stderr as a single JSON line, with the key-values of the record as fields, e.g.
+/// `log::info!(guild_id = 117311, match_id = "6789012345"; "Skipping match")`
+pub struct JsonLogger {
+    pub level: LevelFilter
+}
+
+impl JsonLogger {
+
+    /// Install the logger at the level of `RUST_LOG` environmental variable, such as `debug`
+    /// Otherwise, records are logged from the `info` level
+    pub fn init() -> Result<(), Error> {
+        let level = std::env::var("RUST_LOG").ok()
+            .and_then(|level| level.parse::<LevelFilter>().ok())
+            .unwrap_or(LevelFilter::Info);
+        log::set_boxed_logger(Box::new(JsonLogger { level }))?;
+        log::set_max_level(level);
+
+        Ok(())
+    }
+
+}
+
+impl Log for JsonLogger {
+
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            eprintln!("{}", format_record(record, Utc::now()));
+        }
+    }
+
+    fn flush(&self) {}
+
+}
+
+/// Format a log `record` logged at `now` as a JSON object
+fn format_record(record: &Record, now: DateTime<Utc>) -> Value {
+    let mut fields = Fields(Map::new());
+    // A key-value failing to be visited is left out rather than losing the record
+    let _ = record.key_values().visit(&mut fields);
+
+    let mut line = json!({
+        "timestamp": now.to_rfc3339_opts(SecondsFormat::Millis, true),
+        "level": record.level().as_str(),
+        "target": record.target(),
+        "message": record.args().to_string()
+    });
+    if let Some(line) = line.as_object_mut() {
+        line.extend(fields.0);
+    }
+
+    line
+}
+
+/// The key-values of a log record, as JSON fields
+struct Fields(Map<String, Value>);
+
+impl<'kvs> VisitSource<'kvs> for Fields {
+
+    fn visit_pair(&mut self, key: Key<'kvs>, value: kv::Value<'kvs>) -> Result<(), kv::Error> {
+        let value = if let Some(value) = value.to_bool() {
+            json!(value)
+        } else if let Some(value) = value.to_i64() {
+            json!(value)
+        } else if let Some(value) = value.to_u64() {
+            json!(value)
+        } else if let Some(value) = value.to_f64() {
+            json!(value)
+        } else {
+            json!(value.to_string())
+        };
+        self.0.insert(key.to_string(), value);
+
+        Ok(())
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use chrono::{TimeZone, Utc};
+    use log::{Level, Record};
+    use serde_json::json;
+    use super::format_record;
+
+    #[test]
+    fn test_format_record() {
+        let key_values: [(&str, log::kv::Value); 3] = [
+            ("guild_id", 117311i64.into()),
+            ("match_id", "6789012345".into()),
+            ("sink", "kook".into())
+        ];
+        let record = Record::builder()
+            .level(Level::Warn)
+            .target("poller::outcome")
+            .args(format_args!("Failed to deliver matches"))
+            .key_values(&key_values)
+            .build();
+
+        let now = Utc.timestamp_opt(1650000000, 0).unwrap();
+        assert_eq!(format_record(&record, now), json!({
+            "timestamp": "2022-04-15T05:20:00.000Z",
+            "level": "WARN",
+            "target": "poller::outcome",
+            "message": "Failed to deliver matches",
+            "guild_id": 117311,
+            "match_id": "6789012345",
+            "sink": "kook"
+        }));
+    }
+
+}
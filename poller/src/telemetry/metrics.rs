@@ -0,0 +1,256 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::Duration;
+use chrono::{DateTime, Utc};
+use lambda_runtime::Error;
+use serde_json::{Map, Value, json};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use crate::outcome::RunOutcome;
+
+/// The metrics recorded since the process started, or since they were last taken in Lambda
+static METRICS: Mutex<Metrics> = Mutex::new(Metrics::new());
+
+/// Struct containing the metrics of the polling cycles
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Metrics {
+    /// Cycles run, failed ones included
+    pub cycles: u64,
+    /// Cycles which failed before publishing
+    pub failed_cycles: u64,
+    /// Total time spent in cycles
+    pub poll_seconds: f64,
+    /// Matches by what the cycles did with them: seen, published, skipped, quarantined or deferred
+    pub matches: BTreeMap<&'static str, u64>,
+    /// Matches which failed to be delivered, per sink
+    pub sink_errors: BTreeMap<String, u64>,
+    /// Responses of Stratz, per HTTP status code
+    pub stratz_responses: BTreeMap<u16, u64>
+}
+
+impl Metrics {
+
+    /// Build metrics where nothing was recorded yet
+    pub const fn new() -> Metrics {
+        Metrics {
+            cycles: 0,
+            failed_cycles: 0,
+            poll_seconds: 0.0,
+            matches: BTreeMap::new(),
+            sink_errors: BTreeMap::new(),
+            stratz_responses: BTreeMap::new()
+        }
+    }
+
+    /// Get the metrics as a CloudWatch Embedded Metric Format document, which CloudWatch turns into metrics when
+    /// printed to stdout in Lambda
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace` - The CloudWatch namespace of the metrics
+    /// * `guild_id` - The id of the guild, used as dimension
+    /// * `now` - The time of the metrics
+    pub fn to_emf(&self, namespace: &str, guild_id: i64, now: DateTime<Utc>) -> Value {
+        let mut values: Vec<(String, &str, f64)> = vec![
+            ("PollLatency".to_string(), "Milliseconds", self.poll_seconds * 1000.0),
+            ("FailedCycles".to_string(), "Count", self.failed_cycles as f64)
+        ];
+        for (result, count) in self.matches.iter() {
+            values.push((format!("Matches{}", capitalize(result)), "Count", *count as f64));
+        }
+        for (sink, count) in self.sink_errors.iter() {
+            values.push((format!("SinkErrors.{}", sink), "Count", *count as f64));
+        }
+        for (status, count) in self.stratz_responses.iter() {
+            values.push((format!("StratzResponses.{}", status), "Count", *count as f64));
+        }
+
+        let mut document = Map::new();
+        document.insert("_aws".to_string(), json!({
+            "Timestamp": now.timestamp_millis(),
+            "CloudWatchMetrics": [{
+                "Namespace": namespace,
+                "Dimensions": [["GuildId"]],
+                "Metrics": values.iter()
+                    .map(|(name, unit, _)| json!({ "Name": name, "Unit": unit }))
+                    .collect::<Vec<Value>>()
+            }]
+        }));
+        document.insert("GuildId".to_string(), json!(guild_id.to_string()));
+        for (name, _, value) in values {
+            document.insert(name, json!(value));
+        }
+
+        Value::Object(document)
+    }
+
+    /// Get the metrics in the Prometheus text format
+    pub fn to_prometheus(&self) -> String {
+        let mut text = String::new();
+        let _ = writeln!(text, "# HELP poller_cycles_total Polling cycles run");
+        let _ = writeln!(text, "# TYPE poller_cycles_total counter");
+        let _ = writeln!(text, "poller_cycles_total {}", self.cycles);
+        let _ = writeln!(text, "# HELP poller_failed_cycles_total Polling cycles which failed before publishing");
+        let _ = writeln!(text, "# TYPE poller_failed_cycles_total counter");
+        let _ = writeln!(text, "poller_failed_cycles_total {}", self.failed_cycles);
+        let _ = writeln!(text, "# HELP poller_poll_duration_seconds Time spent in polling cycles");
+        let _ = writeln!(text, "# TYPE poller_poll_duration_seconds summary");
+        let _ = writeln!(text, "poller_poll_duration_seconds_sum {}", self.poll_seconds);
+        let _ = writeln!(text, "poller_poll_duration_seconds_count {}", self.cycles);
+        let _ = writeln!(text, "# HELP poller_matches_total Matches by what the cycles did with them");
+        let _ = writeln!(text, "# TYPE poller_matches_total counter");
+        for (result, count) in self.matches.iter() {
+            let _ = writeln!(text, "poller_matches_total{{result=\"{}\"}} {}", result, count);
+        }
+        let _ = writeln!(text, "# HELP poller_sink_errors_total Matches which failed to be delivered");
+        let _ = writeln!(text, "# TYPE poller_sink_errors_total counter");
+        for (sink, count) in self.sink_errors.iter() {
+            let _ = writeln!(text, "poller_sink_errors_total{{sink=\"{}\"}} {}", sink, count);
+        }
+        let _ = writeln!(text, "# HELP poller_stratz_responses_total Responses of Stratz by HTTP status code");
+        let _ = writeln!(text, "# TYPE poller_stratz_responses_total counter");
+        for (status, count) in self.stratz_responses.iter() {
+            let _ = writeln!(text, "poller_stratz_responses_total{{status=\"{}\"}} {}", status, count);
+        }
+
+        text
+    }
+
+    /// Add a cycle which took `elapsed` and ended with `outcome`, None when it failed before publishing
+    fn add_cycle(&mut self, elapsed: Duration, outcome: Option<&RunOutcome>) {
+        self.cycles += 1;
+        self.poll_seconds += elapsed.as_secs_f64();
+        let Some(outcome) = outcome else {
+            self.failed_cycles += 1;
+            return;
+        };
+
+        let results = [
+            ("seen", outcome.seen),
+            ("published", outcome.published),
+            ("skipped", outcome.skipped),
+            ("quarantined", outcome.quarantined),
+            ("deferred", outcome.deferred)
+        ];
+        for (result, count) in results {
+            *self.matches.entry(result).or_default() += count as u64;
+        }
+        for (sink, count) in outcome.failed.iter() {
+            *self.sink_errors.entry(sink.to_string()).or_default() += *count as u64;
+        }
+    }
+
+}
+
+/// Record a cycle which took `elapsed` and ended with `outcome`, None when it failed before publishing
+pub fn record_cycle(elapsed: Duration, outcome: Option<&RunOutcome>) {
+    if let Ok(mut metrics) = METRICS.lock() {
+        metrics.add_cycle(elapsed, outcome);
+    }
+}
+
+/// Record a response of Stratz with the HTTP `status` code
+pub fn record_stratz_status(status: u16) {
+    if let Ok(mut metrics) = METRICS.lock() {
+        *metrics.stratz_responses.entry(status).or_default() += 1;
+    }
+}
+
+/// Get the metrics recorded since the process started
+pub fn snapshot() -> Metrics {
+    METRICS.lock().map(|metrics| metrics.clone()).unwrap_or_default()
+}
+
+/// Get the metrics recorded since they were last taken, and start over, e.g. once per Lambda invocation
+pub fn take() -> Metrics {
+    METRICS.lock().map(|mut metrics| std::mem::take(&mut *metrics)).unwrap_or_default()
+}
+
+/// Serve the metrics to Prometheus at `/metrics` on `port`, until the process exits
+///
+/// # Arguments
+///
+/// * `port` - The TCP port listened to on every interface
+pub async fn serve(port: u16) -> Result<(), Error> {
+    let listener = TcpListener::bind(("0.0.0.0", port)).await?;
+    log::info!(port = port; "Serving metrics on port {}", port);
+
+    loop {
+        let (mut stream, _) = listener.accept().await?;
+        tokio::spawn(async move {
+            let mut request = [0u8; 1024];
+            let Ok(length) = stream.read(&mut request).await else { return };
+            let request = String::from_utf8_lossy(&request[..length]);
+            let response = match request.split_whitespace().take(2).collect::<Vec<&str>>().as_slice() {
+                ["GET", "/metrics"] => {
+                    let body = snapshot().to_prometheus();
+                    format!(
+                        "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\n\r\n{}",
+                        body.len(), body
+                    )
+                },
+                _ => "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n".to_string()
+            };
+            let _ = stream.write_all(response.as_bytes()).await;
+        });
+    }
+}
+
+/// Capitalize the first letter of `word`, e.g. `seen` to `Seen`
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::time::Duration;
+    use chrono::{TimeZone, Utc};
+    use serde_json::json;
+    use crate::outcome::RunOutcome;
+    use super::Metrics;
+
+    fn metrics() -> Metrics {
+        let mut outcome = RunOutcome { seen: 3, published: 1, skipped: 1, ..Default::default() };
+        outcome.record_failure("kook", &["6789012345"], &"Unauthorized".into());
+
+        let mut metrics = Metrics::new();
+        metrics.add_cycle(Duration::from_millis(1500), Some(&outcome));
+        metrics.add_cycle(Duration::from_millis(500), None);
+        metrics.stratz_responses.insert(200, 1);
+        metrics.stratz_responses.insert(401, 1);
+        metrics
+    }
+
+    #[test]
+    fn test_to_emf() {
+        let now = Utc.timestamp_opt(1650000000, 0).unwrap();
+        let document = metrics().to_emf("NanTuanTracker", 117311, now);
+        assert_eq!(document["_aws"]["Timestamp"], json!(1650000000000i64));
+        assert_eq!(document["_aws"]["CloudWatchMetrics"][0]["Dimensions"], json!([["GuildId"]]));
+        assert_eq!(document["_aws"]["CloudWatchMetrics"][0]["Metrics"][0], json!({ "Name": "PollLatency", "Unit": "Milliseconds" }));
+        assert_eq!(document["GuildId"], json!("117311"));
+        assert_eq!(document["PollLatency"], json!(2000.0));
+        assert_eq!(document["FailedCycles"], json!(1.0));
+        assert_eq!(document["MatchesSeen"], json!(3.0));
+        assert_eq!(document["SinkErrors.kook"], json!(1.0));
+        assert_eq!(document["StratzResponses.401"], json!(1.0));
+    }
+
+    #[test]
+    fn test_to_prometheus() {
+        let text = metrics().to_prometheus();
+        assert!(text.contains("poller_cycles_total 2\n"));
+        assert!(text.contains("poller_failed_cycles_total 1\n"));
+        assert!(text.contains("poller_poll_duration_seconds_sum 2\n"));
+        assert!(text.contains("poller_matches_total{result=\"published\"} 1\n"));
+        assert!(text.contains("poller_sink_errors_total{sink=\"kook\"} 1\n"));
+        assert!(text.contains("poller_stratz_responses_total{status=\"200\"} 1\n"));
+    }
+
+}
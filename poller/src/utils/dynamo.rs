@@ -1,7 +1,12 @@
+use std::collections::HashMap;
 use aws_sdk_dynamodb::{
-    model::AttributeValue, Client, Error, output::GetItemOutput
+    model::{AttributeValue, ReturnValue}, Client, Endpoint, Error, output::GetItemOutput,
+    types::SdkError
 };
 
+/// The attributes of an AWS DynamoDB item
+pub type Item = HashMap<String, AttributeValue>;
+
 /// Dynamo Client
 pub struct DynamoClient {
     pub client: Client
@@ -9,6 +14,24 @@ pub struct DynamoClient {
 
 impl DynamoClient {
 
+    /// Build the client from the AWS environment, against `endpoint` when it is set, e.g. DynamoDB Local
+    ///
+    /// # Arguments
+    ///
+    /// * `endpoint` - The endpoint of AWS DynamoDB, if not the default AWS endpoint
+    pub async fn connect(endpoint: Option<&str>) -> Result<DynamoClient, lambda_runtime::Error> {
+        let loader = aws_config::from_env();
+        let loader = match endpoint {
+            Some(endpoint) => {
+                let uri = endpoint.trim().parse().map_err(|_| format!("Invalid DYNAMODB_ENDPOINT: {}", endpoint))?;
+                loader.endpoint_resolver(Endpoint::immutable(uri))
+            },
+            None => loader
+        };
+
+        Ok(DynamoClient { client: Client::new(&loader.load().await) })
+    }
+
     /// Get item from Dynamo with provided table_name and entry id
     /// 
     /// # Arguments
@@ -24,21 +47,202 @@ impl DynamoClient {
         Ok(item)
     }
 
-    /// Put an item to Dynamo for given table_name, entry id and match_id
+    /// Put an item to Dynamo for given table_name, entry id and match_id, only when the `match_id` stored is still
+    /// `expected_match_id`, `None` meaning the item does not exist yet. Returns false when the condition fails.
     /// 
     /// # Arguments
     /// 
     /// * `table_name` - The name of the AWS DynamoDB table to put data to
     /// * `id` - The id of entry of the AWS DynamoDB table
+    /// * `expected_match_id` - The value the `id` must still be binded to
     /// * `match_id` - The value binded to the `id` to be put into AWS DynamoDB table
-    pub async fn put_item(&self, table_name: &str, id: i64, match_id: i64) -> Result<(), Error> {
+    pub async fn put_item(
+        &self,
+        table_name: &str,
+        id: i64,
+        expected_match_id: Option<i64>,
+        match_id: i64
+    ) -> Result<bool, Error> {
         let request = self.client.put_item().table_name(table_name)
             .item("id", AttributeValue::N(id.to_string()))
             .item("match_id", AttributeValue::N(match_id.to_string()));
-        
-        request.send().await?;
+        let request = match expected_match_id {
+            Some(expected_match_id) => request
+                .condition_expression("match_id = :expected")
+                .expression_attribute_values(":expected", AttributeValue::N(expected_match_id.to_string())),
+            None => request.condition_expression("attribute_not_exists(id)")
+        };
+
+        match request.send().await {
+            Ok(_) => Ok(true),
+            Err(SdkError::ServiceError { err, .. }) if err.is_conditional_check_failed_exception() => Ok(false),
+            Err(e) => Err(e.into())
+        }
+    }
+
+    /// Atomically add one to a counter of an item for `match_id`, creating the item and the counter when missing, and
+    /// return the item after the update. The `last_match_id` attribute of the item keeps the latest match counted, so
+    /// that a match already counted, or older than it, is not counted again.
+    /// Otherwise, None when the match was not counted
+    /// 
+    /// # Arguments
+    /// 
+    /// * `table_name` - The name of the AWS DynamoDB table holding the counter
+    /// * `id` - The string id of entry of the AWS DynamoDB table
+    /// * `counter` - The name of the numeric attribute to be incremented
+    /// * `match_id` - The id of the match counted
+    pub async fn increment_counter(
+        &self,
+        table_name: &str,
+        id: &str,
+        counter: &str,
+        match_id: i64
+    ) -> Result<Option<Item>, Error> {
+        let request = self.client.update_item().table_name(table_name)
+            .key("id", AttributeValue::S(id.to_string()))
+            .update_expression("ADD #counter :one SET last_match_id = :match_id")
+            .condition_expression("attribute_not_exists(last_match_id) OR last_match_id < :match_id")
+            .expression_attribute_names("#counter", counter)
+            .expression_attribute_values(":one", AttributeValue::N("1".to_string()))
+            .expression_attribute_values(":match_id", AttributeValue::N(match_id.to_string()))
+            .return_values(ReturnValue::AllNew);
+
+        match request.send().await {
+            Ok(output) => Ok(output.attributes().cloned()),
+            Err(SdkError::ServiceError { err, .. }) if err.is_conditional_check_failed_exception() => Ok(None),
+            Err(e) => Err(e.into())
+        }
+    }
+
+    /// Get the attributes of an item with a string entry id
+    /// Otherwise, return None when the item does not exist
+    /// 
+    /// # Arguments
+    /// 
+    /// * `table_name` - The name of the AWS DynamoDB table to get data from
+    /// * `id` - The string id of entry of the AWS DynamoDB table
+    pub async fn get_attributes(&self, table_name: &str, id: &str) -> Result<Option<Item>, Error> {
+        let output = self.client.get_item().table_name(table_name)
+            .key("id", AttributeValue::S(id.to_string()))
+            .send()
+            .await?;
+
+        Ok(output.item().cloned())
+    }
+
+    /// Put an item made of the given attributes to Dynamo, replacing any item with the same key
+    /// 
+    /// # Arguments
+    /// 
+    /// * `table_name` - The name of the AWS DynamoDB table to put data to
+    /// * `item` - The attributes of the item, including its key
+    pub async fn put_attributes(&self, table_name: &str, item: Item) -> Result<(), Error> {
+        self.client.put_item().table_name(table_name)
+            .set_item(Some(item))
+            .send()
+            .await?;
 
         Ok(())
     }
 
+    /// Put an item made of the given attributes to Dynamo, only when `condition` holds for the item it replaces.
+    /// Returns false when the condition fails.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `table_name` - The name of the AWS DynamoDB table to put data to
+    /// * `item` - The attributes of the item, including its key
+    /// * `condition` - The condition expression, e.g. `attribute_not_exists(id) OR expires_at < :now`
+    /// * `values` - The values of the placeholders of `condition`
+    pub async fn put_attributes_if(&self, table_name: &str, item: Item, condition: &str, values: Item) -> Result<bool, Error> {
+        let request = self.client.put_item().table_name(table_name)
+            .set_item(Some(item))
+            .condition_expression(condition)
+            .set_expression_attribute_values(Some(values));
+
+        match request.send().await {
+            Ok(_) => Ok(true),
+            Err(SdkError::ServiceError { err, .. }) if err.is_conditional_check_failed_exception() => Ok(false),
+            Err(e) => Err(e.into())
+        }
+    }
+
+    /// Delete the item with a string entry id, only when `condition` holds for it. Returns false when the condition
+    /// fails.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `table_name` - The name of the AWS DynamoDB table to delete data from
+    /// * `id` - The string id of entry of the AWS DynamoDB table
+    /// * `condition` - The condition expression, e.g. `owner = :owner`
+    /// * `values` - The values of the placeholders of `condition`
+    pub async fn delete_item_if(&self, table_name: &str, id: &str, condition: &str, values: Item) -> Result<bool, Error> {
+        let request = self.client.delete_item().table_name(table_name)
+            .key("id", AttributeValue::S(id.to_string()))
+            .condition_expression(condition)
+            .set_expression_attribute_values(Some(values));
+
+        match request.send().await {
+            Ok(_) => Ok(true),
+            Err(SdkError::ServiceError { err, .. }) if err.is_conditional_check_failed_exception() => Ok(false),
+            Err(e) => Err(e.into())
+        }
+    }
+
+    /// Scan every item of a table
+    /// 
+    /// # Arguments
+    /// 
+    /// * `table_name` - The name of the AWS DynamoDB table to scan
+    pub async fn scan_items(&self, table_name: &str) -> Result<Vec<Item>, Error> {
+        let mut items = Vec::new();
+        let mut exclusive_start_key = None;
+        loop {
+            let output = self.client.scan().table_name(table_name)
+                .set_exclusive_start_key(exclusive_start_key)
+                .send()
+                .await?;
+
+            items.extend(output.items().unwrap_or_default().iter().cloned());
+            exclusive_start_key = output.last_evaluated_key().cloned();
+            if exclusive_start_key.is_none() {
+                break;
+            }
+        }
+
+        Ok(items)
+    }
+
+    /// Query every item of a partition whose string sort key is between `from` and `to`, both inclusive, in
+    /// ascending order of the sort key
+    /// 
+    /// # Arguments
+    /// 
+    /// * `table_name` - The name of the AWS DynamoDB table to query
+    /// * `pk` - The string partition key `pk` of the items
+    /// * `from` - The smallest sort key `sk` of the items
+    /// * `to` - The largest sort key `sk` of the items
+    pub async fn query_range(&self, table_name: &str, pk: &str, from: &str, to: &str) -> Result<Vec<Item>, Error> {
+        let mut items = Vec::new();
+        let mut exclusive_start_key = None;
+        loop {
+            let output = self.client.query().table_name(table_name)
+                .key_condition_expression("pk = :pk AND sk BETWEEN :from AND :to")
+                .expression_attribute_values(":pk", AttributeValue::S(pk.to_string()))
+                .expression_attribute_values(":from", AttributeValue::S(from.to_string()))
+                .expression_attribute_values(":to", AttributeValue::S(to.to_string()))
+                .set_exclusive_start_key(exclusive_start_key)
+                .send()
+                .await?;
+
+            items.extend(output.items().unwrap_or_default().iter().cloned());
+            exclusive_start_key = output.last_evaluated_key().cloned();
+            if exclusive_start_key.is_none() {
+                break;
+            }
+        }
+
+        Ok(items)
+    }
+
 }